@@ -0,0 +1,288 @@
+//! Device memory allocator (cudaMalloc / cudaFree).
+//! Hands out non-overlapping ranges of the HBM address space, first fit over
+//! an address-ordered free list that coalesces neighbouring ranges on free.
+//! Tracks live allocations, usage and peak usage against the HBM capacity,
+//! and remembers freed allocations so memcheck can tell a use-after-free from
+//! an out-of-bounds access.
+use std::collections::BTreeMap;
+
+/// Default alignment of allocations in bytes — cudaMalloc guarantees 256.
+pub const DEFAULT_ALIGNMENT: usize = 256;
+
+/// Why an allocation or free failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocError {
+    /// No free range can hold the request (cudaErrorMemoryAllocation).
+    /// `free` may exceed `requested` when free memory is fragmented.
+    OutOfMemory { requested: usize, free: usize, largest_free_block: usize },
+    /// Alignment is zero or not a power of two
+    InvalidAlignment { align: usize },
+    /// `addr` is not the start of a live allocation (cudaErrorInvalidValue)
+    InvalidFree { addr: usize },
+    /// `[addr, addr + len)` is not inside one managed allocation
+    /// (cudaErrorInvalidValue)
+    NotManaged { addr: usize, len: usize },
+}
+
+impl std::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllocError::OutOfMemory { requested, free, largest_free_block } => write!(
+                f,
+                "out of device memory: requested {} bytes, {} free (largest free block {})",
+                requested, free, largest_free_block,
+            ),
+            AllocError::InvalidAlignment { align } => {
+                write!(f, "alignment {} is not a power of two", align)
+            }
+            AllocError::InvalidFree { addr } => {
+                write!(f, "address {:#x} is not the start of a live allocation", addr)
+            }
+            AllocError::NotManaged { addr, len } => {
+                write!(f, "{:#x}+{} is not inside a managed allocation", addr, len)
+            }
+        }
+    }
+}
+
+/// Snapshot of device memory usage (cf. cudaMemGetInfo).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemInfo {
+    /// Capacity of the device memory in bytes
+    pub total: usize,
+    /// Bytes held by live allocations, including alignment padding
+    pub used: usize,
+    /// Bytes not held by any allocation
+    pub free: usize,
+    /// Highest `used` seen since the GPU was created
+    pub peak: usize,
+    /// Number of live allocations
+    pub allocations: usize,
+    /// Largest single allocation that could currently succeed (unaligned)
+    pub largest_free_block: usize,
+    /// External fragmentation: 1 - largest_free_block / free, in [0, 1].
+    /// 0 when all free memory is one contiguous range.
+    pub fragmentation: f64,
+}
+
+/// First-fit allocator over the address range [0, capacity).
+#[derive(Debug, Clone)]
+pub struct DeviceAllocator {
+    capacity: usize,
+    /// Free ranges: start address → length, non-adjacent and non-overlapping
+    free_ranges: BTreeMap<usize, usize>,
+    /// Live allocations: start address → (range start, range length). The
+    /// range includes the padding skipped to align the start.
+    live: BTreeMap<usize, (usize, usize)>,
+    /// Freed allocations whose memory has not been handed out again:
+    /// start address → size
+    freed: BTreeMap<usize, usize>,
+    used: usize,
+    peak: usize,
+}
+
+impl DeviceAllocator {
+    pub fn new(capacity: usize) -> Self {
+        let mut free_ranges = BTreeMap::new();
+        if capacity > 0 {
+            free_ranges.insert(0, capacity);
+        }
+        DeviceAllocator {
+            capacity,
+            free_ranges,
+            live: BTreeMap::new(),
+            freed: BTreeMap::new(),
+            used: 0,
+            peak: 0,
+        }
+    }
+
+    /// Allocate `size` bytes starting at a multiple of `align`; returns the
+    /// device address. Zero-byte requests still get a distinct address.
+    pub fn alloc(&mut self, size: usize, align: usize) -> Result<usize, AllocError> {
+        if !align.is_power_of_two() {
+            return Err(AllocError::InvalidAlignment { align });
+        }
+        let size = size.max(1);
+        let fit = self.free_ranges.iter().find_map(|(&start, &len)| {
+            let addr = start.next_multiple_of(align);
+            (addr + size <= start + len).then_some((start, len, addr))
+        });
+        let Some((start, len, addr)) = fit else {
+            return Err(AllocError::OutOfMemory {
+                requested: size,
+                free: self.capacity - self.used,
+                largest_free_block: self.largest_free_block(),
+            });
+        };
+
+        // The padding before `addr` stays with the allocation so it is
+        // returned on free; the tail goes back to the free list.
+        self.free_ranges.remove(&start);
+        let end = addr + size;
+        if end < start + len {
+            self.free_ranges.insert(end, start + len - end);
+        }
+        self.live.insert(addr, (start, end - start));
+        let reused: Vec<usize> = self
+            .freed
+            .range(..end)
+            .filter(|&(&a, &size)| a + size > start)
+            .map(|(&a, _)| a)
+            .collect();
+        for a in reused {
+            self.freed.remove(&a);
+        }
+        self.used += end - start;
+        self.peak = self.peak.max(self.used);
+        Ok(addr)
+    }
+
+    /// Release the allocation starting at `addr`.
+    pub fn free(&mut self, addr: usize) -> Result<(), AllocError> {
+        let (mut start, mut len) = self.live.remove(&addr).ok_or(AllocError::InvalidFree { addr })?;
+        self.used -= len;
+        self.freed.insert(addr, start + len - addr);
+
+        // Coalesce with the free neighbours on either side
+        if let Some((&prev, &prev_len)) = self.free_ranges.range(..start).next_back()
+            && prev + prev_len == start
+        {
+            self.free_ranges.remove(&prev);
+            start = prev;
+            len += prev_len;
+        }
+        if let Some(next_len) = self.free_ranges.remove(&(start + len)) {
+            len += next_len;
+        }
+        self.free_ranges.insert(start, len);
+        Ok(())
+    }
+
+    /// Size in bytes of the live allocation starting at `addr`, excluding
+    /// alignment padding.
+    pub fn allocation_size(&self, addr: usize) -> Option<usize> {
+        self.live.get(&addr).map(|&(start, len)| start + len - addr)
+    }
+
+    /// The live allocation containing `addr`, as (start address, size).
+    pub fn find(&self, addr: usize) -> Option<(usize, usize)> {
+        let (&start, &(range, len)) = self.live.range(..=addr).next_back()?;
+        let size = range + len - start;
+        (addr < start + size).then_some((start, size))
+    }
+
+    /// The freed allocation containing `addr`, as (start address, size), if
+    /// its memory has not been allocated again since.
+    pub fn find_freed(&self, addr: usize) -> Option<(usize, usize)> {
+        let (&start, &size) = self.freed.range(..=addr).next_back()?;
+        (addr < start + size).then_some((start, size))
+    }
+
+    /// The live allocation closest to `addr` on either side, as (start
+    /// address, size).
+    pub fn nearest(&self, addr: usize) -> Option<(usize, usize)> {
+        let size = |(&start, &(range, len)): (&usize, &(usize, usize))| (start, range + len - start);
+        let before = self.live.range(..=addr).next_back().map(size);
+        let after = self.live.range(addr + 1..).next().map(size);
+        match (before, after) {
+            (Some(b), Some(a)) => {
+                Some(if addr.saturating_sub(b.0 + b.1) <= a.0 - addr { b } else { a })
+            }
+            (b, a) => b.or(a),
+        }
+    }
+
+    fn largest_free_block(&self) -> usize {
+        self.free_ranges.values().copied().max().unwrap_or(0)
+    }
+
+    pub fn info(&self) -> MemInfo {
+        let free = self.capacity - self.used;
+        let largest_free_block = self.largest_free_block();
+        let fragmentation =
+            if free == 0 { 0.0 } else { 1.0 - largest_free_block as f64 / free as f64 };
+        MemInfo {
+            total: self.capacity,
+            used: self.used,
+            free,
+            peak: self.peak,
+            allocations: self.live.len(),
+            largest_free_block,
+            fragmentation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_aligned_first_fit() {
+        let mut alloc = DeviceAllocator::new(4096);
+        assert_eq!(alloc.alloc(100, DEFAULT_ALIGNMENT), Ok(0));
+        assert_eq!(alloc.alloc(100, DEFAULT_ALIGNMENT), Ok(256));
+        // The padding before 256 went with the second allocation
+        assert_eq!(alloc.alloc(8, 8), Ok(360));
+        assert_eq!(alloc.allocation_size(256), Some(100));
+        assert_eq!(alloc.alloc(0, 1), Ok(368));
+        assert_eq!(alloc.info().used, 369);
+        assert_eq!(alloc.alloc(1, 3), Err(AllocError::InvalidAlignment { align: 3 }));
+    }
+
+    #[test]
+    fn exhausted_memory_reports_the_largest_free_block() {
+        let mut alloc = DeviceAllocator::new(1024);
+        let a = alloc.alloc(256, 256).unwrap();
+        alloc.alloc(256, 256).unwrap();
+        alloc.alloc(256, 256).unwrap();
+        alloc.free(a).unwrap();
+        // 512 bytes are free, but in two 256-byte pieces
+        assert_eq!(
+            alloc.alloc(512, 256),
+            Err(AllocError::OutOfMemory { requested: 512, free: 512, largest_free_block: 256 }),
+        );
+        let info = alloc.info();
+        assert_eq!((info.used, info.free, info.allocations), (512, 512, 2));
+        assert_eq!(info.fragmentation, 0.5);
+    }
+
+    #[test]
+    fn free_coalesces_with_both_neighbours() {
+        let mut alloc = DeviceAllocator::new(1024);
+        let addrs: Vec<usize> = (0..4).map(|_| alloc.alloc(256, 256).unwrap()).collect();
+        alloc.free(addrs[0]).unwrap();
+        alloc.free(addrs[2]).unwrap();
+        assert_eq!(alloc.info().largest_free_block, 256);
+        alloc.free(addrs[1]).unwrap();
+        let info = alloc.info();
+        assert_eq!(info.largest_free_block, 768);
+        assert_eq!(info.fragmentation, 0.0);
+        assert_eq!(info.peak, 1024);
+        assert_eq!(alloc.alloc(768, 256), Ok(0));
+    }
+
+    #[test]
+    fn only_live_allocation_starts_can_be_freed() {
+        let mut alloc = DeviceAllocator::new(1024);
+        let a = alloc.alloc(128, 256).unwrap();
+        assert_eq!(alloc.free(a + 4), Err(AllocError::InvalidFree { addr: a + 4 }));
+        alloc.free(a).unwrap();
+        assert_eq!(alloc.free(a), Err(AllocError::InvalidFree { addr: a }));
+        assert_eq!(alloc.allocation_size(a), None);
+    }
+
+    #[test]
+    fn freed_memory_is_remembered_until_reused() {
+        let mut alloc = DeviceAllocator::new(1024);
+        let a = alloc.alloc(100, 256).unwrap();
+        assert_eq!(alloc.find(a + 50), Some((a, 100)));
+        assert_eq!(alloc.find(a + 100), None);
+        alloc.free(a).unwrap();
+        assert_eq!(alloc.find(a), None);
+        assert_eq!(alloc.find_freed(a + 64), Some((a, 100)));
+        alloc.alloc(64, 256).unwrap();
+        assert_eq!(alloc.find_freed(a + 64), None);
+    }
+}
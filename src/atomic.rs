@@ -0,0 +1,183 @@
+//! Atomic read-modify-write operations on global and shared memory.
+//!
+//! Kernels call these through `ctx.gmem` / `ctx.smem` (e.g.
+//! `ctx.gmem.atomic_add(addr, 1u32)`); like CUDA's `atomicAdd` family each
+//! returns the value stored at the address before the update.
+//!
+//! Functionally every atomic is applied immediately, since threads of a block
+//! never run concurrently in the simulator. For timing, lanes of one warp
+//! instruction that target the same address are serialized — the instruction
+//! is replayed once per lane sharing the most contended address.
+use std::collections::HashMap;
+
+use crate::memory::DeviceValue;
+use crate::timing::LaneOp;
+
+/// Values supported by the atomic operations (add / min / max / exch / CAS).
+pub trait AtomicValue: DeviceValue + PartialEq {
+    /// Addition (wrapping for integers)
+    fn atomic_add(self, other: Self) -> Self;
+    fn atomic_min(self, other: Self) -> Self;
+    fn atomic_max(self, other: Self) -> Self;
+}
+
+/// Integer values, which additionally support the bitwise atomics.
+pub trait AtomicBits: AtomicValue {
+    fn atomic_and(self, other: Self) -> Self;
+    fn atomic_or(self, other: Self) -> Self;
+    fn atomic_xor(self, other: Self) -> Self;
+}
+
+impl AtomicValue for u32 {
+    fn atomic_add(self, other: Self) -> Self {
+        self.wrapping_add(other)
+    }
+
+    fn atomic_min(self, other: Self) -> Self {
+        self.min(other)
+    }
+
+    fn atomic_max(self, other: Self) -> Self {
+        self.max(other)
+    }
+}
+
+impl AtomicValue for i32 {
+    fn atomic_add(self, other: Self) -> Self {
+        self.wrapping_add(other)
+    }
+
+    fn atomic_min(self, other: Self) -> Self {
+        self.min(other)
+    }
+
+    fn atomic_max(self, other: Self) -> Self {
+        self.max(other)
+    }
+}
+
+impl AtomicValue for u64 {
+    fn atomic_add(self, other: Self) -> Self {
+        self.wrapping_add(other)
+    }
+
+    fn atomic_min(self, other: Self) -> Self {
+        self.min(other)
+    }
+
+    fn atomic_max(self, other: Self) -> Self {
+        self.max(other)
+    }
+}
+
+impl AtomicValue for i64 {
+    fn atomic_add(self, other: Self) -> Self {
+        self.wrapping_add(other)
+    }
+
+    fn atomic_min(self, other: Self) -> Self {
+        self.min(other)
+    }
+
+    fn atomic_max(self, other: Self) -> Self {
+        self.max(other)
+    }
+}
+
+impl AtomicValue for f32 {
+    fn atomic_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn atomic_min(self, other: Self) -> Self {
+        self.min(other)
+    }
+
+    fn atomic_max(self, other: Self) -> Self {
+        self.max(other)
+    }
+}
+
+impl AtomicValue for f64 {
+    fn atomic_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn atomic_min(self, other: Self) -> Self {
+        self.min(other)
+    }
+
+    fn atomic_max(self, other: Self) -> Self {
+        self.max(other)
+    }
+}
+
+impl AtomicBits for u32 {
+    fn atomic_and(self, other: Self) -> Self {
+        self & other
+    }
+
+    fn atomic_or(self, other: Self) -> Self {
+        self | other
+    }
+
+    fn atomic_xor(self, other: Self) -> Self {
+        self ^ other
+    }
+}
+
+impl AtomicBits for i32 {
+    fn atomic_and(self, other: Self) -> Self {
+        self & other
+    }
+
+    fn atomic_or(self, other: Self) -> Self {
+        self | other
+    }
+
+    fn atomic_xor(self, other: Self) -> Self {
+        self ^ other
+    }
+}
+
+impl AtomicBits for u64 {
+    fn atomic_and(self, other: Self) -> Self {
+        self & other
+    }
+
+    fn atomic_or(self, other: Self) -> Self {
+        self | other
+    }
+
+    fn atomic_xor(self, other: Self) -> Self {
+        self ^ other
+    }
+}
+
+impl AtomicBits for i64 {
+    fn atomic_and(self, other: Self) -> Self {
+        self & other
+    }
+
+    fn atomic_or(self, other: Self) -> Self {
+        self | other
+    }
+
+    fn atomic_xor(self, other: Self) -> Self {
+        self ^ other
+    }
+}
+
+/// Same-address contention within one warp-level atomic instruction.
+/// Returns (degree, conflicts): the number of lanes on the most contended
+/// address — how many times the instruction is serialized — and the number
+/// of lane ops that had to wait behind another lane on the same address.
+pub fn address_contention(ops: &[LaneOp]) -> (u32, u64) {
+    let mut per_addr: HashMap<usize, u32> = HashMap::new();
+    for op in ops {
+        *per_addr.entry(op.addr).or_default() += 1;
+    }
+    let degree = per_addr.values().copied().max().unwrap_or(1);
+    let conflicts = per_addr.values().map(|&n| n as u64 - 1).sum();
+    (degree, conflicts)
+}
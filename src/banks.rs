@@ -0,0 +1,134 @@
+//! Shared memory bank conflict analysis.
+//!
+//! Shared memory is split into 32 banks that each serve one word per cycle.
+//! A warp-wide access is served in as many passes (wavefronts) as the most
+//! distinct words it needs from any one bank; lanes reading the same word
+//! share it (broadcast). Words are 4 bytes wide by default, or 8 bytes in the
+//! eight-byte bank mode (cf. cudaFuncSetSharedMemConfig).
+//!
+//! An access needs at least one wavefront per 32 distinct words (e.g. two for
+//! a conflict-free 64-bit access in four-byte mode); wavefronts beyond that
+//! are bank conflicts. The executor replays conflicting shared memory
+//! instructions once per wavefront in the timing model and aggregates the
+//! conflicts per kernel and per source line.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::timing::{LaneOp, Site};
+
+/// Banks of an SM's shared memory
+pub const NUM_BANKS: usize = 32;
+
+/// Source lines with bank conflicts printed when a kernel completes.
+pub const MAX_PRINTED_SITES: usize = 10;
+
+/// Width of a shared memory bank word.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmemBankSize {
+    #[default]
+    FourByte,
+    EightByte,
+}
+
+impl SmemBankSize {
+    pub fn bytes(self) -> usize {
+        match self {
+            SmemBankSize::FourByte => 4,
+            SmemBankSize::EightByte => 8,
+        }
+    }
+}
+
+/// Wavefronts a warp-wide shared memory access needs, and the fewest it could
+/// need for the number of distinct words it touches: (wavefronts, ideal).
+pub fn bank_wavefronts(ops: &[LaneOp], bank_size: SmemBankSize) -> (u32, u32) {
+    let width = bank_size.bytes();
+    // Distinct words requested from each bank
+    let mut words: HashMap<usize, Vec<usize>> = HashMap::new();
+    for op in ops {
+        let first = op.addr / width;
+        let last = (op.addr + (op.size as usize).max(1) - 1) / width;
+        for word in first..=last {
+            let bank = words.entry(word % NUM_BANKS).or_default();
+            if !bank.contains(&word) {
+                bank.push(word);
+            }
+        }
+    }
+    let wavefronts = words.values().map(|w| w.len() as u32).max().unwrap_or(1);
+    let distinct: usize = words.values().map(Vec::len).sum();
+    let ideal = distinct.div_ceil(NUM_BANKS).max(1) as u32;
+    (wavefronts, ideal)
+}
+
+/// Bank conflicts of the shared memory instructions at one source line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankConflictSite {
+    /// Call site in the kernel source
+    pub site: Site,
+    /// Warp-level shared memory instructions issued from the site
+    pub requests: u64,
+    /// Wavefronts those instructions needed
+    pub wavefronts: u64,
+    /// Wavefronts beyond the conflict-free minimum
+    pub conflicts: u64,
+}
+
+impl std::fmt::Display for BankConflictSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} bank conflicts over {} requests ({:.2} wavefronts/request)",
+            self.site,
+            self.conflicts,
+            self.requests,
+            self.wavefronts as f64 / self.requests.max(1) as f64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::InstrKind;
+
+    /// One `size`-byte load per lane, lane i at `i * stride` bytes.
+    fn warp(stride: usize, size: usize) -> Vec<LaneOp> {
+        (0..32).map(|i| LaneOp::mem(InstrKind::SharedLoad, i * stride, size)).collect()
+    }
+
+    #[test]
+    fn consecutive_words_are_conflict_free() {
+        assert_eq!(bank_wavefronts(&warp(4, 4), SmemBankSize::FourByte), (1, 1));
+        assert_eq!(bank_wavefronts(&[], SmemBankSize::FourByte), (1, 1));
+    }
+
+    #[test]
+    fn strided_words_conflict() {
+        assert_eq!(bank_wavefronts(&warp(8, 4), SmemBankSize::FourByte), (2, 1));
+        // A column of a 32x32 float tile: every lane in bank 0
+        assert_eq!(bank_wavefronts(&warp(128, 4), SmemBankSize::FourByte), (32, 1));
+        // Padding the row to 33 floats spreads the column over all banks
+        assert_eq!(bank_wavefronts(&warp(132, 4), SmemBankSize::FourByte), (1, 1));
+    }
+
+    #[test]
+    fn lanes_reading_one_word_share_it() {
+        assert_eq!(bank_wavefronts(&warp(0, 4), SmemBankSize::FourByte), (1, 1));
+        // Words 0 and 32 share bank 0, words 16 and 48 bank 16; eight lanes each
+        let ops: Vec<LaneOp> =
+            (0..32).map(|i| LaneOp::mem(InstrKind::SharedLoad, (i % 4) * 64, 4)).collect();
+        assert_eq!(bank_wavefronts(&ops, SmemBankSize::FourByte), (2, 1));
+    }
+
+    #[test]
+    fn wide_accesses_depend_on_the_bank_width() {
+        // 64-bit loads touch 64 four-byte words: two wavefronts, neither a conflict
+        assert_eq!(bank_wavefronts(&warp(8, 8), SmemBankSize::FourByte), (2, 2));
+        assert_eq!(bank_wavefronts(&warp(8, 8), SmemBankSize::EightByte), (1, 1));
+        // Two floats 4 bytes apart fall in one eight-byte word
+        assert_eq!(bank_wavefronts(&warp(4, 4), SmemBankSize::EightByte), (1, 1));
+    }
+}
@@ -58,12 +58,11 @@ fn run(
         terminal.draw(|f| render(f, metrics.as_ref()))?;
 
         // Non-blocking: poll for 200ms, then redraw regardless
-        if event::poll(Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
-                    break;
-                }
-            }
+        if event::poll(Duration::from_millis(200))?
+            && let Event::Key(key) = event::read()?
+            && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+        {
+            break;
         }
     }
     Ok(())
@@ -320,6 +319,63 @@ fn render_stats(f: &mut Frame, area: Rect, metrics: Option<&LiveMetrics>) {
                         Style::default().fg(Color::Yellow),
                     ),
                 ]),
+                Line::from(vec![
+                    Span::styled("L1 hit:     ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!(
+                        "{:.1}% (smem {}KB)",
+                        m.l1_hit_rate * 100.0,
+                        m.smem_carveout_kb,
+                    )),
+                ]),
+                Line::from(vec![
+                    Span::styled("L2 hit:     ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!(
+                        "{:.1}% ({} evictions)",
+                        m.l2_hit_rate * 100.0,
+                        m.l2_evictions,
+                    )),
+                ]),
+                Line::from(vec![
+                    Span::styled("Bank conf:  ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!("{}", m.smem_bank_conflicts)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Sect/req:   ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!("{:.2}", m.sectors_per_request)),
+                ]),
+                Line::from(vec![
+                    Span::styled("HBM BW:     ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!(
+                        "{:.1} GB/s ({:.1}% row hits)",
+                        m.hbm_bandwidth_gbps,
+                        m.hbm_row_hit_rate * 100.0,
+                    )),
+                ]),
+                Line::from(vec![
+                    Span::styled("Last copy:  ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(match &m.last_copy {
+                        Some(c) => format!(
+                            "{} {:.1} MB {} {:.1} GB/s",
+                            c.direction,
+                            c.bytes_mb,
+                            if c.pinned { "pinned" } else { "pageable" },
+                            c.bandwidth_gb_s,
+                        ),
+                        None => "—".to_string(),
+                    }),
+                ]),
+                Line::from(vec![
+                    Span::styled("Const/req:  ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!("{:.2} addresses", m.const_addresses_per_request)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Tex hits:   ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!("{:.1}%", m.tex_hit_rate * 100.0)),
+                ]),
+                Line::from(vec![
+                    Span::styled("UM faults:  ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!("{} ({:.1} MB migrated)", m.page_faults, m.migrated_mb)),
+                ]),
                 Line::raw(""),
                 Line::from(vec![
                     Span::styled("Grid:   ", Style::default().fg(Color::DarkGray)),
@@ -0,0 +1,434 @@
+//! Set-associative, sectored cache model.
+//!
+//! The functional model keeps every byte in HBM, so a cache only tracks tags:
+//! which sectors of which lines it holds. That is enough to tell hits from
+//! misses — and so the latency of an access — and to count evictions and
+//! write-backs.
+//!
+//! Lines are split into sectors (32 bytes on NVIDIA GPUs). A miss allocates
+//! the whole line but fills only the sectors accessed, so a hit needs both a
+//! matching tag and a valid sector. Hits and misses are counted per sector,
+//! evictions per line. Sets are allocated on first use, so a 50 MB cache costs
+//! host memory only for the lines a program actually touches.
+use std::collections::HashMap;
+
+/// Which line of a full set a miss evicts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+    /// Least recently used
+    Lru,
+    /// Oldest allocated
+    Fifo,
+    /// Pseudo-random (deterministic from run to run)
+    Random,
+}
+
+/// What a write does to the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Writes allocate the line and mark their sectors dirty; dirty sectors
+    /// are written back to memory when the line is evicted
+    WriteBack,
+    /// Writes go straight to memory and update only lines already cached
+    /// (no write allocate)
+    WriteThrough,
+}
+
+/// Geometry and policies of a cache.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    /// Capacity in bytes
+    pub size_bytes: usize,
+    /// Bytes per line (the unit of tag lookup and allocation)
+    pub line_size: usize,
+    /// Bytes per sector (the unit of fill and hit/miss accounting); at most
+    /// 64 sectors per line
+    pub sector_size: usize,
+    /// Lines per set
+    pub ways: usize,
+    pub replacement: ReplacementPolicy,
+    pub write_policy: WritePolicy,
+}
+
+impl CacheConfig {
+    /// H100 L2: 50 MB, 128-byte lines of four 32-byte sectors, 16-way,
+    /// write-back.
+    pub fn h100_l2() -> Self {
+        CacheConfig {
+            size_bytes: 50 * 1024 * 1024,
+            line_size: 128,
+            sector_size: 32,
+            ways: 16,
+            replacement: ReplacementPolicy::Lru,
+            write_policy: WritePolicy::WriteBack,
+        }
+    }
+
+    /// A100 L2: as H100 with 40 MB.
+    pub fn a100_l2() -> Self {
+        CacheConfig { size_bytes: 40 * 1024 * 1024, ..Self::h100_l2() }
+    }
+
+    /// Ampere / Hopper per-SM L1 data cache of `size_bytes` (whatever the
+    /// shared memory carveout leaves): 128-byte lines of 32-byte sectors,
+    /// 4-way, write-through.
+    pub fn l1(size_bytes: usize) -> Self {
+        CacheConfig {
+            size_bytes,
+            line_size: 128,
+            sector_size: 32,
+            ways: 4,
+            replacement: ReplacementPolicy::Lru,
+            write_policy: WritePolicy::WriteThrough,
+        }
+    }
+
+    /// Per-SM constant cache: 8 KB, 64-byte lines filled whole, 4-way. It is
+    /// read-only; the write policy is never exercised.
+    pub fn constant() -> Self {
+        CacheConfig {
+            size_bytes: 8 * 1024,
+            line_size: 64,
+            sector_size: 64,
+            ways: 4,
+            replacement: ReplacementPolicy::Lru,
+            write_policy: WritePolicy::WriteThrough,
+        }
+    }
+
+    /// Per-SM texture cache: 32 KB, 128-byte lines of 32-byte sectors,
+    /// 4-way, read-only.
+    pub fn texture() -> Self {
+        CacheConfig { size_bytes: 32 * 1024, ..Self::l1(0) }
+    }
+
+    /// Number of sets; 0 when the cache is smaller than one set, which
+    /// leaves it disabled (every access misses)
+    pub fn num_sets(&self) -> usize {
+        self.size_bytes / (self.line_size * self.ways)
+    }
+
+    /// Sectors per line
+    pub fn sectors_per_line(&self) -> usize {
+        self.line_size / self.sector_size
+    }
+}
+
+/// Access counts of a cache, in sectors (evictions in lines).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Sector accesses that found their sector valid
+    pub hits: u64,
+    /// Sector accesses that did not
+    pub misses: u64,
+    /// Valid lines replaced to make room for another
+    pub evictions: u64,
+    /// Dirty sectors written back to memory on eviction
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// Fraction of sector accesses that hit [0.0, 1.0]; 0 without accesses.
+    pub fn hit_rate(&self) -> f64 {
+        if self.accesses() == 0 { 0.0 } else { self.hits as f64 / self.accesses() as f64 }
+    }
+}
+
+impl std::ops::AddAssign for CacheStats {
+    fn add_assign(&mut self, other: CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.evictions += other.evictions;
+        self.writebacks += other.writebacks;
+    }
+}
+
+/// Outcome of one access to a cache.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheAccess {
+    pub stats: CacheStats,
+    /// Sectors that missed, as (address, length) — the fills a read needs
+    /// from the next level. A write that misses in a write-back cache needs
+    /// no fill: the written bytes are held dirty until the line is evicted.
+    pub missed: Vec<(usize, usize)>,
+    /// Written sectors the cache did not keep dirty, as (address, length) —
+    /// every sector written to a write-through (or disabled) cache, passed on
+    /// to the next level
+    pub written_through: Vec<(usize, usize)>,
+    /// Dirty sectors of evicted lines, as (address, length) — the writes
+    /// passed on to the next level
+    pub written_back: Vec<(usize, usize)>,
+}
+
+/// Tag state of one cached line.
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    /// Line number (address / line size)
+    tag: usize,
+    /// Bit per sector: holds valid data
+    valid: u64,
+    /// Bit per sector: modified since it was filled
+    dirty: u64,
+    /// Access counter value at the last access, for LRU
+    last_used: u64,
+    /// Access counter value when the line was allocated, for FIFO
+    allocated: u64,
+}
+
+/// Tag array of a set-associative, sectored cache.
+#[derive(Debug, Clone)]
+pub struct SectoredCache {
+    config: CacheConfig,
+    /// Lines of each set used so far, by set index
+    sets: HashMap<usize, Vec<Line>>,
+    /// Sector accesses so far; stamps lines for LRU and FIFO
+    clock: u64,
+    /// xorshift state for random replacement
+    rng: u64,
+    /// Totals since the cache was created
+    stats: CacheStats,
+}
+
+impl SectoredCache {
+    pub fn new(config: CacheConfig) -> Self {
+        assert!(
+            config.sector_size > 0
+                && config.line_size.is_multiple_of(config.sector_size)
+                && config.sectors_per_line() <= 64
+                && config.ways > 0,
+            "invalid cache geometry: {:?}",
+            config,
+        );
+        SectoredCache {
+            config,
+            sets: HashMap::new(),
+            clock: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// Totals since the cache was created
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Access the sectors covering the byte ranges `ranges`, given as
+    /// (address, length), once each — the requests a warp instruction makes
+    /// after its lanes' accesses are merged. Returns the counts of this
+    /// access alone, the sectors that missed and the sectors written through.
+    pub fn access(
+        &mut self,
+        ranges: impl IntoIterator<Item = (usize, usize)>,
+        write: bool,
+    ) -> CacheAccess {
+        let sector_size = self.config.sector_size;
+        let mut sectors: Vec<usize> = ranges
+            .into_iter()
+            .flat_map(|(addr, len)| {
+                let first = addr / sector_size;
+                let last = (addr + len.max(1) - 1) / sector_size;
+                first..=last
+            })
+            .collect();
+        sectors.sort_unstable();
+        sectors.dedup();
+
+        let mut access = CacheAccess::default();
+        for sector in sectors {
+            let addr = sector * sector_size;
+            if !self.access_sector(addr, write, &mut access) {
+                access.missed.push((addr, sector_size));
+            }
+            let write_back =
+                self.config.write_policy == WritePolicy::WriteBack && self.config.num_sets() > 0;
+            if write && !write_back {
+                access.written_through.push((addr, sector_size));
+            }
+        }
+        self.stats += access.stats;
+        access
+    }
+
+    /// Drop every line without writing anything back.
+    pub fn invalidate(&mut self) {
+        self.sets.clear();
+    }
+
+    /// Access one sector, counting it and any write-backs in `access`;
+    /// returns whether it hit.
+    fn access_sector(&mut self, addr: usize, write: bool, access: &mut CacheAccess) -> bool {
+        let stats = &mut access.stats;
+        let cfg = &self.config;
+        if cfg.num_sets() == 0 {
+            stats.misses += 1;
+            return false;
+        }
+        let tag = addr / cfg.line_size;
+        let bit = 1u64 << (addr % cfg.line_size / cfg.sector_size);
+        let (ways, replacement) = (cfg.ways, cfg.replacement);
+        let (line_size, sector_size) = (cfg.line_size, cfg.sector_size);
+        let allocate = !write || cfg.write_policy == WritePolicy::WriteBack;
+        let dirty = if write && cfg.write_policy == WritePolicy::WriteBack { bit } else { 0 };
+        self.clock += 1;
+        let now = self.clock;
+        let set = self.sets.entry(tag % cfg.num_sets()).or_default();
+
+        if let Some(line) = set.iter_mut().find(|l| l.tag == tag) {
+            let hit = line.valid & bit != 0;
+            if hit {
+                stats.hits += 1;
+            } else {
+                stats.misses += 1;
+                if !allocate {
+                    return false;
+                }
+                line.valid |= bit;
+            }
+            line.dirty |= dirty;
+            line.last_used = now;
+            return hit;
+        }
+
+        stats.misses += 1;
+        if !allocate {
+            return false;
+        }
+        let line = Line { tag, valid: bit, dirty, last_used: now, allocated: now };
+        if set.len() < ways {
+            set.push(line);
+            return false;
+        }
+        let victim = match replacement {
+            ReplacementPolicy::Lru => (0..ways).min_by_key(|&i| set[i].last_used),
+            ReplacementPolicy::Fifo => (0..ways).min_by_key(|&i| set[i].allocated),
+            ReplacementPolicy::Random => {
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 7;
+                self.rng ^= self.rng << 17;
+                Some(self.rng as usize % ways)
+            }
+        }
+        .expect("full set has lines");
+        stats.evictions += 1;
+        let evicted = set[victim];
+        stats.writebacks += evicted.dirty.count_ones() as u64;
+        let dirty_sectors = (0..64).filter(|i| evicted.dirty & (1 << i) != 0);
+        access.written_back.extend(
+            dirty_sectors.map(|i| (evicted.tag * line_size + i * sector_size, sector_size)),
+        );
+        set[victim] = line;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two sets of two 128-byte lines of 32-byte sectors: lines 256 bytes
+    /// apart share a set.
+    fn tiny(replacement: ReplacementPolicy, write_policy: WritePolicy) -> SectoredCache {
+        SectoredCache::new(CacheConfig {
+            size_bytes: 512,
+            line_size: 128,
+            sector_size: 32,
+            ways: 2,
+            replacement,
+            write_policy,
+        })
+    }
+
+    fn read(cache: &mut SectoredCache, addr: usize, len: usize) -> CacheAccess {
+        cache.access([(addr, len)], false)
+    }
+
+    #[test]
+    fn hits_need_a_valid_sector_not_just_the_line() {
+        let mut cache = tiny(ReplacementPolicy::Lru, WritePolicy::WriteBack);
+        assert_eq!(read(&mut cache, 4, 4).missed, [(0, 32)]);
+        assert_eq!(read(&mut cache, 0, 32).stats.hits, 1);
+        // Same line, other sector
+        assert_eq!(read(&mut cache, 40, 4).missed, [(32, 32)]);
+        // Ranges are merged into distinct sectors
+        let access = cache.access([(0, 8), (24, 16), (60, 4)], false);
+        assert_eq!((access.stats.hits, access.stats.misses), (2, 0));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 2, 0));
+        assert_eq!(stats.hit_rate(), 0.6);
+    }
+
+    #[test]
+    fn write_back_holds_dirty_sectors_until_eviction() {
+        let mut cache = tiny(ReplacementPolicy::Lru, WritePolicy::WriteBack);
+        let write = cache.access([(0, 64)], true);
+        assert_eq!((write.stats.misses, write.stats.writebacks), (2, 0));
+        assert!(write.written_through.is_empty());
+        // The written sectors are valid: reading them back hits
+        assert_eq!(read(&mut cache, 0, 64).stats.hits, 2);
+
+        read(&mut cache, 256, 4);
+        let evicting = read(&mut cache, 512, 4);
+        assert_eq!((evicting.stats.evictions, evicting.stats.writebacks), (1, 2));
+        assert_eq!(evicting.written_back, [(0, 32), (32, 32)]);
+        assert_eq!(read(&mut cache, 0, 4).missed, [(0, 32)]);
+    }
+
+    #[test]
+    fn write_through_passes_writes_on_without_allocating() {
+        let mut cache = tiny(ReplacementPolicy::Lru, WritePolicy::WriteThrough);
+        let write = cache.access([(0, 4)], true);
+        assert_eq!(write.written_through, [(0, 32)]);
+        assert_eq!(read(&mut cache, 0, 4).stats.misses, 1);
+
+        // A write to a cached sector hits but is still passed on, and the
+        // line is never dirty
+        let write = cache.access([(0, 4)], true);
+        assert_eq!((write.stats.hits, write.written_through.len()), (1, 1));
+        read(&mut cache, 256, 4);
+        let evicting = read(&mut cache, 512, 4);
+        assert_eq!((evicting.stats.evictions, evicting.stats.writebacks), (1, 0));
+        assert!(evicting.written_back.is_empty());
+    }
+
+    #[test]
+    fn replacement_policy_picks_the_victim() {
+        for (replacement, line_0_kept) in
+            [(ReplacementPolicy::Lru, true), (ReplacementPolicy::Fifo, false)]
+        {
+            let mut cache = tiny(replacement, WritePolicy::WriteBack);
+            read(&mut cache, 0, 4);
+            read(&mut cache, 256, 4);
+            read(&mut cache, 0, 4);
+            read(&mut cache, 512, 4);
+            assert_eq!(read(&mut cache, 0, 4).stats.hits == 1, line_0_kept, "{:?}", replacement);
+        }
+    }
+
+    #[test]
+    fn invalidate_drops_every_line() {
+        let mut cache = tiny(ReplacementPolicy::Lru, WritePolicy::WriteBack);
+        cache.access([(0, 4)], true);
+        cache.invalidate();
+        let again = read(&mut cache, 0, 4);
+        assert_eq!((again.stats.misses, again.stats.writebacks), (1, 0));
+    }
+
+    #[test]
+    fn a_cache_smaller_than_a_set_is_disabled() {
+        let mut cache = SectoredCache::new(CacheConfig::l1(0));
+        assert_eq!(cache.config().num_sets(), 0);
+        read(&mut cache, 0, 4);
+        assert_eq!(read(&mut cache, 0, 4).missed, [(0, 32)]);
+        assert_eq!(cache.access([(0, 4)], true).written_through, [(0, 32)]);
+        assert_eq!(cache.stats().hits, 0);
+    }
+}
@@ -0,0 +1,155 @@
+//! Global memory coalescing analysis.
+//!
+//! The memory system serves a warp-wide global access in 32-byte sectors,
+//! grouped into 128-byte transactions (one cache line each). Lanes touching
+//! the same sector share it, so contiguous, aligned accesses need the fewest:
+//! 32 lanes loading consecutive floats take 4 sectors in 1 transaction, while
+//! a stride of 32 floats takes 32 sectors in 32 transactions.
+//!
+//! Each request is compared with the ideal for the distinct bytes it touches
+//! — those bytes packed into aligned sectors — so broadcasts are not counted
+//! as waste. The sectors are also what reach the L2 and HBM, so the executor
+//! turns the cache counts derived from them into bytes moved and achieved
+//! bandwidth.
+use crate::timing::LaneOp;
+
+/// Bytes per sector, the unit of global memory transfers
+pub const SECTOR_SIZE: usize = 32;
+
+/// Bytes per transaction (a cache line of four sectors)
+pub const TRANSACTION_SIZE: usize = 128;
+
+/// Sector and transaction counts of warp-level global memory requests.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CoalescingStats {
+    /// Warp-level global loads, stores and atomics
+    pub requests: u64,
+    /// Sectors those requests touched
+    pub sectors: u64,
+    /// Sectors needed had each request's bytes been contiguous and aligned
+    pub ideal_sectors: u64,
+    /// 128-byte transactions those requests touched
+    pub transactions: u64,
+    /// Transactions needed had each request's bytes been contiguous and
+    /// aligned
+    pub ideal_transactions: u64,
+    /// Bytes the lanes asked for (thread-level, so a broadcast counts once per
+    /// lane)
+    pub bytes_requested: u64,
+}
+
+impl CoalescingStats {
+    /// Bytes moved for the requests: every sector touched is transferred whole
+    pub fn bytes_transferred(&self) -> u64 {
+        self.sectors * SECTOR_SIZE as u64
+    }
+
+    pub fn sectors_per_request(&self) -> f64 {
+        self.sectors as f64 / self.requests.max(1) as f64
+    }
+
+    pub fn ideal_sectors_per_request(&self) -> f64 {
+        self.ideal_sectors as f64 / self.requests.max(1) as f64
+    }
+
+    /// Ideal over actual sectors [0.0, 1.0]; 1 without requests.
+    pub fn efficiency(&self) -> f64 {
+        if self.sectors == 0 { 1.0 } else { self.ideal_sectors as f64 / self.sectors as f64 }
+    }
+}
+
+impl std::ops::AddAssign for CoalescingStats {
+    fn add_assign(&mut self, other: CoalescingStats) {
+        self.requests += other.requests;
+        self.sectors += other.sectors;
+        self.ideal_sectors += other.ideal_sectors;
+        self.transactions += other.transactions;
+        self.ideal_transactions += other.ideal_transactions;
+        self.bytes_requested += other.bytes_requested;
+    }
+}
+
+/// Counts of one warp-wide global memory request made of the lane accesses
+/// `ops`.
+pub fn coalesce(ops: &[LaneOp]) -> CoalescingStats {
+    let mut ranges: Vec<(usize, usize)> =
+        ops.iter().map(|op| (op.addr, op.addr + (op.size as usize).max(1))).collect();
+    ranges.sort_unstable();
+
+    let mut sectors: Vec<usize> = Vec::new();
+    let mut distinct_bytes = 0;
+    let mut covered = 0; // end of the bytes counted so far
+    for &(start, end) in &ranges {
+        distinct_bytes += end.saturating_sub(start.max(covered));
+        covered = covered.max(end);
+        sectors.extend(start / SECTOR_SIZE..=(end - 1) / SECTOR_SIZE);
+    }
+    sectors.sort_unstable();
+    sectors.dedup();
+    let mut transactions: Vec<usize> =
+        sectors.iter().map(|s| s * SECTOR_SIZE / TRANSACTION_SIZE).collect();
+    transactions.dedup();
+
+    CoalescingStats {
+        requests: 1,
+        sectors: sectors.len() as u64,
+        ideal_sectors: distinct_bytes.div_ceil(SECTOR_SIZE) as u64,
+        transactions: transactions.len() as u64,
+        ideal_transactions: distinct_bytes.div_ceil(TRANSACTION_SIZE) as u64,
+        bytes_requested: ops.iter().map(|op| op.size as u64).sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::InstrKind;
+
+    /// One `size`-byte load per lane, lane i at `base + i * stride`.
+    fn warp(base: usize, stride: usize, size: usize) -> Vec<LaneOp> {
+        (0..32).map(|i| LaneOp::mem(InstrKind::GlobalLoad, base + i * stride, size)).collect()
+    }
+
+    fn counts(stats: CoalescingStats) -> (u64, u64, u64, u64) {
+        (stats.sectors, stats.ideal_sectors, stats.transactions, stats.ideal_transactions)
+    }
+
+    #[test]
+    fn consecutive_floats_take_one_transaction() {
+        let stats = coalesce(&warp(0, 4, 4));
+        assert_eq!(counts(stats), (4, 4, 1, 1));
+        assert_eq!((stats.bytes_requested, stats.bytes_transferred()), (128, 128));
+        assert_eq!(stats.efficiency(), 1.0);
+    }
+
+    #[test]
+    fn misaligned_and_strided_accesses_waste_sectors() {
+        assert_eq!(counts(coalesce(&warp(4, 4, 4))), (5, 4, 2, 1));
+        let strided = coalesce(&warp(0, 128, 4));
+        assert_eq!(counts(strided), (32, 4, 32, 1));
+        assert_eq!(strided.efficiency(), 0.125);
+        assert_eq!(strided.bytes_transferred(), 1024);
+    }
+
+    #[test]
+    fn shared_bytes_are_not_counted_as_waste() {
+        // Every lane loads the same float
+        let broadcast = coalesce(&warp(64, 0, 4));
+        assert_eq!(counts(broadcast), (1, 1, 1, 1));
+        assert_eq!(broadcast.bytes_requested, 128);
+        // Overlapping 12-byte stencil loads cover 34 floats
+        let stencil = coalesce(&warp(0, 4, 12));
+        assert_eq!(counts(stencil), (5, 5, 2, 2));
+        assert_eq!(stencil.bytes_requested, 384);
+    }
+
+    #[test]
+    fn stats_accumulate_over_requests() {
+        let mut total = coalesce(&warp(0, 4, 4));
+        total += coalesce(&warp(0, 128, 4));
+        assert_eq!(total.requests, 2);
+        assert_eq!(total.sectors_per_request(), 18.0);
+        assert_eq!(total.ideal_sectors_per_request(), 4.0);
+        assert_eq!(CoalescingStats::default().efficiency(), 1.0);
+    }
+}
@@ -0,0 +1,100 @@
+//! Constant memory and the constant cache.
+//!
+//! Constant memory is a 64 KB bank that the host writes between launches
+//! (cf. cudaMemcpyToSymbol) and kernels only read. Each SM reads it through
+//! a constant cache that hands one address per pass to the whole warp: lanes
+//! reading the same address share it (broadcast), while a warp reading N
+//! distinct addresses is served in N passes, one after the other. Parameters
+//! every thread reads alike cost a single pass; a per-thread lookup table in
+//! constant memory costs up to 32.
+//!
+//! The executor replays constant loads once per distinct address in the
+//! timing model. A load that hits in the SM's constant cache completes after
+//! `const_latency`; one that misses waits for the L2 to fill the lines.
+use crate::timing::LaneOp;
+
+/// Bytes of constant memory
+pub const CONSTANT_MEMORY_SIZE: usize = 64 * 1024;
+
+/// The device's constant memory bank, zeroed when the GPU is created.
+#[derive(Debug, Clone)]
+pub struct ConstantMemory {
+    data: Vec<u8>,
+}
+
+impl Default for ConstantMemory {
+    fn default() -> Self {
+        ConstantMemory { data: vec![0; CONSTANT_MEMORY_SIZE] }
+    }
+}
+
+impl ConstantMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `len` bytes at `offset`. Panics if the range is outside the bank.
+    #[track_caller]
+    pub fn read(&self, offset: usize, len: usize) -> &[u8] {
+        check_range(offset, len);
+        &self.data[offset..offset + len]
+    }
+
+    /// Write `bytes` at `offset`. Panics if the range is outside the bank.
+    #[track_caller]
+    pub fn write(&mut self, offset: usize, bytes: &[u8]) {
+        check_range(offset, bytes.len());
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+#[track_caller]
+fn check_range(offset: usize, len: usize) {
+    assert!(
+        offset.checked_add(len).is_some_and(|end| end <= CONSTANT_MEMORY_SIZE),
+        "constant memory access of {} bytes at offset {} is outside the {} KB bank",
+        len,
+        offset,
+        CONSTANT_MEMORY_SIZE / 1024,
+    );
+}
+
+/// Passes a warp-wide constant load needs: one per distinct address its
+/// lanes read.
+pub fn constant_passes(ops: &[LaneOp]) -> u32 {
+    let mut addrs: Vec<usize> = ops.iter().map(|op| op.addr).collect();
+    addrs.sort_unstable();
+    addrs.dedup();
+    addrs.len().max(1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::InstrKind;
+
+    #[test]
+    fn a_pass_per_distinct_address() {
+        let load = |addr| LaneOp::mem(InstrKind::ConstantLoad, addr, 4);
+        // Uniform: every lane reads word 0
+        assert_eq!(constant_passes(&vec![load(0); 32]), 1);
+        let ops: Vec<LaneOp> = (0..32).map(|i| load(i % 4 * 4)).collect();
+        assert_eq!(constant_passes(&ops), 4);
+        let ops: Vec<LaneOp> = (0..32).map(|i| load(i * 4)).collect();
+        assert_eq!(constant_passes(&ops), 32);
+        assert_eq!(constant_passes(&[]), 1);
+    }
+
+    #[test]
+    fn writes_land_in_the_bank() {
+        let mut cmem = ConstantMemory::new();
+        cmem.write(60, &[1, 2, 3, 4]);
+        assert_eq!(cmem.read(58, 8), [0, 0, 1, 2, 3, 4, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the 64 KB bank")]
+    fn accesses_past_the_bank_panic() {
+        ConstantMemory::new().read(CONSTANT_MEMORY_SIZE - 2, 4);
+    }
+}
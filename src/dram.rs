@@ -0,0 +1,301 @@
+//! DRAM timing model for HBM.
+//!
+//! HBM stacks are modeled as independent channels (pseudo-channels on HBM3),
+//! each with a data bus and a set of banks that hold one open row each.
+//! Addresses are interleaved over the channels in `interleave_bytes` chunks;
+//! consecutive chunks of a channel fill a row of one bank before moving on to
+//! the next bank, so streaming access keeps hitting open rows.
+//!
+//! Every L2 miss is one sector read or write scheduled at the cycle it is
+//! issued, first come first served:
+//!   - row hit: the row is open, the column access takes `t_cas`
+//!   - row miss: the bank is idle, the row is activated first (`t_rcd`)
+//!   - row conflict: another row is open and is closed first (`t_rp`)
+//!
+//! then the data occupies the channel's bus for `t_burst`. A request waits for
+//! its bank and bus to be free, so bandwidth-bound kernels queue up behind
+//! the channels' peak bandwidth, and accesses that map to a few channels
+//! (partition camping) queue behind those alone. Timing parameters are in SM
+//! cycles.
+
+/// Channel and bank geometry of the memory and its timing in SM cycles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DramConfig {
+    /// Independent channels over all stacks
+    pub channels: usize,
+    /// Banks per channel
+    pub banks: usize,
+    /// Bytes per row of a bank (the row buffer)
+    pub row_bytes: usize,
+    /// Consecutive bytes mapped to one channel before moving to the next;
+    /// divides `row_bytes`
+    pub interleave_bytes: usize,
+    /// Bytes per request (an L2 sector)
+    pub access_bytes: usize,
+    /// Row activation to column access (RAS to CAS delay)
+    pub t_rcd: u64,
+    /// Closing an open row (row precharge)
+    pub t_rp: u64,
+    /// Column access to first data (CAS latency)
+    pub t_cas: u64,
+    /// Cycles one request's data occupies the channel's bus
+    pub t_burst: u64,
+}
+
+impl DramConfig {
+    /// H100 SXM5: five HBM3 stacks of 32 pseudo-channels with 16 banks and
+    /// 1 KB rows, ~14 ns row and column timings at the 1.98 GHz SM clock and
+    /// a 32-byte sector every 3 cycles per channel (~3.4 TB/s in total).
+    pub fn h100() -> Self {
+        DramConfig {
+            channels: 160,
+            banks: 16,
+            row_bytes: 1024,
+            interleave_bytes: 256,
+            access_bytes: 32,
+            t_rcd: 28,
+            t_rp: 28,
+            t_cas: 28,
+            t_burst: 3,
+        }
+    }
+
+    /// A100 SXM4: five HBM2e stacks as H100, at the 1.41 GHz SM clock
+    /// (~1.8 TB/s in total).
+    pub fn a100() -> Self {
+        DramConfig { t_rcd: 20, t_rp: 20, t_cas: 20, t_burst: 4, ..Self::h100() }
+    }
+
+    /// Peak bandwidth over all channels in bytes per SM cycle
+    pub fn peak_bytes_per_cycle(&self) -> f64 {
+        (self.channels * self.access_bytes) as f64 / self.t_burst.max(1) as f64
+    }
+
+    /// Cycles from issue to the end of the data transfer for a request that
+    /// opens a row in an idle bank of an idle channel
+    pub fn unloaded_latency(&self) -> u64 {
+        self.t_rcd + self.t_cas + self.t_burst
+    }
+
+    /// (channel, bank, row) of the byte at `addr`.
+    pub fn map(&self, addr: usize) -> (usize, usize, usize) {
+        let chunk = addr / self.interleave_bytes;
+        let channel = chunk % self.channels;
+        // Chunks of this channel, in order; a row holds several of them
+        let local = chunk / self.channels;
+        let chunks_per_row = (self.row_bytes / self.interleave_bytes).max(1);
+        let bank = local / chunks_per_row % self.banks;
+        let row = local / (chunks_per_row * self.banks);
+        (channel, bank, row)
+    }
+}
+
+/// Request counts of a DRAM, by how they found their bank.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DramStats {
+    pub reads: u64,
+    pub writes: u64,
+    /// Requests to the open row of their bank
+    pub row_hits: u64,
+    /// Requests to a bank without an open row
+    pub row_misses: u64,
+    /// Requests that had to close another row first
+    pub row_conflicts: u64,
+    /// Cycles requests waited for a busy bank or bus, summed
+    pub queue_cycles: u64,
+}
+
+impl DramStats {
+    pub fn requests(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    /// Fraction of requests that hit an open row [0.0, 1.0]; 0 without
+    /// requests.
+    pub fn row_hit_rate(&self) -> f64 {
+        if self.requests() == 0 { 0.0 } else { self.row_hits as f64 / self.requests() as f64 }
+    }
+
+    /// Average cycles a request waited for its bank and bus
+    pub fn queue_cycles_per_request(&self) -> f64 {
+        self.queue_cycles as f64 / self.requests().max(1) as f64
+    }
+}
+
+impl std::ops::AddAssign for DramStats {
+    fn add_assign(&mut self, other: DramStats) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.row_hits += other.row_hits;
+        self.row_misses += other.row_misses;
+        self.row_conflicts += other.row_conflicts;
+        self.queue_cycles += other.queue_cycles;
+    }
+}
+
+/// State of one bank.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bank {
+    open_row: Option<usize>,
+    /// Cycle from which the bank accepts its next request
+    ready_at: u64,
+}
+
+/// Channels and banks of a DRAM, with the requests scheduled on them so far.
+#[derive(Debug, Clone)]
+pub struct Dram {
+    config: DramConfig,
+    /// Banks of every channel, channel-major
+    banks: Vec<Bank>,
+    /// Cycle from which each channel's data bus is free
+    bus_free_at: Vec<u64>,
+    /// Requests each channel has served since the DRAM was created
+    channel_requests: Vec<u64>,
+    /// Totals since the DRAM was created
+    stats: DramStats,
+}
+
+impl Dram {
+    pub fn new(config: DramConfig) -> Self {
+        assert!(
+            config.channels > 0
+                && config.banks > 0
+                && config.interleave_bytes > 0
+                && config.row_bytes.is_multiple_of(config.interleave_bytes),
+            "invalid DRAM geometry: {:?}",
+            config,
+        );
+        Dram {
+            banks: vec![Bank::default(); config.channels * config.banks],
+            bus_free_at: vec![0; config.channels],
+            channel_requests: vec![0; config.channels],
+            config,
+            stats: DramStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> &DramConfig {
+        &self.config
+    }
+
+    /// Totals since the DRAM was created
+    pub fn stats(&self) -> DramStats {
+        self.stats
+    }
+
+    /// Requests each channel has served since the DRAM was created — index =
+    /// channel
+    pub fn channel_requests(&self) -> &[u64] {
+        &self.channel_requests
+    }
+
+    /// Schedule a request for the sector at `addr` issued at cycle `now`,
+    /// counting it in `stats`. Returns the cycle its data transfer ends.
+    pub fn access(&mut self, addr: usize, write: bool, now: u64, stats: &mut DramStats) -> u64 {
+        let cfg = &self.config;
+        let (channel, bank, row) = cfg.map(addr);
+        let b = &mut self.banks[channel * cfg.banks + bank];
+
+        let start = now.max(b.ready_at);
+        let mut request = DramStats::default();
+        let activate = match b.open_row {
+            Some(open) if open == row => {
+                request.row_hits = 1;
+                0
+            }
+            Some(_) => {
+                request.row_conflicts = 1;
+                cfg.t_rp + cfg.t_rcd
+            }
+            None => {
+                request.row_misses = 1;
+                cfg.t_rcd
+            }
+        };
+        b.open_row = Some(row);
+        // Column accesses to an open row pipeline one burst apart
+        b.ready_at = start + activate + cfg.t_burst;
+
+        let data = (start + activate + cfg.t_cas).max(self.bus_free_at[channel]);
+        let done = data + cfg.t_burst;
+        self.bus_free_at[channel] = done;
+        self.channel_requests[channel] += 1;
+
+        if write {
+            request.writes = 1;
+        } else {
+            request.reads = 1;
+        }
+        // Waiting beyond the time the request itself needs
+        request.queue_cycles = done - now - (activate + cfg.t_cas + cfg.t_burst);
+        self.stats += request;
+        *stats += request;
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four channels of two banks with 1 KB rows of four 256-byte chunks.
+    fn small() -> DramConfig {
+        DramConfig {
+            channels: 4,
+            banks: 2,
+            row_bytes: 1024,
+            interleave_bytes: 256,
+            access_bytes: 32,
+            t_rcd: 10,
+            t_rp: 10,
+            t_cas: 10,
+            t_burst: 2,
+        }
+    }
+
+    #[test]
+    fn map_interleaves_channels_then_fills_rows_and_banks() {
+        let cfg = small();
+        assert_eq!(cfg.map(0), (0, 0, 0));
+        assert_eq!(cfg.map(255), (0, 0, 0));
+        assert_eq!(cfg.map(256), (1, 0, 0));
+        assert_eq!(cfg.map(3 * 256), (3, 0, 0));
+        // Next chunk of channel 0, same row
+        assert_eq!(cfg.map(4 * 256), (0, 0, 0));
+        // Channel 0's fifth chunk starts a row in the next bank
+        assert_eq!(cfg.map(16 * 256), (0, 1, 0));
+        // ... and its ninth wraps around to bank 0, row 1
+        assert_eq!(cfg.map(32 * 256), (0, 0, 1));
+        assert_eq!(cfg.map(32 * 256 + 100), (0, 0, 1));
+    }
+
+    #[test]
+    fn requests_pay_for_activation_and_conflicts() {
+        let cfg = small();
+        assert_eq!(cfg.unloaded_latency(), 22);
+        let mut dram = Dram::new(cfg);
+        let mut stats = DramStats::default();
+        assert_eq!(dram.access(0, false, 0, &mut stats), 22);
+        // A row hit behind it waits for the bank, then for the bus
+        assert_eq!(dram.access(32, true, 0, &mut stats), 24);
+        // Another row of the same bank closes the open one
+        assert_eq!(dram.access(32 * 256, false, 100, &mut stats), 132);
+        // Another channel is independent
+        assert_eq!(dram.access(256, false, 0, &mut stats), 22);
+
+        assert_eq!((stats.reads, stats.writes), (3, 1));
+        assert_eq!((stats.row_hits, stats.row_misses, stats.row_conflicts), (1, 2, 1));
+        assert_eq!(stats.queue_cycles, 12);
+        assert_eq!(stats.row_hit_rate(), 0.25);
+        assert_eq!(dram.stats(), stats);
+        assert_eq!(dram.channel_requests(), [3, 1, 0, 0]);
+    }
+
+    #[test]
+    fn h100_peak_bandwidth() {
+        let cfg = DramConfig::h100();
+        // ~3.4 TB/s at 1.98 GHz
+        let tb_s = cfg.peak_bytes_per_cycle() * 1.98e9 / 1e12;
+        assert!((3.3..3.5).contains(&tb_s), "{}", tb_s);
+    }
+}
@@ -6,20 +6,65 @@
 ///    Assigns thread blocks to SMs based on resource availability — the SM
 ///    with the most remaining headroom (vs. its occupancy limit) gets the
 ///    next block. Ties broken by SM ID (effectively round-robin among equals).
+///    Each SM is filled up to `max_blocks_per_sm`; as resident blocks retire
+///    the freed slots are handed to pending blocks.
 ///
 /// 2. Warp scheduling (per SM):
-///    Within each block, warps are ordered by the chosen policy (LRR, GTO,
-///    or TwoLevel) and executed in that order.
+///    Each block's threads are run functionally when it is dispatched,
+///    recording the instructions every warp issues. The timing model
+///    (timing.rs) then replays the warps of all resident blocks cycle by
+///    cycle, letting the chosen policy (LRR, GTO, or TwoLevel) pick which
+///    eligible warps issue each cycle.
+///
+/// With thread block clusters (`LaunchConfig::with_cluster`) the unit of
+/// dispatch is a whole cluster: its blocks are placed together on SMs of one
+/// GPC, run together in the functional model (so they can access each other's
+/// shared memory and meet at cluster barriers), and a cluster barrier in the
+/// timing model holds each block until every live block of its cluster has
+/// arrived. A cooperative launch is dispatched as one unit in the same way:
+/// all of its blocks become resident together and meet at grid barriers.
+///
+/// Threads can launch child grids (dynamic parallelism, `ThreadCtx::launch`).
+/// A child becomes a launch of its own once the device-side launch latency
+/// has elapsed; its parent completes only after all of its children.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::task::{Context, Waker};
+
+use crate::atomic::address_contention;
+use crate::banks::{bank_wavefronts, BankConflictSite, SmemBankSize, MAX_PRINTED_SITES};
+use crate::cache::CacheStats;
+use crate::coalescing::{coalesce, CoalescingStats};
+use crate::constant::constant_passes;
+use crate::dram::DramStats;
 use crate::gpu::GPU;
-use crate::kernel::{Dim3, Kernel, LaunchConfig, ThreadCtx};
+use crate::kernel::{
+    ChildLaunches, DeviceMemory, Dim3, Kernel, KernelFn, LaunchConfig, LaunchError, ThreadCtx,
+    ThreadTask,
+};
+use crate::managed::ManagedStats;
+use crate::memcheck::{Memcheck, MemcheckError, MAX_PRINTED_ERRORS};
 use crate::metrics::{now_ms, write_metrics, LiveMetrics};
-use crate::occupancy::{max_blocks_per_sm, occupancy, KernelResources, SmConfig};
-use crate::scheduler::{SchedulingPolicy, WarpScheduler, WarpSlot};
+use crate::occupancy::{
+    block_footprint, max_blocks_per_sm, occupancy, smem_carveout, BlockFootprint, KernelResources,
+    SmConfig,
+};
+use crate::racecheck::{RaceHazard, RaceState, MAX_PRINTED_HAZARDS};
+use crate::scheduler::{SchedulingPolicy, WarpScheduler};
+use crate::simt::{merge_lanes, WarpStream};
+use crate::sync::{resolve_warp_ops, LaneState, SyncPoint};
+use crate::timing::{GlobalMemory, InstrKind, LaneOp, Site, SmCaches, SmTimer, WarpInstr};
 use crate::warp::WARP_SIZE;
 
+/// Modeled latency from a device-side launch until the child grid can start
+/// dispatching blocks, in nanoseconds.
+pub const DEVICE_LAUNCH_LATENCY_NS: u64 = 1_000;
+
 /// Statistics collected during a kernel launch.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ExecutionStats {
+    /// Name of the launched kernel
+    pub kernel_name: String,
     /// Total thread blocks executed
     pub blocks_executed: u32,
     /// Total warps executed
@@ -28,37 +73,281 @@ pub struct ExecutionStats {
     pub threads_executed: u32,
     /// Theoretical occupancy [0.0, 1.0]
     pub theoretical_occupancy: f32,
+    /// Resident warps averaged over the cycles each SM was busy, as a
+    /// fraction of `max_warps` [0.0, 1.0]
+    pub achieved_occupancy: f32,
+    /// Most blocks resident on any one SM at the same time
+    pub peak_resident_blocks: u32,
     /// Max blocks allowed per SM by occupancy constraints
     pub max_blocks_per_sm: u32,
     /// Which resource limited occupancy
     pub occupancy_limiter: String,
     /// Name of the warp scheduling policy used
     pub scheduling_policy: String,
+    /// Device cycle (`GPU::cycle`) at which the launch started
+    pub start_cycle: u64,
+    /// Device cycle at which the last block retired
+    pub end_cycle: u64,
+    /// Simulated cycles from launch until the last block retired
+    pub total_cycles: u64,
+    /// Warp instructions issued across all SMs
+    pub instructions_issued: u64,
+    /// Cycles each SM spent executing blocks — index = SM id
+    pub sm_busy_cycles: Vec<u64>,
+    /// Cycles each SM sat idle while the kernel was running — index = SM id
+    pub sm_idle_cycles: Vec<u64>,
+    /// Estimated wall time in microseconds (total_cycles at the SM clock)
+    pub wall_time_us: f64,
+    /// Atomic operations (thread-level) on global memory
+    pub global_atomics: u64,
+    /// Atomic operations (thread-level) on shared memory
+    pub shared_atomics: u64,
+    /// Atomic operations serialized behind another lane of the same warp
+    /// instruction that targeted the same address
+    pub atomic_conflicts: u64,
+    /// Most contended global atomic address and the number of atomics on it
+    pub atomic_hotspot: Option<(usize, u64)>,
+    /// Atomic operations completed per simulated cycle
+    pub atomic_throughput: f64,
+    /// Branches at which the active lanes of a warp took different paths
+    pub divergent_branches: u64,
+    /// Warps that diverged at least once
+    pub divergent_warps: u32,
+    /// Average fraction of a warp's 32 lanes active per issued instruction
+    /// (warp execution efficiency) [0.0, 1.0]
+    pub simt_efficiency: f64,
+    /// Lane utilization of every warp, in launch order [0.0, 1.0]
+    pub warp_lane_utilization: Vec<f64>,
+    /// Nesting depth of the launch: 0 from the host, parent's depth + 1 for a
+    /// child grid
+    pub nesting_depth: u32,
+    /// Child grids launched by this kernel's threads
+    pub child_launches: u64,
+    /// Blocks per thread block cluster (1 without clusters)
+    pub cluster_size: u32,
+    /// Accesses (thread-level) to another block's shared memory in the
+    /// cluster (DSMEM)
+    pub dsmem_accesses: u64,
+    /// Invalid global memory accesses found by memcheck (`GPU::memcheck`)
+    pub memcheck_errors: Vec<MemcheckError>,
+    /// Data races found by racecheck (`GPU::racecheck`)
+    pub race_hazards: Vec<RaceHazard>,
+    /// Bytes of each SM's unified L1 / shared memory array given to shared
+    /// memory for this kernel; the rest is L1
+    pub smem_carveout: u32,
+    /// L1 sector hits and misses of this kernel's global memory
+    /// instructions, over all SMs
+    pub l1: CacheStats,
+    /// L2 sector hits, misses and evictions caused by this kernel's global
+    /// memory instructions
+    pub l2: CacheStats,
+    /// Warp-level shared memory instructions (loads, stores and atomics)
+    pub smem_requests: u64,
+    /// Wavefronts those instructions needed (see banks.rs)
+    pub smem_wavefronts: u64,
+    /// Wavefronts beyond the conflict-free minimum
+    pub smem_bank_conflicts: u64,
+    /// Bank conflicts of every source line with shared memory accesses, most
+    /// conflicts first
+    pub bank_conflict_sites: Vec<BankConflictSite>,
+    /// Warp-level constant memory loads
+    pub const_requests: u64,
+    /// Passes those loads needed, one per distinct address (see constant.rs)
+    pub const_passes: u64,
+    /// Constant cache lookups of this kernel's constant loads
+    pub const_cache: CacheStats,
+    /// Warp-level texture requests: texture fetches (one per row of texels
+    /// a fetch reads) and surface reads
+    pub tex_requests: u64,
+    /// Texture cache sector hits and misses of those requests
+    pub tex_cache: CacheStats,
+    /// Sectors and 128-byte transactions of this kernel's warp-level global
+    /// memory requests, against the ideal (see coalescing.rs)
+    pub coalescing: CoalescingStats,
+    /// Bytes moved between the SMs and the L2: every sector reaching the L2
+    pub l2_bytes: u64,
+    /// Bytes moved between the L2 and HBM: sectors filled plus sectors
+    /// written
+    pub hbm_bytes: u64,
+    /// Of `hbm_bytes`, the sectors read to fill L2 misses of loads and
+    /// atomics (store misses need no fill)
+    pub hbm_fill_bytes: u64,
+    /// Of `hbm_bytes`, the sectors written: written back when a dirty line is
+    /// evicted, or written through by a write-through L2 — once per write
+    pub hbm_write_bytes: u64,
+    /// Achieved HBM bandwidth in GB/s (`hbm_bytes` over the wall time)
+    pub hbm_bandwidth_gbps: f64,
+    /// DRAM requests caused by this kernel's L2 misses and write-backs: row
+    /// hits, misses and conflicts, and queueing (see dram.rs)
+    pub dram: DramStats,
+    /// DRAM requests each channel served while the kernel ran — index =
+    /// channel. Requests piling up on a few channels are partition camping.
+    pub dram_channel_requests: Vec<u64>,
+    /// Page faults, migrations and remote accesses of this kernel's managed
+    /// memory accesses (see managed.rs)
+    pub managed: ManagedStats,
+}
+
+/// Identifies a launch started with `KernelExecutor::begin`.
+pub type LaunchId = usize;
+
+/// A kernel launch whose blocks are still being dispatched or are resident.
+struct ActiveLaunch {
+    kernel: Kernel,
+    config: LaunchConfig,
+    stats: ExecutionStats,
+    /// Resources one block occupies on an SM
+    footprint: BlockFootprint,
+    /// Blocks of this kernel allowed per SM by occupancy (at least 1)
+    block_cap: u32,
+    blocks_total: u32,
+    /// Linear index of the next block to dispatch
+    next_block: u32,
+    blocks_retired: u32,
+    /// Blocks of this launch resident on each SM — index = SM id
+    resident: Vec<u32>,
+    /// Cycle at which each SM last started hosting a block of this launch
+    busy_since: Vec<u64>,
+    /// Cycles each SM hosted at least one block of this launch
+    busy: Vec<u64>,
+    /// Resident warps of this launch integrated over time, summed over SMs
+    warp_cycles: u64,
+    /// Global atomic count per address, for hotspot reporting
+    atomic_addrs: HashMap<usize, u64>,
+    /// Shared memory bank conflicts per source line
+    bank_sites: HashMap<Site, BankConflictSite>,
+    /// `Dram::channel_requests` when the launch began
+    dram_channels_at_start: Vec<u64>,
+    /// Active lanes and issued instructions summed over all warps
+    active_lanes: u64,
+    lane_instructions: u64,
+    /// Launch whose thread launched this one (dynamic parallelism)
+    parent: Option<LaunchId>,
+    /// Child launches that have not completed yet
+    pending_children: u32,
+    /// Race detection state, when racecheck is enabled
+    racecheck: Option<RaceState>,
+    done: bool,
+}
+
+impl ActiveLaunch {
+    fn is_complete(&self) -> bool {
+        self.next_block == self.blocks_total
+            && self.blocks_retired == self.blocks_total
+            && self.pending_children == 0
+    }
+}
+
+/// A child grid waiting out the device-side launch latency.
+struct QueuedChild {
+    kernel: Kernel,
+    config: LaunchConfig,
+    parent: LaunchId,
+    /// Device cycle at which the child launch begins
+    start_at: u64,
 }
 
-/// Executes a kernel on a GPU, simulating the SM/warp/thread hierarchy.
+/// Executes kernels on a GPU, simulating the SM/warp/thread hierarchy.
+///
+/// Several launches can be in flight at once (see stream.rs): their blocks
+/// share the SMs whenever resources allow, and the device clock
+/// (`GPU::cycle`) advances for all of them together.
 pub struct KernelExecutor<'a> {
     pub gpu: &'a mut GPU,
-    scheduler: Box<dyn WarpScheduler>,
+    policy: SchedulingPolicy,
+    /// One warp scheduler per SM
+    schedulers: Vec<Box<dyn WarpScheduler>>,
     sm_config: SmConfig,
     /// Monotonically increasing counter for assigning warp ages
     warp_age_counter: u64,
+    /// Per-SM issue pipelines
+    timers: Vec<SmTimer>,
+    /// Cycle at which each SM last went from idle to busy
+    sm_busy_since: Vec<u64>,
+    launches: Vec<ActiveLaunch>,
+    /// Launch owning each resident block, keyed by `SmTimer` block id
+    block_owner: HashMap<u32, LaunchId>,
+    next_block_id: u32,
+    /// Child grids launched by threads that have not begun yet
+    queued_children: Vec<QueuedChild>,
+    /// Cluster of each resident block that belongs to a multi-block cluster
+    block_cluster: HashMap<u32, u64>,
+    /// Resident blocks of each cluster
+    clusters: HashMap<u64, Vec<u32>>,
+    next_cluster_id: u64,
 }
 
 impl<'a> KernelExecutor<'a> {
     pub fn new(gpu: &'a mut GPU, policy: SchedulingPolicy, sm_config: SmConfig) -> Self {
+        let num_sms = gpu.sms.len();
         KernelExecutor {
-            scheduler: policy.build(),
+            policy,
+            schedulers: gpu.sms.iter().map(|_| policy.build()).collect(),
             gpu,
             sm_config,
             warp_age_counter: 0,
+            timers: (0..num_sms).map(|_| SmTimer::new()).collect(),
+            sm_busy_since: vec![0; num_sms],
+            launches: Vec::new(),
+            block_owner: HashMap::new(),
+            next_block_id: 0,
+            queued_children: Vec::new(),
+            block_cluster: HashMap::new(),
+            clusters: HashMap::new(),
+            next_cluster_id: 0,
         }
     }
 
-    /// Launch a kernel with the given configuration.
+    /// Launch a kernel with the given configuration and run it to completion.
     pub fn launch(&mut self, kernel: &Kernel, config: &LaunchConfig) -> ExecutionStats {
+        let id = self.begin(kernel, config);
+        loop {
+            if let Some((_, stats)) = self.advance(None).into_iter().find(|(done, _)| *done == id) {
+                return stats;
+            }
+        }
+    }
+
+    /// True when every launch has completed.
+    pub fn is_idle(&self) -> bool {
+        self.launches.iter().all(|l| l.done)
+    }
+
+    /// Start a kernel launch at the current device cycle. Its blocks are
+    /// dispatched by subsequent calls to `advance`.
+    pub fn begin(&mut self, kernel: &Kernel, config: &LaunchConfig) -> LaunchId {
+        self.begin_nested(kernel, config, None)
+    }
+
+    /// Start a launch made by a thread of launch `parent`, or by the host.
+    fn begin_nested(
+        &mut self,
+        kernel: &Kernel,
+        config: &LaunchConfig,
+        parent: Option<LaunchId>,
+    ) -> LaunchId {
+        // Device-side launches were checked by `ThreadCtx::launch`
+        if let Err(e) = config.check_clusters(self.gpu.smallest_gpc_sms()) {
+            panic!("kernel '{}': {}", kernel.name, e);
+        }
+
+        // Only `GPU::launch_cooperative_kernel` sets the flag, after this check
+        if config.cooperative {
+            let max_resident = self.gpu.max_cooperative_blocks(config);
+            assert!(
+                config.num_blocks() <= max_resident,
+                "kernel '{}': {}",
+                kernel.name,
+                LaunchError::CooperativeLaunchTooLarge { blocks: config.num_blocks(), max_resident },
+            );
+        }
+
         let mut stats = ExecutionStats {
-            scheduling_policy: self.scheduler.name().to_string(),
+            kernel_name: kernel.name.clone(),
+            scheduling_policy: self.policy.name().to_string(),
+            start_cycle: self.gpu.cycle,
+            nesting_depth: parent.map_or(0, |p| self.launches[p].stats.nesting_depth + 1),
+            cluster_size: config.cluster_size(),
             ..Default::default()
         };
 
@@ -69,17 +358,22 @@ impl<'a> KernelExecutor<'a> {
             smem_per_block: config.smem_per_block,
         };
 
-        let (max_blks, limiter) = max_blocks_per_sm(&kernel_res, &self.sm_config);
+        // Occupancy as limited by the shared memory of the launch's carveout
+        let carveout = smem_carveout(&kernel_res, &self.sm_config, config.smem_carveout);
+        let sm_config = self.sm_config.with_smem_carveout(carveout);
+        let (max_blks, limiter) = max_blocks_per_sm(&kernel_res, &sm_config);
         let warps_per_block = config.threads_per_block().div_ceil(32);
         let occ = occupancy(max_blks, warps_per_block, self.sm_config.max_warps);
 
+        stats.smem_carveout = carveout;
         stats.max_blocks_per_sm = max_blks;
         stats.theoretical_occupancy = occ;
         stats.occupancy_limiter = limiter.to_string();
 
         println!(
             "[gpusim] Launching kernel '{}' | grid=({},{},{}) block=({},{},{}) | \
-             policy={} | max_blocks/SM={} | occupancy={:.1}% (limited by {})",
+             policy={} | max_blocks/SM={} | occupancy={:.1}% (limited by {}) | \
+             smem/L1={}KB/{}KB",
             kernel.name,
             config.grid_dim.x, config.grid_dim.y, config.grid_dim.z,
             config.block_dim.x, config.block_dim.y, config.block_dim.z,
@@ -87,87 +381,532 @@ impl<'a> KernelExecutor<'a> {
             max_blks,
             occ * 100.0,
             limiter,
+            carveout / 1024,
+            self.sm_config.l1_smem_bytes.saturating_sub(carveout) / 1024,
         );
 
-        // Reset SM resource usage before launch
-        for sm in self.gpu.sms.iter_mut() {
-            sm.resource_usage = Default::default();
+        let num_sms = self.gpu.sms.len();
+        self.launches.push(ActiveLaunch {
+            kernel: kernel.clone(),
+            config: config.clone(),
+            stats,
+            footprint: block_footprint(&kernel_res, &sm_config),
+            // A kernel that cannot fit even one block still runs one at a time
+            block_cap: max_blks.max(1),
+            blocks_total: config.num_blocks(),
+            next_block: 0,
+            blocks_retired: 0,
+            resident: vec![0; num_sms],
+            busy_since: vec![0; num_sms],
+            busy: vec![0; num_sms],
+            warp_cycles: 0,
+            atomic_addrs: HashMap::new(),
+            bank_sites: HashMap::new(),
+            dram_channels_at_start: self.gpu.hbm.dram.channel_requests().to_vec(),
+            active_lanes: 0,
+            lane_instructions: 0,
+            parent,
+            pending_children: 0,
+            racecheck: self.gpu.racecheck.then(RaceState::default),
+            done: false,
+        });
+        let id = self.launches.len() - 1;
+
+        // Write initial "running" snapshot so viz shows the kernel immediately
+        self.write_snapshot(id, "running");
+        id
+    }
+
+    /// Run one scheduling round at the current device cycle — retire finished
+    /// blocks, dispatch pending blocks onto free SMs, issue one cycle on every
+    /// busy SM — then advance the clock, skipping ahead when nothing can issue
+    /// but never past `until`. Returns the launches that completed.
+    pub fn advance(&mut self, until: Option<u64>) -> Vec<(LaunchId, ExecutionStats)> {
+        let now = self.gpu.cycle;
+        if self.is_idle() {
+            if let Some(t) = until {
+                self.gpu.cycle = t.max(now);
+            }
+            return Vec::new();
         }
 
-        let blocks_total = config.num_blocks();
+        // Retire finished blocks and free their resources
+        for sm_id in 0..self.timers.len() {
+            let retired = self.timers[sm_id].retire(now);
+            if retired.is_empty() {
+                continue;
+            }
+            for block in retired {
+                let id = self.block_owner.remove(&block.id).expect("unknown block");
+                if let Some(cluster) = self.block_cluster.remove(&block.id) {
+                    let members = self.clusters.get_mut(&cluster).expect("unknown cluster");
+                    members.retain(|&b| b != block.id);
+                    if members.is_empty() {
+                        self.clusters.remove(&cluster);
+                    }
+                }
+                let l = &mut self.launches[id];
+                self.gpu.sms[sm_id].free_block(&l.footprint);
+                l.blocks_retired += 1;
+                l.stats.blocks_executed += 1;
+                l.stats.instructions_issued += block.instructions_issued;
+                l.stats.l1 += block.l1;
+                l.stats.const_cache += block.constant;
+                l.stats.tex_cache += block.texture;
+                l.stats.l2 += block.l2;
+                l.stats.dram += block.dram;
+                l.stats.managed += block.managed;
+                l.resident[sm_id] -= 1;
+                if l.resident[sm_id] == 0 {
+                    l.busy[sm_id] += now - l.busy_since[sm_id];
+                }
+                self.write_snapshot(id, "running");
+            }
+            let sm = &mut self.gpu.sms[sm_id];
+            sm.clock = now;
+            if self.timers[sm_id].is_idle() {
+                sm.busy_cycles += now - self.sm_busy_since[sm_id];
+            }
+        }
 
-        // Write initial "running" snapshot so viz shows the kernel immediately
-        self.write_snapshot(kernel, config, &stats, blocks_total, "running");
-
-        // Iterate over all blocks in the grid and assign them to SMs
-        for bz in 0..config.grid_dim.z {
-            for by in 0..config.grid_dim.y {
-                for bx in 0..config.grid_dim.x {
-                    let block_idx = Dim3::new(bx, by, bz);
-
-                    // Find the SM with the most available headroom
-                    let sm_id = self.find_best_sm(max_blks);
-
-                    // Allocate resources on that SM
-                    let warps = warps_per_block;
-                    let smem = config.smem_per_block;
-                    self.gpu.sms[sm_id].allocate_block(
-                        config.threads_per_block(),
-                        warps,
-                        smem,
-                    );
-
-                    // Write snapshot: shows this SM as active while block runs
-                    self.write_snapshot(kernel, config, &stats, blocks_total, "running");
-
-                    // Optional per-block pause for live visualisation
-                    if config.block_delay_ms > 0 {
-                        std::thread::sleep(std::time::Duration::from_millis(
-                            config.block_delay_ms,
-                        ));
+        // Begin child grids whose launch latency has elapsed
+        let (ready, queued): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.queued_children).into_iter().partition(|c| c.start_at <= now);
+        self.queued_children = queued;
+        for child in ready {
+            self.begin_nested(&child.kernel, &child.config, Some(child.parent));
+        }
+
+        // Fill the SMs with pending blocks, oldest launch first. A later
+        // launch only gets the resources left over once every block of the
+        // launches before it has been dispatched.
+        for id in 0..self.launches.len() {
+            if self.launches[id].done {
+                continue;
+            }
+            self.dispatch_blocks(id, now);
+            if self.launches[id].next_block < self.launches[id].blocks_total {
+                break;
+            }
+        }
+
+        // A parent completes in the same cycle as its last child, so repeat
+        // until no further launch completes
+        let mut completed = Vec::new();
+        let mut any_completed = true;
+        while any_completed {
+            any_completed = false;
+            for id in 0..self.launches.len() {
+                if !self.launches[id].done && self.launches[id].is_complete() {
+                    completed.push((id, self.finish(id, now)));
+                    if let Some(parent) = self.launches[id].parent {
+                        self.launches[parent].pending_children -= 1;
                     }
+                    any_completed = true;
+                }
+            }
+        }
+        if self.is_idle() {
+            return completed;
+        }
+
+        self.release_group_barriers(now);
+
+        // Advance every busy SM by one cycle; skip ahead when none can issue
+        let mut issued = 0;
+        let gpu = &mut *self.gpu;
+        let units = self.timers.iter_mut().zip(self.schedulers.iter_mut()).zip(gpu.sms.iter_mut());
+        for ((timer, scheduler), sm) in units {
+            if !timer.is_idle() {
+                let mut global = GlobalMemory {
+                    l2: &mut gpu.l2_cache,
+                    dram: &mut gpu.hbm.dram,
+                    managed: &mut gpu.managed,
+                };
+                let mut caches = SmCaches {
+                    l1: &mut sm.l1,
+                    constant: &mut sm.const_cache,
+                    texture: &mut sm.tex_cache,
+                };
+                let cfg = &self.sm_config;
+                issued += timer.step(now, scheduler.as_mut(), cfg, &mut caches, &mut global);
+            }
+        }
+        let mut next = if issued > 0 {
+            now + 1
+        } else {
+            self.timers
+                .iter()
+                .filter_map(|t| t.next_event(now))
+                .chain(self.queued_children.iter().map(|c| c.start_at))
+                .min()
+                .unwrap_or(now + 1)
+        };
+        if let Some(t) = until
+            && t > now
+        {
+            next = next.min(t);
+        }
+        for l in self.launches.iter_mut().filter(|l| !l.done) {
+            let resident_warps: u64 =
+                l.resident.iter().map(|&b| (b * l.footprint.warps) as u64).sum();
+            l.warp_cycles += resident_warps * (next - now);
+        }
+        self.gpu.cycle = next;
+        completed
+    }
+
+    /// Dispatch blocks of launch `id` while some SM has room for them — a
+    /// whole cluster at a time when the launch uses clusters, and the whole
+    /// grid at once for a cooperative launch.
+    fn dispatch_blocks(&mut self, id: LaunchId, now: u64) {
+        while self.launches[id].next_block < self.launches[id].blocks_total
+            && let Some(sms) = self.place_blocks(id)
+        {
+            let l = &mut self.launches[id];
+            let size = l.config.cluster_size();
+            let first = l.next_block / size;
+            let count = sms.len() as u32 / size;
+            let block_idxs: Vec<Dim3> =
+                (first..first + count).flat_map(|c| cluster_blocks(c, &l.config)).collect();
+            l.next_block += count * size;
+            let (kernel, config) = (l.kernel.clone(), l.config.clone());
+
+            for &sm_id in &sms {
+                // Allocate resources on that SM
+                let l = &mut self.launches[id];
+                let allocated = self.gpu.sms[sm_id].allocate_block(&l.footprint, &self.sm_config);
+                assert!(allocated, "place_cluster picked an SM without room for the block");
+                if l.resident[sm_id] == 0 {
+                    l.busy_since[sm_id] = now;
+                }
+                l.resident[sm_id] += 1;
+                l.stats.peak_resident_blocks = l
+                    .stats
+                    .peak_resident_blocks
+                    .max(self.gpu.sms[sm_id].resource_usage.active_blocks);
+                if self.timers[sm_id].is_idle() {
+                    self.sm_busy_since[sm_id] = now;
+                }
+
+                // Write snapshot: shows this SM as active while block runs
+                self.write_snapshot(id, "running");
+
+                // Optional per-block pause for live visualisation
+                if config.block_delay_ms > 0 {
+                    std::thread::sleep(std::time::Duration::from_millis(config.block_delay_ms));
+                }
+            }
 
-                    // Execute the block
-                    let mut smem_buf = vec![0u8; config.smem_per_block.max(1) as usize];
-                    self.execute_block(kernel, config, block_idx, &mut smem_buf, &mut stats);
+            // Execute the blocks functionally, then make their warps resident
+            // on their SMs for the timing model
+            let (blocks, children) = self.execute_blocks(id, &kernel, &config, &block_idxs);
+            let start_at = now + DEVICE_LAUNCH_LATENCY_NS * self.sm_config.clock_mhz as u64 / 1000;
+            let l = &mut self.launches[id];
+            l.stats.child_launches += children.len() as u64;
+            l.pending_children += children.len() as u32;
+            self.queued_children.extend(children.into_iter().map(|(kernel, config)| {
+                QueuedChild { kernel, config, parent: id, start_at }
+            }));
 
-                    // Free resources after block completes
-                    self.gpu.sms[sm_id].free_block(
-                        config.threads_per_block(),
-                        warps,
-                        smem,
-                    );
+            let mut cluster = None;
+            for (i, (sm_id, merged)) in sms.into_iter().zip(blocks).enumerate() {
+                if size > 1 && i % size as usize == 0 {
+                    cluster = Some(self.next_cluster_id);
+                    self.next_cluster_id += 1;
+                }
+                let l = &mut self.launches[id];
+                l.stats.threads_executed += config.threads_per_block();
+                l.stats.warps_executed += merged.len() as u32;
+                for warp in &merged {
+                    l.stats.divergent_branches += warp.divergent_branches as u64;
+                    l.stats.divergent_warps += (warp.divergent_branches > 0) as u32;
+                    l.stats.warp_lane_utilization.push(warp.lane_utilization());
+                    l.active_lanes += warp.active_lanes();
+                    l.lane_instructions += warp.lane_instructions();
+                }
+                let mut warp_streams: Vec<Vec<WarpInstr>> =
+                    merged.into_iter().map(|w| w.instrs).collect();
+                analyze_atomics(&mut warp_streams, &mut l.stats, &mut l.atomic_addrs);
+                analyze_bank_conflicts(
+                    &mut warp_streams,
+                    config.smem_bank_size,
+                    &mut l.stats,
+                    &mut l.bank_sites,
+                );
+                analyze_constant_loads(&mut warp_streams, &mut l.stats);
+                for instr in warp_streams.iter().flatten().filter(|i| i.kind.is_global()) {
+                    l.stats.coalescing += coalesce(&instr.ops());
+                }
+                l.stats.dsmem_accesses += warp_streams
+                    .iter()
+                    .flatten()
+                    .filter(|i| {
+                        matches!(
+                            i.kind,
+                            InstrKind::DsmemLoad | InstrKind::DsmemStore | InstrKind::DsmemAtomic
+                        )
+                    })
+                    .map(|i| i.lanes.len() as u64)
+                    .sum::<u64>();
+                l.stats.tex_requests += warp_streams
+                    .iter()
+                    .flatten()
+                    .filter(|i| i.kind == InstrKind::TextureFetch)
+                    .count() as u64;
 
-                    stats.blocks_executed += 1;
+                let block_id = self.next_block_id;
+                self.next_block_id += 1;
+                self.block_owner.insert(block_id, id);
+                if let Some(cluster) = cluster {
+                    self.block_cluster.insert(block_id, cluster);
+                    self.clusters.entry(cluster).or_default().push(block_id);
+                }
+                let num_warps = warp_streams.len() as u64;
+                self.timers[sm_id].add_block(block_id, warp_streams, self.warp_age_counter, now);
+                self.warp_age_counter += num_warps;
+            }
+        }
+    }
+
+    /// Release the blocks waiting at a cluster barrier whose cluster has fully
+    /// arrived (a block outside any cluster is released right away), and those
+    /// waiting at a grid barrier whose whole grid has.
+    fn release_group_barriers(&mut self, now: u64) {
+        let waiting: Vec<(usize, u32, InstrKind)> = self
+            .timers
+            .iter()
+            .enumerate()
+            .flat_map(|(sm_id, t)| t.group_barrier_waiting().map(move |(b, k)| (sm_id, b, k)))
+            .collect();
+        let is_waiting = |block: u32, kind: InstrKind| {
+            waiting.iter().any(|&(_, b, k)| b == block && k == kind)
+        };
+        for &(sm_id, block, kind) in &waiting {
+            let arrived = match kind {
+                InstrKind::GridBarrier => {
+                    let launch = self.block_owner[&block];
+                    self.block_owner
+                        .iter()
+                        .filter(|&(_, &owner)| owner == launch)
+                        .all(|(&b, _)| is_waiting(b, kind))
                 }
+                _ => self.block_cluster.get(&block).is_none_or(|cluster| {
+                    self.clusters[cluster].iter().all(|&b| is_waiting(b, kind))
+                }),
+            };
+            if arrived {
+                self.timers[sm_id].release_group_barrier(block, now, &self.sm_config);
             }
         }
+    }
+
+    /// Finalize the statistics of a launch whose last block retired at `now`.
+    fn finish(&mut self, id: LaunchId, now: u64) -> ExecutionStats {
+        let l = &mut self.launches[id];
+        l.done = true;
+        let stats = &mut l.stats;
+
+        stats.end_cycle = now;
+        stats.total_cycles = now - stats.start_cycle;
+        stats.sm_busy_cycles = std::mem::take(&mut l.busy);
+        stats.sm_idle_cycles = stats
+            .sm_busy_cycles
+            .iter()
+            .map(|busy| stats.total_cycles - busy)
+            .collect();
+        let busy_total: u64 = stats.sm_busy_cycles.iter().sum();
+        if busy_total > 0 {
+            stats.achieved_occupancy =
+                l.warp_cycles as f32 / (busy_total * self.sm_config.max_warps as u64) as f32;
+        }
+        stats.wall_time_us = stats.total_cycles as f64 / self.sm_config.clock_mhz.max(1) as f64;
+        stats.atomic_hotspot = std::mem::take(&mut l.atomic_addrs)
+            .into_iter()
+            .max_by_key(|&(addr, count)| (count, usize::MAX - addr));
+        stats.simt_efficiency = if l.lane_instructions > 0 {
+            l.active_lanes as f64 / (l.lane_instructions * WARP_SIZE as u64) as f64
+        } else {
+            1.0
+        };
+        if let Some(racecheck) = l.racecheck.take() {
+            stats.race_hazards = racecheck.into_hazards();
+        }
+        let sector_size = self.gpu.l2_cache.config().sector_size as u64;
+        stats.l2_bytes = stats.l2.accesses() * sector_size;
+        stats.hbm_fill_bytes = stats.dram.reads * sector_size;
+        stats.hbm_write_bytes = stats.dram.writes * sector_size;
+        stats.hbm_bytes = stats.hbm_fill_bytes + stats.hbm_write_bytes;
+        stats.dram_channel_requests = self
+            .gpu
+            .hbm
+            .dram
+            .channel_requests()
+            .iter()
+            .zip(&l.dram_channels_at_start)
+            .map(|(now, start)| now - start)
+            .collect();
+        if stats.wall_time_us > 0.0 {
+            stats.hbm_bandwidth_gbps = stats.hbm_bytes as f64 / (stats.wall_time_us * 1e3);
+        }
+        stats.bank_conflict_sites = std::mem::take(&mut l.bank_sites).into_values().collect();
+        stats.bank_conflict_sites.sort_by_key(|s| {
+            (std::cmp::Reverse(s.conflicts), s.site.file(), s.site.line(), s.site.column())
+        });
+        if stats.total_cycles > 0 {
+            stats.atomic_throughput =
+                (stats.global_atomics + stats.shared_atomics) as f64 / stats.total_cycles as f64;
+        }
 
         println!(
             "[gpusim] Kernel '{}' complete | {} blocks | {} warps | {} threads | \
-             occupancy={:.1}%",
-            kernel.name,
+             occupancy={:.1}% | {} cycles ({:.2} µs)",
+            stats.kernel_name,
             stats.blocks_executed,
             stats.warps_executed,
             stats.threads_executed,
             stats.theoretical_occupancy * 100.0,
+            stats.total_cycles,
+            stats.wall_time_us,
         );
+        if stats.l1.accesses() > 0 {
+            println!(
+                "[gpusim] L1: {} hits, {} misses ({:.1}% hit rate)",
+                stats.l1.hits,
+                stats.l1.misses,
+                stats.l1.hit_rate() * 100.0,
+            );
+        }
+        if stats.l2.accesses() > 0 {
+            println!(
+                "[gpusim] L2: {} hits, {} misses ({:.1}% hit rate) | {} evictions",
+                stats.l2.hits,
+                stats.l2.misses,
+                stats.l2.hit_rate() * 100.0,
+                stats.l2.evictions,
+            );
+        }
+        let c = &stats.coalescing;
+        if c.requests > 0 {
+            println!(
+                "[gpusim] global: {} requests | {:.2} sectors/request (ideal {:.2}) | \
+                 {} transactions (ideal {}) | {} bytes requested, {} transferred",
+                c.requests,
+                c.sectors_per_request(),
+                c.ideal_sectors_per_request(),
+                c.transactions,
+                c.ideal_transactions,
+                c.bytes_requested,
+                c.bytes_transferred(),
+            );
+            let peak_gbps = self.gpu.hbm.bandwidth_bps(self.sm_config.clock_mhz) as f64 / 1e9;
+            println!(
+                "[gpusim] bandwidth: L2 {} bytes | HBM {} bytes ({} filled, {} written) | \
+                 {:.1} GB/s, {:.1}% of peak",
+                stats.l2_bytes,
+                stats.hbm_bytes,
+                stats.hbm_fill_bytes,
+                stats.hbm_write_bytes,
+                stats.hbm_bandwidth_gbps,
+                stats.hbm_bandwidth_gbps / peak_gbps * 100.0,
+            );
+        }
+        if stats.dram.requests() > 0 {
+            let channels = &stats.dram_channel_requests;
+            let mean = channels.iter().sum::<u64>() as f64 / channels.len().max(1) as f64;
+            let busiest = channels.iter().copied().max().unwrap_or(0);
+            println!(
+                "[gpusim] HBM: {} reads, {} writes | {:.1}% row hits, {} row conflicts | \
+                 {:.1} queue cycles/request | busiest channel {:.1}x the mean",
+                stats.dram.reads,
+                stats.dram.writes,
+                stats.dram.row_hit_rate() * 100.0,
+                stats.dram.row_conflicts,
+                stats.dram.queue_cycles_per_request(),
+                busiest as f64 / mean,
+            );
+        }
+        if stats.const_requests > 0 {
+            println!(
+                "[gpusim] constant: {} requests | {:.2} addresses/request | \
+                 {:.1}% constant cache hits",
+                stats.const_requests,
+                stats.const_passes as f64 / stats.const_requests as f64,
+                stats.const_cache.hit_rate() * 100.0,
+            );
+        }
+        if stats.tex_requests > 0 {
+            println!(
+                "[gpusim] texture: {} requests | {:.1}% texture cache hits ({} hits, {} misses)",
+                stats.tex_requests,
+                stats.tex_cache.hit_rate() * 100.0,
+                stats.tex_cache.hits,
+                stats.tex_cache.misses,
+            );
+        }
+        let um = &stats.managed;
+        if um.gpu_page_faults + um.remote_accesses > 0 {
+            println!(
+                "[gpusim] managed: {} page faults | {} bytes migrated to the device | \
+                 {} remote accesses",
+                um.gpu_page_faults,
+                um.bytes_htod,
+                um.remote_accesses,
+            );
+        }
+        if stats.smem_bank_conflicts > 0 {
+            println!(
+                "[gpusim] bank conflicts: {} over {} shared memory requests \
+                 ({:.2} wavefronts/request)",
+                stats.smem_bank_conflicts,
+                stats.smem_requests,
+                stats.smem_wavefronts as f64 / stats.smem_requests as f64,
+            );
+            let conflicting = stats.bank_conflict_sites.iter().filter(|s| s.conflicts > 0);
+            for site in conflicting.take(MAX_PRINTED_SITES) {
+                println!("[gpusim]   {}", site);
+            }
+        }
+        if self.gpu.memcheck {
+            for error in stats.memcheck_errors.iter().take(MAX_PRINTED_ERRORS) {
+                println!("[gpusim] memcheck: {}", error);
+            }
+            let hidden = stats.memcheck_errors.len().saturating_sub(MAX_PRINTED_ERRORS);
+            if hidden > 0 {
+                println!("[gpusim] memcheck: ... {} more errors", hidden);
+            }
+            println!(
+                "[gpusim] memcheck: kernel '{}': {} errors",
+                stats.kernel_name,
+                stats.memcheck_errors.len(),
+            );
+        }
+        if self.gpu.racecheck {
+            for hazard in stats.race_hazards.iter().take(MAX_PRINTED_HAZARDS) {
+                println!("[gpusim] racecheck: {}", hazard);
+            }
+            let hidden = stats.race_hazards.len().saturating_sub(MAX_PRINTED_HAZARDS);
+            if hidden > 0 {
+                println!("[gpusim] racecheck: ... {} more hazards", hidden);
+            }
+            println!(
+                "[gpusim] racecheck: kernel '{}': {} hazards",
+                stats.kernel_name,
+                stats.race_hazards.len(),
+            );
+        }
 
         // Write final "complete" snapshot
-        self.write_snapshot(kernel, config, &stats, blocks_total, "complete");
+        self.write_snapshot(id, "complete");
 
-        stats
+        self.launches[id].stats.clone()
     }
 
-    /// Snapshot current simulation state and write to the metrics file.
-    fn write_snapshot(
-        &self,
-        kernel: &Kernel,
-        config: &LaunchConfig,
-        stats: &ExecutionStats,
-        blocks_total: u32,
-        status: &str,
-    ) {
+    /// Snapshot current simulation state of launch `id` and write it to the
+    /// metrics file.
+    fn write_snapshot(&self, id: LaunchId, status: &str) {
+        let l = &self.launches[id];
+        let (config, stats) = (&l.config, &l.stats);
         let sm_active_blocks = self
             .gpu
             .sms
@@ -177,18 +916,37 @@ impl<'a> KernelExecutor<'a> {
 
         write_metrics(&LiveMetrics {
             status: status.to_string(),
-            kernel_name: kernel.name.clone(),
-            scheduling_policy: self.scheduler.name().to_string(),
+            kernel_name: l.kernel.name.clone(),
+            scheduling_policy: self.policy.name().to_string(),
             grid: [config.grid_dim.x, config.grid_dim.y, config.grid_dim.z],
             block: [config.block_dim.x, config.block_dim.y, config.block_dim.z],
             theoretical_occupancy: stats.theoretical_occupancy,
             occupancy_limiter: stats.occupancy_limiter.clone(),
             max_blocks_per_sm: stats.max_blocks_per_sm,
-            blocks_total,
+            blocks_total: l.blocks_total,
             blocks_executed: stats.blocks_executed,
             warps_executed: stats.warps_executed,
             threads_executed: stats.threads_executed,
             sm_active_blocks,
+            total_cycles: self.gpu.cycle - stats.start_cycle,
+            smem_carveout_kb: stats.smem_carveout / 1024,
+            l1_hits: stats.l1.hits,
+            l1_misses: stats.l1.misses,
+            l1_hit_rate: stats.l1.hit_rate(),
+            l2_hits: stats.l2.hits,
+            l2_misses: stats.l2.misses,
+            l2_evictions: stats.l2.evictions,
+            l2_hit_rate: stats.l2.hit_rate(),
+            smem_bank_conflicts: stats.smem_bank_conflicts,
+            sectors_per_request: stats.coalescing.sectors_per_request(),
+            hbm_bandwidth_gbps: stats.hbm_bandwidth_gbps,
+            hbm_row_hit_rate: stats.dram.row_hit_rate(),
+            const_addresses_per_request: stats.const_passes as f64
+                / stats.const_requests.max(1) as f64,
+            tex_hit_rate: stats.tex_cache.hit_rate(),
+            page_faults: stats.managed.gpu_page_faults,
+            migrated_mb: stats.managed.migrated_bytes() as f64 / 1_000_000.0,
+            last_copy: self.gpu.copies.last().map(|c| c.snapshot(self.gpu.copy_link(c.direction))),
             timestamp_ms: now_ms(),
             // Cluster fields are not set by the single-GPU executor;
             // cluster.rs enriches the snapshot after launch_kernel_on() returns.
@@ -196,79 +954,941 @@ impl<'a> KernelExecutor<'a> {
         });
     }
 
-    /// Find the SM with the most remaining block headroom (resource-availability-based
-    /// scheduling, matching empirical NVIDIA GigaThread Engine behaviour).
-    /// Ties broken by SM ID (lowest first).
-    fn find_best_sm(&self, max_blocks: u32) -> usize {
+    /// Choose SMs for the next dispatch unit of launch `id`: one cluster (a
+    /// single block without clusters), or every cluster of a cooperative
+    /// launch. Returns one SM per block, or None if the unit does not fit.
+    fn place_blocks(&mut self, id: LaunchId) -> Option<Vec<usize>> {
+        let config = &self.launches[id].config;
+        let clusters = if config.cooperative { config.num_clusters() } else { 1 };
+        let footprint = self.launches[id].footprint;
+
+        // Place the blocks one by one, holding their resources so the next
+        // block sees them, then release them again
+        let mut placed = Vec::new();
+        let fits = (0..clusters).all(|_| self.place_cluster(id, &mut placed));
+        for &sm_id in &placed {
+            self.gpu.sms[sm_id].free_block(&footprint);
+        }
+        fits.then_some(placed)
+    }
+
+    /// Tentatively place the blocks of one cluster of launch `id` (a single
+    /// block without clusters), allocating their resources and appending
+    /// their SMs to `placed`. All blocks of a cluster go to SMs of the same
+    /// GPC; GPCs with the most free block slots are tried first. Returns
+    /// false, leaving `placed` unchanged, when no GPC can take the cluster.
+    fn place_cluster(&mut self, id: LaunchId, placed: &mut Vec<usize>) -> bool {
+        let size = self.launches[id].config.cluster_size() as usize;
+        let (cap, footprint) = (self.launches[id].block_cap, self.launches[id].footprint);
+        let gpcs: Vec<Option<usize>> = if size == 1 {
+            vec![None]
+        } else {
+            let free_slots = |gpc: usize| -> u32 {
+                let sms = self.gpu.sms.iter().filter(|sm| sm.gpc == gpc);
+                sms.map(|sm| cap.saturating_sub(sm.resource_usage.active_blocks)).sum()
+            };
+            let mut gpcs: Vec<usize> = (0..self.gpu.num_gpcs()).collect();
+            gpcs.sort_by_key(|&gpc| std::cmp::Reverse(free_slots(gpc)));
+            gpcs.into_iter().map(Some).collect()
+        };
+
+        let start = placed.len();
+        for gpc in gpcs {
+            while placed.len() - start < size
+                && let Some(sm_id) = self.find_best_sm(id, gpc, placed)
+            {
+                let allocated = self.gpu.sms[sm_id].allocate_block(&footprint, &self.sm_config);
+                assert!(allocated, "find_best_sm picked an SM without room for the block");
+                placed.push(sm_id);
+            }
+            if placed.len() - start == size {
+                return true;
+            }
+            for sm_id in placed.drain(start..) {
+                self.gpu.sms[sm_id].free_block(&footprint);
+            }
+        }
+        false
+    }
+
+    /// Find the SM with the most remaining block headroom for launch `id`
+    /// (resource-availability-based scheduling, matching empirical NVIDIA
+    /// GigaThread Engine behaviour), optionally restricted to one GPC.
+    /// `placed` lists SMs already holding a tentatively placed block of the
+    /// launch. Ties broken by SM clock (the SM that freed up first), then
+    /// SM ID (lowest first).
+    /// Returns None when no SM can take another block of the launch.
+    fn find_best_sm(&self, id: LaunchId, gpc: Option<usize>, placed: &[usize]) -> Option<usize> {
+        let l = &self.launches[id];
+        let resident = |sm_id: usize| {
+            l.resident[sm_id] + placed.iter().filter(|&&p| p == sm_id).count() as u32
+        };
         self.gpu
             .sms
             .iter()
             .enumerate()
-            .filter(|(_, sm)| sm.resource_usage.active_blocks < max_blocks)
+            .filter(|(_, sm)| gpc.is_none_or(|g| sm.gpc == g))
+            .filter(|(sm_id, sm)| {
+                resident(*sm_id) < l.block_cap && sm.fits(&l.footprint, &self.sm_config)
+            })
             .max_by_key(|(id, sm)| {
-                let headroom = max_blocks.saturating_sub(sm.resource_usage.active_blocks);
-                // Primary: headroom (higher = better); secondary: lower SM ID wins ties
-                (headroom, usize::MAX - id)
+                let headroom = l.block_cap.saturating_sub(sm.resource_usage.active_blocks);
+                // Primary: headroom (higher = better); then earliest clock; then lower SM ID
+                (headroom, u64::MAX - sm.clock, usize::MAX - id)
             })
             .map(|(id, _)| id)
-            // Fallback: SM 0 (should never happen with a valid grid)
-            .unwrap_or(0)
     }
 
-    /// Execute all threads in a single thread block, using the warp scheduler
-    /// to determine warp execution order.
-    fn execute_block(
+    /// Functionally execute all threads of a dispatch unit — one cluster's
+    /// blocks (a single block without clusters), or the whole grid of a
+    /// cooperative launch — and return each block's warp instruction streams
+    /// (see simt.rs) for the timing model, along with the child grids the
+    /// threads launched. `block_idxs` lists whole clusters of launch `id`,
+    /// each in rank order. Memcheck errors go straight to the launch's stats;
+    /// racecheck state is carried over to the launch's next dispatch unit.
+    ///
+    /// Sync kernels run each thread to completion, warp by warp and block by
+    /// block. Async kernels run every thread as a task: all tasks are polled
+    /// (in block and warp order) until each has finished or parked at a sync
+    /// point. Warp-level ops whose lanes have all arrived are then evaluated
+    /// in lockstep; once none remain, the barrier of every block whose live
+    /// threads have all arrived is released, failing that the barrier of every
+    /// such cluster, and failing that the grid barrier.
+    fn execute_blocks(
         &mut self,
+        id: LaunchId,
         kernel: &Kernel,
         config: &LaunchConfig,
-        block_idx: Dim3,
-        smem: &mut Vec<u8>,
-        stats: &mut ExecutionStats,
-    ) {
+        block_idxs: &[Dim3],
+    ) -> (Vec<Vec<WarpStream>>, Vec<(Kernel, LaunchConfig)>) {
+        let depth = self.launches[id].stats.nesting_depth;
         let threads_per_block = config.threads_per_block() as usize;
-        let num_warps = threads_per_block.div_ceil(WARP_SIZE);
 
-        // Create warp slots for the scheduler, assigning ages in order
-        let warp_slots: Vec<WarpSlot> = (0..num_warps)
-            .map(|i| {
-                let age = self.warp_age_counter + i as u64;
-                WarpSlot::new(i, age)
+        let smem: Vec<RefCell<Vec<u8>>> = block_idxs
+            .iter()
+            .map(|_| RefCell::new(vec![0u8; config.smem_per_block as usize]))
+            .collect();
+        let children = ChildLaunches::new(depth, self.gpu.smallest_gpc_sms());
+        let mem = DeviceMemory {
+            hbm: RefCell::new(&mut self.gpu.hbm),
+            constant: &self.gpu.constant,
+            memcheck: self.gpu.memcheck.then(|| Memcheck::new(&self.gpu.allocator, &kernel.name)),
+            racecheck: self.launches[id].racecheck.take().map(RefCell::new),
+        };
+        // One lane state (instruction trace + sync state) per thread
+        let lanes: Vec<Vec<LaneState>> = block_idxs
+            .iter()
+            .map(|_| (0..threads_per_block).map(|_| LaneState::default()).collect())
+            .collect();
+        let cluster_size = config.cluster_size() as usize;
+        let new_ctx = |block: usize, tid: usize| {
+            let thread_idx = flat_to_dim3(tid as u32, config.block_dim);
+            let first = block / cluster_size * cluster_size;
+            let cluster_smem = &smem[first..first + cluster_size];
+            let lane = &lanes[block][tid];
+            ThreadCtx::new(thread_idx, block_idxs[block], config, cluster_smem, &mem, lane, &children)
+        };
+        // Whether every live thread in `blocks` is parked at `point`
+        let all_at = |live: &[Vec<usize>], blocks: std::ops::Range<usize>, point: SyncPoint| {
+            let mut threads = blocks.flat_map(|b| live[b].iter().map(move |&tid| (b, tid))).peekable();
+            threads.peek().is_some()
+                && threads.all(|(b, tid)| lanes[b][tid].waiting_at() == Some(point))
+        };
+
+        match &kernel.func {
+            KernelFn::Sync(func) => {
+                // Execute all 32 lanes of each warp (simulated SIMD)
+                for block in 0..block_idxs.len() {
+                    for tid in 0..threads_per_block {
+                        func(&mut new_ctx(block, tid));
+                    }
+                }
+            }
+            KernelFn::Async(func) => {
+                let mut tasks: Vec<Vec<Option<ThreadTask>>> = (0..block_idxs.len())
+                    .map(|b| (0..threads_per_block).map(|tid| Some(func(new_ctx(b, tid)))).collect())
+                    .collect();
+                let mut cx = Context::from_waker(Waker::noop());
+                loop {
+                    for (block, block_tasks) in tasks.iter_mut().enumerate() {
+                        for (tid, task) in block_tasks.iter_mut().enumerate() {
+                            if let Some(t) = task
+                                && t.as_mut().poll(&mut cx).is_ready()
+                            {
+                                *task = None;
+                                lanes[block][tid].finished.set(true);
+                            }
+                        }
+                    }
+
+                    // Live threads of each block
+                    let live: Vec<Vec<usize>> = tasks
+                        .iter()
+                        .map(|b| (0..threads_per_block).filter(|&tid| b[tid].is_some()).collect())
+                        .collect();
+                    if live.iter().all(|b| b.is_empty()) {
+                        break;
+                    }
+
+                    // Warp-level ops first: they complete without the rest of the block
+                    let mut progressed = false;
+                    for warp in lanes.iter().flat_map(|b| b.chunks(WARP_SIZE)) {
+                        progressed |= resolve_warp_ops(warp);
+                    }
+                    if progressed {
+                        continue;
+                    }
+
+                    // Release each block whose live threads are all parked at
+                    // its barrier, then each such cluster
+                    for (size, point) in
+                        [(1, SyncPoint::Barrier), (cluster_size, SyncPoint::ClusterBarrier)]
+                    {
+                        for first in (0..block_idxs.len()).step_by(size) {
+                            if all_at(&live, first..first + size, point) {
+                                for block in first..first + size {
+                                    live[block].iter().for_each(|&tid| lanes[block][tid].release());
+                                }
+                                progressed = true;
+                            }
+                        }
+                        if progressed {
+                            break;
+                        }
+                    }
+                    if progressed {
+                        continue;
+                    }
+
+                    // Every live thread of the grid is now parked at the grid barrier
+                    for (block, block_live) in live.iter().enumerate() {
+                        for &tid in block_live {
+                            assert_eq!(
+                                lanes[block][tid].waiting_at(),
+                                Some(SyncPoint::GridBarrier),
+                                "kernel '{}' deadlocked: thread {} of block ({},{},{}) cannot make progress",
+                                kernel.name,
+                                tid,
+                                block_idxs[block].x,
+                                block_idxs[block].y,
+                                block_idxs[block].z,
+                            );
+                            lanes[block][tid].release();
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(memcheck) = &mem.memcheck {
+            self.launches[id].stats.memcheck_errors.extend(memcheck.errors.take());
+        }
+        if let Some(racecheck) = &mem.racecheck {
+            let mut state = racecheck.take();
+            state.end_dispatch();
+            self.launches[id].racecheck = Some(state);
+        }
+        let blocks = lanes
+            .into_iter()
+            .map(|block| {
+                let traces: Vec<Vec<LaneOp>> =
+                    block.into_iter().map(|l| l.trace.into_inner()).collect();
+                traces.chunks(WARP_SIZE).map(merge_lanes).collect()
             })
             .collect();
-        self.warp_age_counter += num_warps as u64;
-
-        // Get execution order from the warp scheduler
-        let ordered = self.scheduler.order_warps(&warp_slots);
-
-        for warp_idx in ordered {
-            let warp_start = warp_idx * WARP_SIZE;
-            let warp_end = (warp_start + WARP_SIZE).min(threads_per_block);
-
-            // Execute all 32 lanes of the warp (simulated SIMD)
-            for lane in warp_start..warp_end {
-                let thread_idx = flat_to_dim3(lane as u32, config.block_dim);
-                let mut ctx = ThreadCtx {
-                    thread_idx,
-                    block_idx,
-                    block_dim: config.block_dim,
-                    grid_dim: config.grid_dim,
-                    smem,
-                    gmem: &mut self.gpu.hbm,
-                };
-                (kernel.func)(&mut ctx);
-                stats.threads_executed += 1;
+        (blocks, children.queued.into_inner())
+    }
+}
+
+/// Count atomics and serialize warp-level atomic instructions whose lanes
+/// target the same address.
+fn analyze_atomics(
+    warp_streams: &mut [Vec<WarpInstr>],
+    stats: &mut ExecutionStats,
+    atomic_addrs: &mut HashMap<usize, u64>,
+) {
+    for instr in warp_streams.iter_mut().flatten() {
+        match instr.kind {
+            InstrKind::GlobalAtomic => {
+                stats.global_atomics += instr.lanes.len() as u64;
+                for (_, op) in &instr.lanes {
+                    *atomic_addrs.entry(op.addr).or_default() += 1;
+                }
             }
+            InstrKind::SharedAtomic | InstrKind::DsmemAtomic => {
+                stats.shared_atomics += instr.lanes.len() as u64
+            }
+            _ => continue,
+        }
+        let (degree, conflicts) = address_contention(&instr.ops());
+        instr.replays = instr.replays.max(degree);
+        stats.atomic_conflicts += conflicts;
+    }
+}
+
+/// Count the wavefronts of shared memory instructions, per kernel and per
+/// source line, and serialize instructions with bank conflicts: one pass per
+/// wavefront.
+fn analyze_bank_conflicts(
+    warp_streams: &mut [Vec<WarpInstr>],
+    bank_size: SmemBankSize,
+    stats: &mut ExecutionStats,
+    sites: &mut HashMap<Site, BankConflictSite>,
+) {
+    for instr in warp_streams.iter_mut().flatten() {
+        if !matches!(
+            instr.kind,
+            InstrKind::SharedLoad | InstrKind::SharedStore | InstrKind::SharedAtomic
+        ) {
+            continue;
+        }
+        let (wavefronts, ideal) = bank_wavefronts(&instr.ops(), bank_size);
+        instr.replays = instr.replays.max(wavefronts);
+        let conflicts = (wavefronts - ideal) as u64;
+        stats.smem_requests += 1;
+        stats.smem_wavefronts += wavefronts as u64;
+        stats.smem_bank_conflicts += conflicts;
 
-            self.scheduler.record_issued(warp_idx);
-            stats.warps_executed += 1;
+        let site = instr.lanes[0].1.site;
+        let entry = sites.entry(site).or_insert(BankConflictSite {
+            site,
+            requests: 0,
+            wavefronts: 0,
+            conflicts: 0,
+        });
+        entry.requests += 1;
+        entry.wavefronts += wavefronts as u64;
+        entry.conflicts += conflicts;
+    }
+}
+
+/// Serialize every constant load over the distinct addresses its lanes read
+/// (see constant.rs).
+fn analyze_constant_loads(warp_streams: &mut [Vec<WarpInstr>], stats: &mut ExecutionStats) {
+    for instr in warp_streams.iter_mut().flatten() {
+        if instr.kind != InstrKind::ConstantLoad {
+            continue;
         }
+        let passes = constant_passes(&instr.ops());
+        instr.replays = instr.replays.max(passes);
+        stats.const_requests += 1;
+        stats.const_passes += passes as u64;
     }
 }
 
-/// Convert a flat thread index into a Dim3 given block dimensions.
-fn flat_to_dim3(flat: u32, block_dim: Dim3) -> Dim3 {
-    let x = flat % block_dim.x;
-    let y = (flat / block_dim.x) % block_dim.y;
-    let z = flat / (block_dim.x * block_dim.y);
+/// Block indices of cluster number `cluster` (clusters numbered x fastest
+/// over the grid), in cluster rank order.
+fn cluster_blocks(cluster: u32, config: &LaunchConfig) -> Vec<Dim3> {
+    let c = config.cluster_dim;
+    let clusters_per_grid =
+        Dim3::new(config.grid_dim.x / c.x, config.grid_dim.y / c.y, config.grid_dim.z / c.z);
+    let origin = flat_to_dim3(cluster, clusters_per_grid);
+    (0..c.size())
+        .map(|rank| {
+            let offset = flat_to_dim3(rank, c);
+            Dim3::new(
+                origin.x * c.x + offset.x,
+                origin.y * c.y + offset.y,
+                origin.z * c.z + offset.z,
+            )
+        })
+        .collect()
+}
+
+/// Convert a flat thread (or block) index into a Dim3 given the block (or grid)
+/// dimensions.
+fn flat_to_dim3(flat: u32, dim: Dim3) -> Dim3 {
+    let x = flat % dim.x;
+    let y = (flat / dim.x) % dim.y;
+    let z = flat / (dim.x * dim.y);
     Dim3::new(x, y, z)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cache::{CacheConfig, WritePolicy};
+    use crate::kernel::{LaunchError, MAX_NESTING_DEPTH};
+    use crate::memcheck::MemcheckErrorKind;
+    use crate::memory::DeviceBuffer;
+    use crate::racecheck::{HazardKind, MemorySpace};
+    use crate::texture::{FilterMode, TextureDesc, TextureObject};
+
+    fn launch(kernel: &Kernel, config: &LaunchConfig) -> ExecutionStats {
+        GPU::h100().launch_kernel(kernel, config, SchedulingPolicy::Gto)
+    }
+
+    /// Words [0, n) of HBM as u32s.
+    fn read_words(gpu: &GPU, n: usize) -> Vec<u32> {
+        gpu.hbm
+            .read(0, n * 4)
+            .chunks(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn dependent_alu_instructions_wait_for_each_other() {
+        let kernel = Kernel::new("alu", |ctx| ctx.alu(10));
+        let stats = launch(&kernel, &LaunchConfig::new(Dim3::x(1), Dim3::x(32)));
+        // 10 ALU instructions and EXIT from one warp
+        assert_eq!(stats.instructions_issued, 11);
+        // Each ALU instruction waits out the previous one's 4-cycle latency
+        assert_eq!(stats.total_cycles, 10 * 4 + 1);
+        assert_eq!(stats.sm_busy_cycles[0], stats.total_cycles);
+        assert_eq!(stats.sm_idle_cycles[1], stats.total_cycles);
+    }
+
+    #[test]
+    fn warps_issue_up_to_the_issue_width_per_cycle() {
+        let kernel = Kernel::new("alu", |ctx| ctx.alu(1));
+        // Eight warps on four schedulers: two cycles to issue each round
+        let stats = launch(&kernel, &LaunchConfig::new(Dim3::x(1), Dim3::x(256)));
+        assert_eq!(stats.instructions_issued, 16);
+        assert_eq!(stats.total_cycles, 6);
+        let wall_time_us = stats.total_cycles as f64 / 1980.0;
+        assert!((stats.wall_time_us - wall_time_us).abs() < 1e-9);
+    }
+
+    #[test]
+    fn syncthreads_orders_shared_memory_writes_before_reads() {
+        // Each thread reads the value its right neighbour wrote before the barrier
+        let rotate = Kernel::new_async("rotate", async |ctx| {
+            let t = ctx.thread_idx.x as usize;
+            let n = ctx.block_dim.x as usize;
+            ctx.smem.write(t * 4, &(t as u32).to_le_bytes());
+            ctx.syncthreads().await;
+            let v = ctx.smem.read((t + 1) % n * 4, 4);
+            ctx.gmem.write(t * 4, &v);
+        });
+        let mut gpu = GPU::h100();
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(64)).with_resources(32, 256);
+        let stats = gpu.launch_kernel(&rotate, &config, SchedulingPolicy::Gto);
+        let expected: Vec<u32> = (0..64).map(|t| (t + 1) % 64).collect();
+        assert_eq!(read_words(&gpu, 64), expected);
+        // Store, barrier, load, store and EXIT from each of two warps
+        assert_eq!(stats.instructions_issued, 10);
+    }
+
+    #[test]
+    fn warp_intrinsics_only_see_the_lanes_in_the_mask() {
+        const LOW_HALF: u32 = 0x0000_ffff;
+        let kernel = Kernel::new_async("half_warp", async |ctx| {
+            let lane = ctx.lane_id();
+            if lane >= 16 {
+                return;
+            }
+            let down = ctx.shfl_down_sync(LOW_HALF, lane, 8).await;
+            let ballot = ctx.ballot_sync(LOW_HALF, lane % 3 == 0).await;
+            let any = ctx.any_sync(LOW_HALF, lane == 5).await;
+            let all = ctx.all_sync(LOW_HALF, lane < 15).await;
+            let lane = lane as usize;
+            ctx.gmem.write(lane * 4, &down.to_le_bytes());
+            ctx.gmem.write((16 + lane) * 4, &ballot.to_le_bytes());
+            ctx.gmem.write((32 + lane) * 4, &(any as u32 | (all as u32) << 1).to_le_bytes());
+        });
+        let mut gpu = GPU::h100();
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32));
+        gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        let out = read_words(&gpu, 48);
+        // Lanes 8-15 would read lanes 16-23, outside the mask: they keep their own value
+        let down: Vec<u32> = (0..16).map(|l| if l < 8 { l + 8 } else { l }).collect();
+        assert_eq!(out[..16], down);
+        assert!(out[16..32].iter().all(|&b| b == 0b1001_0010_0100_1001));
+        // any: lane 5 set the predicate; all: lane 15 did not
+        assert!(out[32..].iter().all(|&v| v == 0b01));
+    }
+
+    #[test]
+    fn contended_atomics_all_land_and_serialize() {
+        // Every thread bumps one global counter and claims a slot in its
+        // block's shared counter; the shared ticket is stored per thread
+        let kernel = Kernel::new("count", |ctx| {
+            ctx.gmem.atomic_add(0, 1u32);
+            let ticket: u32 = ctx.smem.atomic_add(0, 1u32);
+            let slot = 4 + ctx.global_id() as usize;
+            ctx.gmem.write(slot * 4, &ticket.to_le_bytes());
+        });
+        let mut gpu = GPU::h100();
+        let config = LaunchConfig::new(Dim3::x(4), Dim3::x(256)).with_resources(32, 4);
+        let stats = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        let out = read_words(&gpu, 4 + 1024);
+        assert_eq!(out[0], 1024);
+        for block in out[4..].chunks(256) {
+            let mut tickets = block.to_vec();
+            tickets.sort_unstable();
+            assert_eq!(tickets, (0..256).collect::<Vec<u32>>());
+        }
+        assert_eq!((stats.global_atomics, stats.shared_atomics), (1024, 1024));
+        // All 32 lanes of each of 32 warps hit one address, twice over
+        assert_eq!(stats.atomic_conflicts, 2 * 32 * 31);
+        assert_eq!(stats.atomic_hotspot, Some((0, 1024)));
+        assert!(stats.atomic_throughput > 0.0);
+    }
+
+    #[test]
+    fn several_blocks_share_an_sm_and_overlap() {
+        let kernel = Kernel::new("alu", |ctx| ctx.alu(10));
+        let sms = GPU::h100().sms.len() as u32;
+        // 256-thread blocks: the 2048-thread limit allows eight per SM
+        let one = launch(&kernel, &LaunchConfig::new(Dim3::x(sms), Dim3::x(256)));
+        let eight = launch(&kernel, &LaunchConfig::new(Dim3::x(8 * sms), Dim3::x(256)));
+        assert_eq!(one.peak_resident_blocks, 1);
+        assert_eq!(eight.max_blocks_per_sm, 8);
+        assert_eq!(eight.peak_resident_blocks, 8);
+        assert_eq!(eight.blocks_executed, 8 * sms);
+        // Resident blocks hide each other's ALU latency instead of running
+        // back to back
+        assert!(eight.total_cycles < 8 * one.total_cycles);
+        assert!(eight.achieved_occupancy > one.achieved_occupancy);
+    }
+
+    #[test]
+    fn child_grid_finishes_before_its_parent() {
+        let child = Kernel::new("child", |ctx| {
+            ctx.alu(4);
+            ctx.gmem.write(4, &7u32.to_le_bytes());
+        });
+        let parent = Kernel::new("parent", move |ctx| {
+            if ctx.thread_idx.x == 0 {
+                ctx.launch(&child, &LaunchConfig::new(Dim3::x(1), Dim3::x(32))).unwrap();
+            }
+        });
+        let mut gpu = GPU::h100();
+        let stream = gpu.create_stream();
+        gpu.launch_kernel_async(&parent, &LaunchConfig::new(Dim3::x(1), Dim3::x(32)), stream);
+        let stats = gpu.synchronize();
+        assert_eq!(read_words(&gpu, 2)[1], 7);
+
+        // Completion order: the child, then the parent waiting on it
+        let names: Vec<&str> = stats.iter().map(|s| s.kernel_name.as_str()).collect();
+        assert_eq!(names, vec!["child", "parent"]);
+        let (child, parent) = (&stats[0], &stats[1]);
+        assert_eq!(parent.child_launches, 1);
+        assert_eq!((parent.nesting_depth, child.nesting_depth), (0, 1));
+        let latency = DEVICE_LAUNCH_LATENCY_NS * 1980 / 1000;
+        assert!(child.start_cycle >= parent.start_cycle + latency);
+        assert_eq!(parent.end_cycle, child.end_cycle);
+    }
+
+    fn nest() -> Kernel {
+        Kernel::new("nest", |ctx| {
+            let config = LaunchConfig::new(Dim3::x(1), Dim3::x(1));
+            if ctx.nesting_depth() < MAX_NESTING_DEPTH {
+                ctx.launch(&nest(), &config).unwrap();
+            } else if ctx.launch(&nest(), &config).is_err() {
+                ctx.gmem.write(0, &1u32.to_le_bytes());
+            }
+        })
+    }
+
+    #[test]
+    fn launches_past_the_nesting_limit_are_rejected() {
+        let mut gpu = GPU::h100();
+        let stream = gpu.create_stream();
+        gpu.launch_kernel_async(&nest(), &LaunchConfig::new(Dim3::x(1), Dim3::x(1)), stream);
+        let stats = gpu.synchronize();
+        assert_eq!(stats.len(), MAX_NESTING_DEPTH as usize + 1);
+        assert_eq!(read_words(&gpu, 1)[0], 1);
+        // The rejected launch issues no launch instruction: only the store
+        // and EXIT
+        let deepest = &stats[0];
+        assert_eq!(deepest.nesting_depth, MAX_NESTING_DEPTH);
+        assert_eq!((deepest.child_launches, deepest.instructions_issued), (0, 2));
+        assert_eq!(stats[1].child_launches, 1);
+    }
+
+    #[test]
+    fn cluster_blocks_read_each_others_shared_memory() {
+        let kernel = Kernel::new_async("swap", async |ctx| {
+            let (tid, rank) = (ctx.thread_idx.x, ctx.cluster_block_rank());
+            ctx.smem.write(tid as usize * 4, &(rank * 100 + tid).to_le_bytes());
+            ctx.cluster_sync().await;
+            let partner = ctx.cluster_smem(1 - rank).read(tid as usize * 4, 4);
+            ctx.gmem.write(ctx.global_id() as usize * 4, &partner);
+        });
+        let config = LaunchConfig::new(Dim3::x(4), Dim3::x(32))
+            .with_resources(32, 128)
+            .with_cluster(Dim3::x(2));
+        let mut gpu = GPU::h100();
+        let stats = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        let out = read_words(&gpu, 4 * 32);
+        for (i, &v) in out.iter().enumerate() {
+            let partner_rank = 1 - (i as u32 / 32) % 2;
+            assert_eq!(v, partner_rank * 100 + i as u32 % 32);
+        }
+        assert_eq!(stats.cluster_size, 2);
+        assert_eq!(stats.dsmem_accesses, 4 * 32);
+    }
+
+    #[test]
+    fn child_launches_with_bad_clusters_are_rejected() {
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let seen = errors.clone();
+        let child = Kernel::new("child", |_| {});
+        let parent = Kernel::new("parent", move |ctx| {
+            if ctx.thread_idx.x == 0 {
+                let block = Dim3::x(32);
+                let too_big = LaunchConfig::new(Dim3::x(16), block).with_cluster(Dim3::x(16));
+                let ragged = LaunchConfig::new(Dim3::x(3), block).with_cluster(Dim3::x(2));
+                for config in [too_big, ragged] {
+                    seen.borrow_mut().push(ctx.launch(&child, &config).unwrap_err());
+                }
+            }
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32));
+        let stats = launch(&parent, &config);
+        assert_eq!(
+            *errors.borrow(),
+            vec![
+                LaunchError::ClusterTooLarge { blocks: 16 },
+                LaunchError::GridNotClusterMultiple { grid: Dim3::x(3), cluster: Dim3::x(2) },
+            ]
+        );
+        assert_eq!(stats.child_launches, 0);
+        let wide = LaunchConfig::new(Dim3::x(8), Dim3::x(32)).with_cluster(Dim3::x(8));
+        let err = LaunchError::ClusterExceedsGpc { blocks: 8, gpc_sms: 4 };
+        assert_eq!(wide.check_clusters(4), Err(err));
+        assert_eq!(wide.check_clusters(16), Ok(()));
+    }
+
+    #[test]
+    fn grid_sync_orders_writes_across_blocks() {
+        let mut gpu = GPU::h100();
+        // Twice as many blocks as SMs, so blocks share SMs
+        let blocks = 2 * gpu.sms.len() as u32;
+        let kernel = Kernel::new_async("rotate", async move |ctx| {
+            let b = ctx.block_idx.x;
+            if ctx.thread_idx.x == 0 {
+                ctx.gmem.write(b as usize * 4, &(b + 1).to_le_bytes());
+            }
+            ctx.grid_sync().await;
+            if ctx.thread_idx.x == 0 {
+                let next = ctx.gmem.read(((b + 1) % blocks) as usize * 4, 4);
+                ctx.gmem.write((blocks + b) as usize * 4, &next);
+            }
+        });
+        let config = LaunchConfig::new(Dim3::x(blocks), Dim3::x(64));
+        let stats = gpu.launch_cooperative_kernel(&kernel, &config, SchedulingPolicy::Gto).unwrap();
+        let out = read_words(&gpu, 2 * blocks as usize);
+        for b in 0..blocks {
+            assert_eq!(out[(blocks + b) as usize], (b + 1) % blocks + 1);
+        }
+        assert_eq!(stats.blocks_executed, blocks);
+        assert_eq!(stats.peak_resident_blocks, 2);
+    }
+
+    #[test]
+    fn cooperative_grids_must_fit_on_the_gpu_at_once() {
+        let mut gpu = GPU::h100();
+        let kernel = Kernel::new_async("coop", async |ctx| ctx.grid_sync().await);
+        let block = Dim3::x(1024);
+        let max_resident = gpu.max_cooperative_blocks(&LaunchConfig::new(Dim3::x(1), block));
+        // Two 1024-thread blocks fill an SM's 2048 threads
+        assert_eq!(max_resident, 2 * gpu.sms.len() as u32);
+
+        let fits = LaunchConfig::new(Dim3::x(max_resident), block);
+        assert!(gpu.launch_cooperative_kernel(&kernel, &fits, SchedulingPolicy::Gto).is_ok());
+        let too_large = LaunchConfig::new(Dim3::x(max_resident + 1), block);
+        let result = gpu.launch_cooperative_kernel(&kernel, &too_large, SchedulingPolicy::Gto);
+        let blocks = max_resident + 1;
+        assert_eq!(
+            result.err(),
+            Some(LaunchError::CooperativeLaunchTooLarge { blocks, max_resident })
+        );
+    }
+
+    #[test]
+    fn two_dimensional_grids_index_row_major() {
+        // An 8x4 grid of 4x2 blocks covering a 32x8 array of u32
+        let out: DeviceBuffer<u32> = DeviceBuffer::new(0, 32 * 8);
+        let kernel = Kernel::new("iota2d", move |ctx| {
+            let (idx, dim) = (ctx.global_idx(), ctx.global_dim());
+            assert_eq!((dim.x, dim.y, dim.z), (32, 8, 1));
+            let id = ctx.global_linear_id();
+            // Stage the value through shared memory with typed accessors
+            let slot = ctx.thread_rank() as usize * 8;
+            ctx.smem.store(slot, id);
+            let id: u64 = ctx.smem.load(slot);
+            ctx.gmem.store(out.at((idx.y * dim.x + idx.x) as usize), id as u32);
+        });
+        let config =
+            LaunchConfig::new(Dim3::new(8, 4, 1), Dim3::new(4, 2, 1)).with_resources(32, 64);
+        let mut gpu = GPU::h100();
+        gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        assert_eq!(out.copy_to_host(&mut gpu), (0..32 * 8).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn memcheck_reports_stores_past_the_end_of_a_buffer() {
+        let mut gpu = GPU::h100();
+        gpu.memcheck = true;
+        let buf = gpu.alloc_buffer::<u32>(40).unwrap();
+        let kernel = Kernel::new("overrun", move |ctx| {
+            let i = ctx.global_id() as usize;
+            ctx.gmem.store(buf.addr() + i * 4, i as u32);
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(64));
+        let stats = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        // Threads 40..64 write past the 160-byte allocation
+        assert_eq!(stats.memcheck_errors.len(), 24);
+        let first = &stats.memcheck_errors[0];
+        assert_eq!((first.addr, first.thread_idx.x), (buf.addr() + 160, 40));
+        let nearest = Some((buf.addr(), 160));
+        for e in &stats.memcheck_errors {
+            assert_eq!(e.kind, MemcheckErrorKind::OutOfBounds { nearest });
+            assert_eq!(e.access, InstrKind::GlobalStore);
+        }
+        // The accesses still went ahead
+        assert_eq!(read_words(&gpu, 64)[63], 63);
+    }
+
+    fn neighbour_exchange(barrier: bool) -> Kernel {
+        Kernel::new_async("exchange", async move |ctx| {
+            let t = ctx.thread_idx.x as usize;
+            ctx.smem.store(t * 4, t as u32);
+            if barrier {
+                ctx.syncthreads().await;
+            }
+            let v: u32 = ctx.smem.load((t + 1) % 64 * 4);
+            ctx.gmem.store(t * 4, v);
+        })
+    }
+
+    #[test]
+    fn racecheck_reports_shared_memory_hazards_without_a_barrier() {
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(64)).with_resources(32, 256);
+        let mut gpu = GPU::h100();
+        gpu.racecheck = true;
+        let racy = gpu.launch_kernel(&neighbour_exchange(false), &config, SchedulingPolicy::Gto);
+        let kinds: Vec<HazardKind> = racy.race_hazards.iter().map(|h| h.kind).collect();
+        // Lane 31 reads word 32 before warp 1 writes it; lane 63 reads word 0
+        // after warp 0 wrote it
+        assert!(kinds.contains(&HazardKind::WriteAfterRead));
+        assert!(kinds.contains(&HazardKind::ReadAfterWrite));
+        assert!(racy.race_hazards.iter().all(|h| h.space == MemorySpace::Shared));
+
+        let synced = gpu.launch_kernel(&neighbour_exchange(true), &config, SchedulingPolicy::Gto);
+        assert!(synced.race_hazards.is_empty());
+    }
+
+    #[test]
+    fn a_second_pass_over_cached_data_hits_in_l2() {
+        // One warp loads 128 consecutive bytes: four sectors of one line
+        let kernel = Kernel::new("load", |ctx| {
+            let _: u32 = ctx.gmem.load(ctx.thread_rank() as usize * 4);
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32));
+        let mut gpu = GPU::h100();
+        let cold = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        assert_eq!((cold.l2.hits, cold.l2.misses), (0, 4));
+        let warm = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        assert_eq!((warm.l2.hits, warm.l2.misses), (4, 0));
+        assert!(warm.total_cycles < cold.total_cycles);
+
+        gpu.l2_cache.invalidate();
+        let again = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        assert_eq!(again.l2.misses, 4);
+        // The DRAM row the first launch opened is still open: slower than an
+        // L2 hit, faster than the first miss
+        assert!(warm.total_cycles < again.total_cycles && again.total_cycles < cold.total_cycles);
+    }
+
+    #[test]
+    fn repeated_loads_hit_in_the_sms_l1() {
+        let kernel = Kernel::new("load_twice", |ctx| {
+            let addr = ctx.thread_rank() as usize * 4;
+            let _: u32 = ctx.gmem.load(addr);
+            let _: u32 = ctx.gmem.load(addr);
+        });
+        let stats = launch(&kernel, &LaunchConfig::new(Dim3::x(1), Dim3::x(32)));
+        // The second load finds the four sectors the first one filled
+        assert_eq!((stats.l1.hits, stats.l1.misses), (4, 4));
+        assert_eq!(stats.l2.accesses(), 4);
+    }
+
+    #[test]
+    fn the_carveout_fits_the_launch_and_honours_a_preference() {
+        let kernel = Kernel::new("alu", |ctx| ctx.alu(1));
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(256));
+        // Without shared memory the whole unified array is L1
+        assert_eq!(launch(&kernel, &config).smem_carveout, 0);
+        // Full occupancy is eight blocks; eight blocks of 6KB fit in the
+        // 64KB split
+        let stats = launch(&kernel, &config.clone().with_resources(0, 6 * 1024));
+        assert_eq!(stats.smem_carveout, 64 * 1024);
+        // Half of 228KB rounds up to the 132KB split
+        let stats = launch(&kernel, &config.with_smem_carveout(50));
+        assert_eq!(stats.smem_carveout, 132 * 1024);
+    }
+
+    /// One warp reading a column of a 32-row float tile with rows `row_bytes`
+    /// apart.
+    fn column_read(row_bytes: usize) -> Kernel {
+        Kernel::new("column", move |ctx| {
+            let _: f32 = ctx.smem.load(ctx.thread_rank() as usize * row_bytes);
+        })
+    }
+
+    #[test]
+    fn bank_conflicts_are_counted_per_source_line_and_serialize() {
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32)).with_resources(32, 33 * 128);
+        // Rows of 32 floats put the whole column in bank 0
+        let column = launch(&column_read(128), &config);
+        assert_eq!((column.smem_requests, column.smem_wavefronts), (1, 32));
+        assert_eq!(column.smem_bank_conflicts, 31);
+        let site = &column.bank_conflict_sites[0];
+        assert_eq!((site.site.file(), site.conflicts), (file!(), 31));
+
+        // Padding each row by one float removes the conflicts
+        let padded = launch(&column_read(132), &config);
+        assert_eq!(padded.smem_bank_conflicts, 0);
+        assert!(padded.total_cycles < column.total_cycles);
+    }
+
+    /// Launch a kernel whose `n` threads each store one f32 to a fresh buffer.
+    fn store_only(gpu: &mut GPU, n: usize) -> ExecutionStats {
+        let out = gpu.alloc_buffer::<f32>(n).unwrap();
+        let kernel = Kernel::new("store_only", move |ctx| {
+            let i = ctx.global_id() as usize;
+            ctx.gmem.store(out.at(i), i as f32);
+        });
+        let config = LaunchConfig::new(Dim3::x(n as u32 / 256), Dim3::x(256));
+        gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto)
+    }
+
+    #[test]
+    fn write_through_l2_writes_each_stored_sector_once() {
+        let l2 = CacheConfig { write_policy: WritePolicy::WriteThrough, ..CacheConfig::h100_l2() };
+        let mut gpu = GPU::h100().with_l2(l2);
+        let n = 16 * 1024;
+        let stats = store_only(&mut gpu, n);
+        assert_eq!(stats.hbm_fill_bytes, 0);
+        assert_eq!(stats.hbm_write_bytes, (n * 4) as u64);
+        assert_eq!(stats.hbm_bytes, (n * 4) as u64);
+    }
+
+    #[test]
+    fn write_back_l2_writes_dirty_sectors_back_once() {
+        // A 16 KB L2 keeps only the last lines written; the rest are
+        // evicted and written back exactly once, with no fills
+        let l2_bytes = 16 * 1024;
+        let mut gpu =
+            GPU::h100().with_l2(CacheConfig { size_bytes: l2_bytes, ..CacheConfig::h100_l2() });
+        let n = 64 * 1024;
+        let stats = store_only(&mut gpu, n);
+        let footprint = (n * 4) as u64;
+        assert_eq!(stats.hbm_fill_bytes, 0);
+        assert!(stats.hbm_write_bytes <= footprint);
+        assert!(stats.hbm_write_bytes >= footprint - l2_bytes as u64);
+    }
+
+    #[test]
+    fn write_back_l2_keeps_a_small_footprint() {
+        let mut gpu = GPU::h100();
+        let stats = store_only(&mut gpu, 16 * 1024);
+        assert_eq!(stats.hbm_bytes, 0);
+        assert_eq!(stats.l2.misses, 16 * 1024 * 4 / 32);
+    }
+
+    #[test]
+    fn loads_fill_every_sector_they_touch() {
+        let mut gpu = GPU::h100();
+        let buf = gpu.alloc_buffer::<f32>(32 * 256).unwrap();
+        // Each lane loads a float from its own 128-byte line
+        let kernel = Kernel::new("strided", move |ctx| {
+            let _: f32 = ctx.gmem.load(buf.at(ctx.global_id() as usize * 32));
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(256));
+        let stats = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        assert_eq!(stats.coalescing.requests, 8);
+        assert_eq!(stats.coalescing.sectors_per_request(), 32.0);
+        assert_eq!(stats.l2_bytes, 256 * 32);
+        assert_eq!((stats.hbm_fill_bytes, stats.hbm_write_bytes), (256 * 32, 0));
+    }
+
+    /// Each warp loads 128 bytes `chunks` 256-byte chunks after the previous
+    /// warp's.
+    fn warp_stride_loads(chunks: usize) -> ExecutionStats {
+        let mut gpu = GPU::h100();
+        let buf = gpu.alloc_buffer::<f32>(32 * chunks * 64).unwrap();
+        let kernel = Kernel::new("warp_stride", move |ctx| {
+            let (warp, lane) = (ctx.global_id() as usize / 32, ctx.global_id() as usize % 32);
+            let _: f32 = ctx.gmem.load(buf.at(warp * chunks * 64 + lane));
+        });
+        let config = LaunchConfig::new(Dim3::x(4), Dim3::x(256));
+        gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto)
+    }
+
+    #[test]
+    fn misses_on_one_dram_channel_queue_behind_each_other() {
+        // 160 channels interleaved every 256 bytes: every warp on channel 0
+        let camping = warp_stride_loads(160);
+        let busiest = camping.dram_channel_requests.iter().max().copied();
+        assert_eq!(busiest, Some(camping.dram.requests()));
+        assert_eq!(camping.dram.requests(), 32 * 4);
+        assert!(camping.dram.queue_cycles > 0);
+
+        let spread = warp_stride_loads(161);
+        assert_eq!(spread.dram.requests(), 32 * 4);
+        assert!(spread.dram.queue_cycles < camping.dram.queue_cycles);
+        assert!(spread.total_cycles < camping.total_cycles);
+    }
+
+    #[test]
+    fn constant_loads_take_a_pass_per_distinct_address() {
+        let run = |distinct: u32| {
+            let mut gpu = GPU::h100();
+            gpu.write_constant(0, &(0..32u32).collect::<Vec<_>>());
+            let kernel = Kernel::new("cmem", move |ctx| {
+                let lane = ctx.thread_rank() % distinct;
+                let v: u32 = ctx.cmem.load(lane as usize * 4);
+                assert_eq!(v, lane);
+            });
+            let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32));
+            gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto)
+        };
+        let mut previous = 0;
+        for distinct in [1, 4, 32] {
+            let stats = run(distinct);
+            assert_eq!((stats.const_requests, stats.const_passes), (1, distinct as u64));
+            // Cold misses on the 64-byte lines read: the second only with 32 words
+            assert_eq!(stats.const_cache.misses, if distinct == 32 { 2 } else { 1 });
+            assert!(stats.total_cycles > previous);
+            previous = stats.total_cycles;
+        }
+    }
+
+    #[test]
+    fn linear_texture_fetches_interpolate_and_hit_the_texture_cache() {
+        let mut gpu = GPU::h100();
+        let (w, h) = (32, 8);
+        let texels = gpu.alloc_buffer::<f32>(w * h).unwrap();
+        texels.copy_from_host(&mut gpu, &(0..w * h).map(|i| i as f32).collect::<Vec<_>>());
+        let desc = TextureDesc { filter_mode: FilterMode::Linear, ..TextureDesc::default() };
+        let tex = TextureObject::new_2d(texels, w, h, desc);
+        let out = gpu.alloc_buffer::<f32>(w * h).unwrap();
+        let kernel = Kernel::new("sample", move |ctx| {
+            let (x, y) = (ctx.thread_idx.x as usize, ctx.thread_idx.y as usize);
+            // Halfway between texel x and x + 1 of row y, twice
+            let first = ctx.tex2d(&tex, x as f32 + 1.0, y as f32 + 0.5);
+            let again = ctx.tex2d(&tex, x as f32 + 1.0, y as f32 + 0.5);
+            assert_eq!(first, again);
+            ctx.gmem.store(out.at(y * w + x), first);
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::new(w as u32, h as u32, 1));
+        let stats = gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        let sampled = out.copy_to_host(&mut gpu);
+        assert_eq!(sampled[0], 0.5);
+        assert_eq!(sampled[3 * w + 10], (3 * w + 10) as f32 + 0.5);
+        // The last column clamps to the edge texel
+        assert_eq!(sampled[w - 1], (w - 1) as f32);
+
+        // One warp per row, two fetches, each reading two rows of texels
+        assert_eq!(stats.tex_requests, 2 * 2 * h as u64);
+        // The second fetch of every warp finds the texels the first loaded
+        assert!(stats.tex_cache.hits >= stats.tex_cache.misses);
+    }
+}
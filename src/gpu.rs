@@ -1,12 +1,21 @@
 /// Top-level GPU simulator.
 /// Models a GPU as a collection of Streaming Multiprocessors (SMs)
 /// connected to a shared memory hierarchy (L2 cache + HBM).
+use crate::allocator::{AllocError, DeviceAllocator, MemInfo, DEFAULT_ALIGNMENT};
+use crate::cache::CacheConfig;
+use crate::constant::ConstantMemory;
+use crate::dram::{Dram, DramConfig};
 use crate::executor::{ExecutionStats, KernelExecutor};
-use crate::kernel::{Kernel, LaunchConfig};
-use crate::memory::{HBM, L2Cache};
-use crate::occupancy::SmConfig;
+use crate::graph::{Graph, GraphExec};
+use crate::kernel::{Kernel, LaunchConfig, LaunchError};
+use crate::managed::{ManagedBuffer, ManagedMemory, ManagedStats, MemAdvice, MemLocation};
+use crate::memory::{decode_slice, encode_slice, DeviceBuffer, DeviceValue, HBM, L2Cache};
+use crate::metrics::{now_ms, read_metrics, write_metrics};
+use crate::occupancy::{max_blocks_per_sm, smem_carveout, KernelResources, SmConfig};
+use crate::pcie::{copy_cycles, CopyDirection, CopyEngines, CopyStats, PcieConfig};
 use crate::scheduler::SchedulingPolicy;
 use crate::sm::StreamingMultiprocessor;
+use crate::stream::{self, Event, HostBuffer, Stream, StreamTable};
 
 pub struct GPU {
     /// All SMs on the GPU
@@ -15,8 +24,32 @@ pub struct GPU {
     pub l2_cache: L2Cache,
     /// High Bandwidth Memory (main GPU memory)
     pub hbm: HBM,
+    /// Allocator handing out ranges of HBM (see allocator.rs)
+    pub allocator: DeviceAllocator,
     /// SM hardware configuration (used for occupancy calculations)
     pub sm_config: SmConfig,
+    /// Device clock: cycles simulated since the GPU was created
+    pub cycle: u64,
+    /// Warp scheduling policy for work submitted to streams
+    pub scheduling_policy: SchedulingPolicy,
+    /// Streams and events (see stream.rs)
+    pub streams: StreamTable,
+    /// Check every global memory access of later launches for out-of-bounds,
+    /// use-after-free, misaligned and uninitialized accesses (see memcheck.rs)
+    pub memcheck: bool,
+    /// Check shared and global memory accesses of later launches for data
+    /// races between unsynchronized threads (see racecheck.rs)
+    pub racecheck: bool,
+    /// Host link carrying host–device copies (see pcie.rs)
+    pub pcie: PcieConfig,
+    /// Copies that can run at the same time
+    pub copy_engines: usize,
+    /// Every completed copy, in completion order
+    pub copies: Vec<CopyStats>,
+    /// Page residency of managed allocations (see managed.rs)
+    pub managed: ManagedMemory,
+    /// Constant memory bank (see constant.rs)
+    pub constant: ConstantMemory,
 }
 
 impl GPU {
@@ -27,14 +60,25 @@ impl GPU {
         sm_config: SmConfig,
     ) -> Self {
         let sms = (0..num_sms)
-            .map(|id| StreamingMultiprocessor::new(id))
+            .map(|id| StreamingMultiprocessor::new(id, &sm_config))
             .collect();
 
         GPU {
             sms,
             l2_cache: L2Cache::new(l2_size_bytes),
             hbm: HBM::new(hbm_size_bytes),
+            allocator: DeviceAllocator::new(hbm_size_bytes),
             sm_config,
+            cycle: 0,
+            scheduling_policy: SchedulingPolicy::Gto,
+            streams: StreamTable::default(),
+            memcheck: false,
+            racecheck: false,
+            pcie: PcieConfig::gen5_x16(),
+            copy_engines: 2,
+            copies: Vec::new(),
+            managed: ManagedMemory::new(PcieConfig::gen5_x16()),
+            constant: ConstantMemory::new(),
         }
     }
 
@@ -46,9 +90,64 @@ impl GPU {
             80 * 1024 * 1024 * 1024, // 80GB HBM
             SmConfig::h100(),
         )
+        .with_gpcs(8)
     }
 
-    /// Launch a kernel with the given scheduling policy.
+    /// Split the SMs into `num_gpcs` graphics processing clusters of
+    /// consecutive SM ids (as evenly as possible). A new GPU has one GPC.
+    pub fn with_gpcs(mut self, num_gpcs: usize) -> Self {
+        let (num_sms, num_gpcs) = (self.sms.len(), num_gpcs.max(1));
+        for sm in &mut self.sms {
+            sm.gpc = sm.id * num_gpcs / num_sms;
+        }
+        self
+    }
+
+    /// Replace the L2 with one of the given geometry and policies (see
+    /// cache.rs). `GPU::new` builds an H100-style L2 of `l2_size_bytes`.
+    pub fn with_l2(mut self, config: CacheConfig) -> Self {
+        self.l2_cache = L2Cache::with_config(config);
+        self
+    }
+
+    /// Replace the HBM's channel and bank timing (see dram.rs). `GPU::new`
+    /// uses `DramConfig::h100()`.
+    pub fn with_dram(mut self, config: DramConfig) -> Self {
+        self.hbm.dram = Dram::new(config);
+        self
+    }
+
+    /// Replace the host link (see pcie.rs). `GPU::new` uses PCIe Gen5 x16.
+    pub fn with_pcie(mut self, config: PcieConfig) -> Self {
+        self.managed.set_link(config.clone());
+        self.pcie = config;
+        self
+    }
+
+    /// Set the number of copy engines; `GPU::new` has two, so an upload and
+    /// a download can overlap.
+    pub fn with_copy_engines(mut self, engines: usize) -> Self {
+        self.copy_engines = engines.max(1);
+        self
+    }
+
+    /// Number of graphics processing clusters (GPCs)
+    pub fn num_gpcs(&self) -> usize {
+        self.sms.iter().map(|sm| sm.gpc + 1).max().unwrap_or(0)
+    }
+
+    /// SM count of the smallest GPC, i.e. the largest cluster that can be
+    /// placed on any GPC
+    pub fn smallest_gpc_sms(&self) -> u32 {
+        (0..self.num_gpcs())
+            .map(|gpc| self.sms.iter().filter(|sm| sm.gpc == gpc).count() as u32)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Launch a kernel with the given scheduling policy and run it to
+    /// completion, with the whole GPU to itself. Work queued on streams is not
+    /// affected — it runs at the next `synchronize()`.
     pub fn launch_kernel(
         &mut self,
         kernel: &Kernel,
@@ -59,4 +158,333 @@ impl GPU {
         let mut executor = KernelExecutor::new(self, policy, sm_config);
         executor.launch(kernel, config)
     }
+
+    /// Launch a kernel cooperatively (cudaLaunchCooperativeKernel): every
+    /// block is resident at the same time, so threads can synchronize across
+    /// the whole grid with `ctx.grid_sync().await`. Fails if the grid has more
+    /// blocks than `max_cooperative_blocks`.
+    pub fn launch_cooperative_kernel(
+        &mut self,
+        kernel: &Kernel,
+        config: &LaunchConfig,
+        policy: SchedulingPolicy,
+    ) -> Result<ExecutionStats, LaunchError> {
+        let max_resident = self.max_cooperative_blocks(config);
+        if config.num_blocks() > max_resident {
+            return Err(LaunchError::CooperativeLaunchTooLarge {
+                blocks: config.num_blocks(),
+                max_resident,
+            });
+        }
+        let config = LaunchConfig { cooperative: true, ..config.clone() };
+        Ok(self.launch_kernel(kernel, &config, policy))
+    }
+
+    /// Most blocks of a launch with `config` that can be resident at once:
+    /// `max_blocks_per_sm` × SM count.
+    pub fn max_cooperative_blocks(&self, config: &LaunchConfig) -> u32 {
+        let kernel_res = KernelResources {
+            threads_per_block: config.threads_per_block(),
+            regs_per_thread: config.regs_per_thread,
+            smem_per_block: config.smem_per_block,
+        };
+        let carveout = smem_carveout(&kernel_res, &self.sm_config, config.smem_carveout);
+        let (per_sm, _) =
+            max_blocks_per_sm(&kernel_res, &self.sm_config.with_smem_carveout(carveout));
+        per_sm * self.sms.len() as u32
+    }
+
+    // -----------------------------------------------------------------------
+    // Device memory
+    // -----------------------------------------------------------------------
+
+    /// Allocate `size` bytes of device memory (cudaMalloc), aligned to 256
+    /// bytes. Returns the device address.
+    pub fn malloc(&mut self, size: usize) -> Result<usize, AllocError> {
+        self.allocator.alloc(size, DEFAULT_ALIGNMENT)
+    }
+
+    /// Allocate `size` bytes starting at a multiple of `align` (a power of two).
+    pub fn malloc_aligned(&mut self, size: usize, align: usize) -> Result<usize, AllocError> {
+        self.allocator.alloc(size, align)
+    }
+
+    /// Allocate a typed buffer of `len` elements.
+    pub fn alloc_buffer<T: DeviceValue>(&mut self, len: usize) -> Result<DeviceBuffer<T>, AllocError> {
+        let addr = self.malloc(len * T::SIZE)?;
+        Ok(DeviceBuffer::new(addr, len))
+    }
+
+    /// Free the allocation starting at `addr` (cudaFree). Its contents are
+    /// discarded: the memory reads as uninitialized once allocated again.
+    pub fn free(&mut self, addr: usize) -> Result<(), AllocError> {
+        let size = self.allocator.allocation_size(addr).ok_or(AllocError::InvalidFree { addr })?;
+        self.allocator.free(addr)?;
+        self.hbm.discard(addr, size);
+        self.managed.unregister(addr);
+        Ok(())
+    }
+
+    /// Device memory usage, peak usage and fragmentation (cudaMemGetInfo).
+    pub fn mem_info(&self) -> MemInfo {
+        self.allocator.info()
+    }
+
+    // -----------------------------------------------------------------------
+    // Constant memory
+    // -----------------------------------------------------------------------
+
+    /// Copy `values` into constant memory at byte `offset`
+    /// (cudaMemcpyToSymbol). Every SM's constant cache is invalidated. Panics
+    /// if the values do not fit in the 64 KB bank.
+    pub fn write_constant<T: DeviceValue>(&mut self, offset: usize, values: &[T]) {
+        self.constant.write(offset, &encode_slice(values));
+        for sm in &mut self.sms {
+            sm.const_cache.invalidate();
+        }
+    }
+
+    /// Read `len` `T`s from constant memory at byte `offset`
+    /// (cudaMemcpyFromSymbol).
+    pub fn read_constant<T: DeviceValue>(&self, offset: usize, len: usize) -> Vec<T> {
+        decode_slice(self.constant.read(offset, len * T::SIZE))
+    }
+
+    // -----------------------------------------------------------------------
+    // Managed memory
+    // -----------------------------------------------------------------------
+
+    /// Allocate `size` bytes of managed memory (cudaMallocManaged), shared by
+    /// the host and the device. Pages migrate on first touch (see
+    /// managed.rs); free it with `free`.
+    pub fn malloc_managed(&mut self, size: usize) -> Result<usize, AllocError> {
+        let addr = self.allocator.alloc(size, DEFAULT_ALIGNMENT)?;
+        self.managed.register(addr, size);
+        Ok(addr)
+    }
+
+    /// Allocate a typed managed buffer of `len` elements.
+    pub fn alloc_managed<T: DeviceValue>(&mut self, len: usize) -> Result<ManagedBuffer<T>, AllocError> {
+        let addr = self.malloc_managed(len * T::SIZE)?;
+        Ok(ManagedBuffer::new(addr, len))
+    }
+
+    /// Give the driver a usage hint for `len` bytes of managed memory at
+    /// `addr` (cudaMemAdvise).
+    pub fn mem_advise(&mut self, addr: usize, len: usize, advice: MemAdvice) -> Result<(), AllocError> {
+        match self.managed.advise(addr, len, advice) {
+            true => Ok(()),
+            false => Err(AllocError::NotManaged { addr, len }),
+        }
+    }
+
+    /// Migrate `len` bytes of managed memory at `addr` to `location` now, as
+    /// one copy over the host link, advancing the device clock by its
+    /// duration. Pages already there are not moved.
+    pub fn mem_prefetch(
+        &mut self,
+        addr: usize,
+        len: usize,
+        location: MemLocation,
+    ) -> Result<CopyStats, AllocError> {
+        let bytes = self
+            .managed
+            .prefetch(addr, len, location)
+            .ok_or(AllocError::NotManaged { addr, len })?;
+        Ok(self.copy_now(location.copy_direction(), bytes, true))
+    }
+
+    /// Queue a migration of `len` bytes of managed memory at `addr` to
+    /// `location` (cudaMemPrefetchAsync).
+    pub fn mem_prefetch_async(
+        &mut self,
+        addr: usize,
+        len: usize,
+        location: MemLocation,
+        stream: Stream,
+    ) -> Result<(), AllocError> {
+        if !self.managed.contains(addr, len) {
+            return Err(AllocError::NotManaged { addr, len });
+        }
+        self.streams.mem_prefetch(addr, len, location, stream);
+        Ok(())
+    }
+
+    /// Page faults, migrations and remote accesses of managed memory since
+    /// the GPU was created
+    pub fn managed_stats(&self) -> ManagedStats {
+        self.managed.stats()
+    }
+
+    /// Fault in `len` bytes of managed memory at `addr` for a host access,
+    /// advancing the device clock while the host waits.
+    pub(crate) fn managed_host_access(&mut self, addr: usize, len: usize, write: bool) {
+        let clock_mhz = self.sm_config.clock_mhz;
+        self.cycle = self.managed.host_access(addr, len, write, self.cycle, clock_mhz);
+    }
+
+    // -----------------------------------------------------------------------
+    // Streams and events
+    // -----------------------------------------------------------------------
+
+    pub fn create_stream(&mut self) -> Stream {
+        self.streams.create_stream()
+    }
+
+    pub fn create_event(&mut self) -> Event {
+        self.streams.create_event()
+    }
+
+    /// Queue a kernel launch on `stream`.
+    pub fn launch_kernel_async(&mut self, kernel: &Kernel, config: &LaunchConfig, stream: Stream) {
+        self.streams.launch(kernel, config, stream);
+    }
+
+    /// Copy the whole host buffer `src` to device address `dst` now,
+    /// advancing the device clock by the copy's duration (cudaMemcpy). Work
+    /// queued on streams is not affected — it runs at the next
+    /// `synchronize()`.
+    pub fn memcpy_htod(&mut self, dst: usize, src: &HostBuffer) -> CopyStats {
+        self.hbm.write(dst, &src.read());
+        self.copy_now(CopyDirection::HostToDevice, src.len(), src.is_pinned())
+    }
+
+    /// Fill the host buffer `dst` from device address `src` now, advancing
+    /// the device clock by the copy's duration (cudaMemcpy).
+    pub fn memcpy_dtoh(&mut self, dst: &HostBuffer, src: usize) -> CopyStats {
+        dst.write_from(self.hbm.read(src, dst.len()));
+        self.copy_now(CopyDirection::DeviceToHost, dst.len(), dst.is_pinned())
+    }
+
+    /// Time a blocking copy of `bytes` at device address `addr` to or from
+    /// pageable host memory, for the `DeviceBuffer` copies. The host reads
+    /// or writes a managed range in place instead, faulting its pages over.
+    pub(crate) fn host_copy(&mut self, addr: usize, bytes: usize, direction: CopyDirection) {
+        if bytes == 0 {
+            return;
+        }
+        if self.managed.contains(addr, bytes) {
+            self.managed_host_access(addr, bytes, direction == CopyDirection::HostToDevice);
+        } else {
+            self.copy_now(direction, bytes, false);
+        }
+    }
+
+    /// Time a copy that starts at the current cycle on an idle GPU.
+    fn copy_now(&mut self, direction: CopyDirection, bytes: usize, pinned: bool) -> CopyStats {
+        let mut engines = CopyEngines::new(self.copy_engines);
+        let copy = self.schedule_copy(&mut engines, direction, bytes, pinned, self.cycle);
+        self.cycle = copy.end_cycle;
+        self.record_copy(copy.clone());
+        copy
+    }
+
+    /// Reserve a copy engine for a copy of `bytes` ready at cycle `now`.
+    /// Host copies take the PCIe link's latency and bandwidth; device copies
+    /// read and write every byte at HBM bandwidth.
+    pub(crate) fn schedule_copy(
+        &self,
+        engines: &mut CopyEngines,
+        direction: CopyDirection,
+        bytes: usize,
+        pinned: bool,
+        now: u64,
+    ) -> CopyStats {
+        let clock_mhz = self.sm_config.clock_mhz;
+        let cycles = match direction {
+            CopyDirection::DeviceToDevice => {
+                copy_cycles(2 * bytes, self.hbm.bandwidth_bps(clock_mhz), 0, clock_mhz)
+            }
+            _ => {
+                let bandwidth = self.pcie.bandwidth_bps(pinned);
+                copy_cycles(bytes, bandwidth, self.pcie.latency_ns, clock_mhz)
+            }
+        };
+        let start = engines.schedule(direction, now, cycles);
+        CopyStats::new(direction, bytes, pinned, now, start, start + cycles, clock_mhz)
+    }
+
+    /// Log a completed copy. The live metrics show the last one from the
+    /// next kernel snapshot or `synchronize()`.
+    pub(crate) fn record_copy(&mut self, copy: CopyStats) {
+        self.copies.push(copy);
+    }
+
+    /// What carries copies in `direction`
+    pub(crate) fn copy_link(&self, direction: CopyDirection) -> &'static str {
+        match direction {
+            CopyDirection::DeviceToDevice => "HBM",
+            _ => self.pcie.name,
+        }
+    }
+
+    /// Queue a copy of the whole host buffer `src` to device address `dst`.
+    pub fn memcpy_htod_async(&mut self, dst: usize, src: &HostBuffer, stream: Stream) {
+        self.streams.memcpy_htod(dst, src, stream);
+    }
+
+    /// Queue a copy from device address `src` filling the host buffer `dst`.
+    pub fn memcpy_dtoh_async(&mut self, dst: &HostBuffer, src: usize, stream: Stream) {
+        self.streams.memcpy_dtoh(dst, src, stream);
+    }
+
+    /// Queue a device-to-device copy of `len` bytes.
+    pub fn memcpy_dtod_async(&mut self, dst: usize, src: usize, len: usize, stream: Stream) {
+        self.streams.memcpy_dtod(dst, src, len, stream);
+    }
+
+    /// Queue a record of `event`: it completes once all earlier work in
+    /// `stream` has.
+    pub fn record_event(&mut self, event: Event, stream: Stream) {
+        self.streams.record(event, stream);
+    }
+
+    /// Make later work in `stream` wait for the last record of `event`.
+    pub fn stream_wait_event(&mut self, stream: Stream, event: Event) {
+        self.streams.wait(stream, event);
+    }
+
+    /// Start capturing `stream` into a graph. Until `end_capture`, work
+    /// submitted to it — and to streams that wait on events recorded in it —
+    /// is recorded instead of queued.
+    pub fn begin_capture(&mut self, stream: Stream) {
+        self.streams.begin_capture(stream);
+    }
+
+    /// Stop capturing `stream` and return the recorded graph. Fails if two
+    /// different kernels with the same name were captured.
+    pub fn end_capture(&mut self, stream: Stream) -> Result<Graph, String> {
+        self.streams.end_capture(stream)
+    }
+
+    /// Queue a launch of an instantiated graph on `stream`.
+    pub fn launch_graph(&mut self, graph: &GraphExec, stream: Stream) {
+        self.streams.launch_graph(graph, stream);
+    }
+
+    /// Run all queued stream work to completion. Returns the statistics of
+    /// every kernel launched, in completion order.
+    pub fn synchronize(&mut self) -> Vec<ExecutionStats> {
+        let copies = self.copies.len();
+        let stats = stream::synchronize(self);
+        // Kernel snapshots carry the last copy; copies after the last kernel
+        // are shown here
+        if let Some(copy) = self.copies.get(copies..).and_then(|c| c.last()) {
+            let mut m = read_metrics().unwrap_or_default();
+            m.last_copy = Some(copy.snapshot(self.copy_link(copy.direction)));
+            m.timestamp_ms = now_ms();
+            write_metrics(&m);
+        }
+        stats
+    }
+
+    /// Milliseconds between two recorded events (cudaEventElapsedTime).
+    /// None if either event has not been recorded yet.
+    pub fn event_elapsed(&self, start: Event, end: Event) -> Option<f64> {
+        let start = self.streams.timestamp(start)?;
+        let end = self.streams.timestamp(end)?;
+        let cycles = end as f64 - start as f64;
+        Some(cycles / (self.sm_config.clock_mhz.max(1) as f64 * 1000.0))
+    }
 }
+
@@ -0,0 +1,374 @@
+//! Task graphs: a fixed sequence of launches and copies, captured once and
+//! replayed many times (cf. CUDA graphs).
+//!
+//! A `Graph` is built by capturing a stream (`GPU::begin_capture` /
+//! `GPU::end_capture`): ops submitted to the captured stream become nodes
+//! instead of running, with edges that follow stream order and event waits.
+//! `Graph::instantiate` validates the graph once and yields a `GraphExec`,
+//! which `GPU::launch_graph` submits to a stream. A graph launch pays one
+//! launch overhead for the whole graph plus a small per-kernel-node cost, far
+//! less than launching the same kernels into a stream one by one.
+//!
+//! Graphs serialize to JSON. Kernel closures cannot be serialized, so kernel
+//! nodes refer to kernels by name; after loading a graph, bind the kernels
+//! with `Graph::bind_kernel` before instantiating it.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::banks::SmemBankSize;
+use crate::kernel::{Dim3, Kernel, LaunchConfig};
+use crate::managed::MemLocation;
+use crate::stream::HostBuffer;
+
+/// Operation performed by a graph node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GraphNodeKind {
+    /// Kernel launch
+    Kernel {
+        /// Name of the kernel, resolved against the bound kernels
+        kernel: String,
+        grid: [u32; 3],
+        block: [u32; 3],
+        #[serde(default)]
+        regs_per_thread: u32,
+        #[serde(default)]
+        smem_per_block: u32,
+        /// Preferred shared memory carveout in percent
+        #[serde(default)]
+        smem_carveout: Option<u32>,
+        /// Shared memory bank width
+        #[serde(default)]
+        smem_bank_size: SmemBankSize,
+        /// Blocks per thread block cluster; [1, 1, 1] = no clusters
+        #[serde(default = "no_cluster")]
+        cluster: [u32; 3],
+    },
+    /// Host to device copy of `bytes` bytes to `dst`
+    MemcpyHtoD { dst: usize, bytes: usize },
+    /// Device to host copy of `bytes` bytes from `src`
+    MemcpyDtoH { src: usize, bytes: usize },
+    /// Device to device copy
+    MemcpyDtoD { dst: usize, src: usize, bytes: usize },
+    /// Migration of `bytes` bytes of managed memory at `addr` to `location`
+    MemPrefetch { addr: usize, bytes: usize, location: MemLocation },
+}
+
+impl GraphNodeKind {
+    pub fn kernel(kernel: &Kernel, config: &LaunchConfig) -> Self {
+        let dim = |d: Dim3| [d.x, d.y, d.z];
+        GraphNodeKind::Kernel {
+            kernel: kernel.name.clone(),
+            grid: dim(config.grid_dim),
+            block: dim(config.block_dim),
+            regs_per_thread: config.regs_per_thread,
+            smem_per_block: config.smem_per_block,
+            smem_carveout: config.smem_carveout,
+            smem_bank_size: config.smem_bank_size,
+            cluster: dim(config.cluster_dim),
+        }
+    }
+
+    /// Launch configuration of a kernel node (None for copies).
+    pub fn launch_config(&self) -> Option<LaunchConfig> {
+        let GraphNodeKind::Kernel {
+            grid,
+            block,
+            regs_per_thread,
+            smem_per_block,
+            smem_carveout,
+            smem_bank_size,
+            cluster,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let dim = |d: &[u32; 3]| Dim3::new(d[0], d[1], d[2]);
+        Some(LaunchConfig {
+            smem_carveout: *smem_carveout,
+            smem_bank_size: *smem_bank_size,
+            cluster_dim: dim(cluster),
+            ..LaunchConfig::new(dim(grid), dim(block))
+                .with_resources(*regs_per_thread, *smem_per_block)
+        })
+    }
+}
+
+/// Cluster shape of kernel nodes saved without one.
+fn no_cluster() -> [u32; 3] {
+    [1, 1, 1]
+}
+
+/// One node of a graph.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphNode {
+    pub kind: GraphNodeKind,
+    /// Indices of the nodes that must complete before this one starts
+    #[serde(default)]
+    pub deps: Vec<usize>,
+    /// Host buffer of a host copy. Not serialized — a loaded graph copies
+    /// from / into a zero-filled buffer of the recorded size.
+    #[serde(skip)]
+    pub host: Option<HostBuffer>,
+}
+
+/// A dependency graph of launches and copies. Nodes only depend on earlier
+/// nodes, so node order is a valid execution order.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    /// Kernels referenced by kernel nodes, by name
+    #[serde(skip)]
+    kernels: HashMap<String, Kernel>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a node depending on `deps`; returns its index.
+    pub fn add_node(
+        &mut self,
+        kind: GraphNodeKind,
+        deps: Vec<usize>,
+        host: Option<HostBuffer>,
+    ) -> usize {
+        self.nodes.push(GraphNode { kind, deps, host });
+        self.nodes.len() - 1
+    }
+
+    /// Make `kernel` available to kernel nodes that refer to it by name.
+    /// Fails if the name is already bound to a different kernel; binding a
+    /// clone of the bound kernel again is fine.
+    pub fn bind_kernel(&mut self, kernel: &Kernel) -> Result<(), String> {
+        if let Some(bound) = self.kernels.get(&kernel.name)
+            && !bound.func.same_as(&kernel.func)
+        {
+            return Err(format!("kernel '{}' is already bound to a different kernel", kernel.name));
+        }
+        self.kernels.insert(kernel.name.clone(), kernel.clone());
+        Ok(())
+    }
+
+    /// Names of the kernels referenced by the graph, in first-use order.
+    pub fn kernel_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for node in &self.nodes {
+            if let GraphNodeKind::Kernel { kernel, .. } = &node.kind
+                && !names.contains(&kernel.as_str())
+            {
+                names.push(kernel);
+            }
+        }
+        names
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("graph serialization cannot fail")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("invalid graph: {}", e))
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_json(&json)
+    }
+
+    /// Validate the graph and prepare it for launching: every kernel must be
+    /// bound and every dependency must point at an earlier node.
+    pub fn instantiate(&self) -> Result<GraphExec, String> {
+        let mut kernels = HashMap::new();
+        let mut hosts = Vec::with_capacity(self.nodes.len());
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some(&dep) = node.deps.iter().find(|&&d| d >= i) {
+                return Err(format!("node {} depends on node {}, which does not precede it", i, dep));
+            }
+            let bytes = match &node.kind {
+                GraphNodeKind::Kernel { kernel, .. } => {
+                    let bound = self
+                        .kernels
+                        .get(kernel)
+                        .ok_or_else(|| format!("node {}: kernel '{}' is not bound", i, kernel))?;
+                    kernels.insert(kernel.clone(), bound.clone());
+                    0
+                }
+                GraphNodeKind::MemcpyHtoD { bytes, .. } | GraphNodeKind::MemcpyDtoH { bytes, .. } => {
+                    *bytes
+                }
+                GraphNodeKind::MemcpyDtoD { .. } | GraphNodeKind::MemPrefetch { .. } => 0,
+            };
+            hosts.push(node.host.clone().unwrap_or_else(|| HostBuffer::new(bytes)));
+        }
+        Ok(GraphExec { nodes: self.nodes.clone(), hosts, kernels })
+    }
+}
+
+/// An instantiated graph, ready to launch with `GPU::launch_graph`.
+pub struct GraphExec {
+    nodes: Vec<GraphNode>,
+    /// Host buffer of each node (empty for non-host nodes)
+    hosts: Vec<HostBuffer>,
+    kernels: HashMap<String, Kernel>,
+}
+
+impl GraphExec {
+    /// Nodes in execution order, each with its host buffer.
+    pub fn nodes(&self) -> impl Iterator<Item = (&GraphNode, &HostBuffer)> {
+        self.nodes.iter().zip(&self.hosts)
+    }
+
+    /// The kernel bound to `name`.
+    pub fn kernel(&self, name: &str) -> &Kernel {
+        &self.kernels[name]
+    }
+
+    /// Host buffer of node `node` — the destination of a device to host copy
+    /// or the source of a host to device copy.
+    pub fn host_buffer(&self, node: usize) -> &HostBuffer {
+        &self.hosts[node]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GPU;
+
+    #[test]
+    fn json_round_trip_keeps_nodes_and_edges() {
+        let kernel = Kernel::new("k", |_| {});
+        let config = LaunchConfig::new(Dim3::x(4), Dim3::new(32, 2, 1))
+            .with_resources(40, 1024)
+            .with_smem_carveout(50)
+            .with_smem_bank_size(SmemBankSize::EightByte)
+            .with_cluster(Dim3::x(2));
+        let mut graph = Graph::new();
+        let copy = graph.add_node(GraphNodeKind::MemcpyHtoD { dst: 256, bytes: 64 }, vec![], None);
+        graph.add_node(GraphNodeKind::kernel(&kernel, &config), vec![copy], None);
+
+        let loaded = Graph::from_json(&graph.to_json()).unwrap();
+        assert_eq!(loaded.nodes.len(), 2);
+        assert_eq!(loaded.nodes[0].kind, graph.nodes[0].kind);
+        assert_eq!(loaded.nodes[1].kind, graph.nodes[1].kind);
+        assert_eq!(loaded.nodes[1].deps, vec![0]);
+        let restored = loaded.nodes[1].kind.launch_config().unwrap();
+        assert_eq!(restored.grid_dim, config.grid_dim);
+        assert_eq!(restored.block_dim, config.block_dim);
+        assert_eq!((restored.regs_per_thread, restored.smem_per_block), (40, 1024));
+        assert_eq!(restored.cluster_dim, Dim3::x(2));
+        assert_eq!(restored.smem_carveout, Some(50));
+        assert_eq!(restored.smem_bank_size, SmemBankSize::EightByte);
+        assert_eq!(loaded.kernel_names(), vec!["k"]);
+    }
+
+    #[test]
+    fn kernel_nodes_without_a_cluster_default_to_none() {
+        let json = r#"{"nodes": [{"kind": {"type": "kernel", "kernel": "k",
+            "grid": [1, 1, 1], "block": [32, 1, 1]}}]}"#;
+        let graph = Graph::from_json(json).unwrap();
+        let config = graph.nodes[0].kind.launch_config().unwrap();
+        assert_eq!(config.cluster_dim, Dim3::x(1));
+    }
+
+    #[test]
+    fn instantiate_requires_bound_kernels() {
+        let kernel = Kernel::new("k", |_| {});
+        let node = GraphNodeKind::kernel(&kernel, &LaunchConfig::new(Dim3::x(1), Dim3::x(32)));
+        let mut graph = Graph::new();
+        graph.add_node(node, vec![], None);
+        let mut loaded = Graph::from_json(&graph.to_json()).unwrap();
+        assert!(loaded.instantiate().is_err());
+        loaded.bind_kernel(&kernel).unwrap();
+        assert!(loaded.instantiate().is_ok());
+    }
+
+    #[test]
+    fn bind_kernel_rejects_a_second_kernel_with_the_same_name() {
+        let first = Kernel::new("k", |_| {});
+        let second = Kernel::new("k", |_| {});
+        let mut graph = Graph::new();
+        graph.bind_kernel(&first).unwrap();
+        graph.bind_kernel(&first.clone()).unwrap();
+        assert!(graph.bind_kernel(&second).is_err());
+    }
+
+    #[test]
+    fn capturing_two_kernels_with_one_name_fails_end_capture() {
+        let mut gpu = GPU::h100();
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32));
+        let stream = gpu.create_stream();
+        gpu.begin_capture(stream);
+        gpu.launch_kernel_async(&Kernel::new("k", |_| {}), &config, stream);
+        gpu.launch_kernel_async(&Kernel::new("k", |_| {}), &config, stream);
+        let Err(err) = gpu.end_capture(stream) else { panic!("capture should fail") };
+        assert!(err.contains("already bound to a different kernel"));
+    }
+
+    #[test]
+    fn graph_replay_is_cheaper_than_stream_launches() {
+        let kernel = Kernel::new("inc", |ctx| {
+            let addr = ctx.global_id() as usize * 4;
+            let v = u32::from_le_bytes(ctx.gmem.read(addr, 4).try_into().unwrap());
+            ctx.gmem.write(addr, &(v + 1).to_le_bytes());
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(32));
+
+        let mut streamed = GPU::h100();
+        let stream = streamed.create_stream();
+        for _ in 0..4 {
+            streamed.launch_kernel_async(&kernel, &config, stream);
+        }
+        streamed.synchronize();
+
+        let mut graphed = GPU::h100();
+        let stream = graphed.create_stream();
+        graphed.begin_capture(stream);
+        for _ in 0..4 {
+            graphed.launch_kernel_async(&kernel, &config, stream);
+        }
+        let graph = graphed.end_capture(stream).unwrap();
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.nodes[3].deps, vec![2]);
+        // Nothing runs while capturing
+        assert_eq!(graphed.synchronize().len(), 0);
+        assert_eq!(graphed.hbm.read(0, 4), vec![0; 4]);
+
+        graphed.launch_graph(&graph.instantiate().unwrap(), stream);
+        assert_eq!(graphed.synchronize().len(), 4);
+        assert_eq!(graphed.hbm.read(124, 4), 4u32.to_le_bytes());
+        assert_eq!(graphed.hbm.read(124, 4), streamed.hbm.read(124, 4));
+        assert!(graphed.cycle < streamed.cycle);
+    }
+
+    #[test]
+    fn captured_cluster_kernel_replays_with_clusters() {
+        let kernel = Kernel::new("ranks", |ctx| {
+            if ctx.thread_idx.x == 0 {
+                let addr = ctx.block_idx.x as usize * 4;
+                ctx.gmem.write(addr, &ctx.cluster_block_rank().to_le_bytes());
+            }
+        });
+        let config = LaunchConfig::new(Dim3::x(4), Dim3::x(32)).with_cluster(Dim3::x(2));
+        let mut gpu = GPU::h100();
+        let stream = gpu.create_stream();
+        gpu.begin_capture(stream);
+        gpu.launch_kernel_async(&kernel, &config, stream);
+        let graph = gpu.end_capture(stream).unwrap();
+        let mut loaded = Graph::from_json(&graph.to_json()).unwrap();
+        loaded.bind_kernel(&kernel).unwrap();
+        gpu.launch_graph(&loaded.instantiate().unwrap(), stream);
+        let stats = gpu.synchronize();
+        assert_eq!(stats[0].cluster_size, 2);
+        let ranks: Vec<u8> = gpu.hbm.read(0, 16).into_iter().step_by(4).collect();
+        assert_eq!(ranks, vec![0, 1, 0, 1]);
+    }
+}
@@ -1,14 +1,14 @@
-/// Communication channel models for multi-GPU clusters.
-///
-/// Models NVLink (intra-node, via NVSwitch) and InfiniBand (inter-node, fat-tree)
-/// interconnects, including point-to-point transfer time simulation and
-/// collective operation algorithms (Ring, Tree, Direct AllReduce).
-///
-/// Bandwidth reference:
-///   H100 NVLink 4.0 — 900 GB/s bidirectional per GPU (via NVSwitch)
-///   A100 NVLink 3.0 — 600 GB/s bidirectional per GPU
-///   NDR InfiniBand  — 400 Gb/s = 50 GB/s per link
-///   HDR InfiniBand  — 200 Gb/s = 25 GB/s per link
+//! Communication channel models for multi-GPU clusters.
+//!
+//! Models NVLink (intra-node, via NVSwitch) and InfiniBand (inter-node, fat-tree)
+//! interconnects, including point-to-point transfer time simulation and
+//! collective operation algorithms (Ring, Tree, Direct AllReduce).
+//!
+//! Bandwidth reference:
+//!   H100 NVLink 4.0 — 900 GB/s bidirectional per GPU (via NVSwitch)
+//!   A100 NVLink 3.0 — 600 GB/s bidirectional per GPU
+//!   NDR InfiniBand  — 400 Gb/s = 50 GB/s per link
+//!   HDR InfiniBand  — 200 Gb/s = 25 GB/s per link
 
 // ---------------------------------------------------------------------------
 // Channel configurations
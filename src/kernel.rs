@@ -1,10 +1,24 @@
 /// Kernel definitions and launch configuration.
 /// A kernel is a function that every thread executes, identified by its
 /// thread/block coordinates — mirroring the CUDA execution model.
-use crate::memory::HBM;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use crate::atomic::{AtomicBits, AtomicValue};
+use crate::banks::SmemBankSize;
+use crate::constant::ConstantMemory;
+use crate::memcheck::Memcheck;
+use crate::memory::{decode_slice, encode_slice, DeviceValue, HBM};
+use crate::racecheck::{Access, MemorySpace, RaceState};
+use crate::sync::{LaneState, SyncFuture, WarpFuture, WarpOp, WarpValue};
+use crate::texture::{SurfaceObject, TexelFetch, TextureObject};
+use crate::timing::{InstrKind, LaneOp};
+use crate::warp::WARP_SIZE;
 
 /// 3D dimension struct used for grid and block sizes (mirrors CUDA's dim3).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Dim3 {
     pub x: u32,
     pub y: u32,
@@ -29,6 +43,7 @@ impl Dim3 {
 
 /// Configuration for launching a kernel: grid/block dimensions plus
 /// resource hints used by the occupancy calculator.
+#[derive(Debug, Clone)]
 pub struct LaunchConfig {
     pub grid_dim: Dim3,
     pub block_dim: Dim3,
@@ -36,6 +51,22 @@ pub struct LaunchConfig {
     pub regs_per_thread: u32,
     /// Shared memory bytes requested per block (0 = none)
     pub smem_per_block: u32,
+    /// Preferred shared memory carveout, in percent of the SM's maximum
+    /// shared memory; the rest of the unified array is L1. None lets the
+    /// simulator pick the smallest carveout that keeps full occupancy (see
+    /// `occupancy::smem_carveout`).
+    pub smem_carveout: Option<u32>,
+    /// Width of a shared memory bank word, which decides which accesses
+    /// conflict (see banks.rs)
+    pub smem_bank_size: SmemBankSize,
+    /// Blocks per thread block cluster (Hopper); (1, 1, 1) = no clusters.
+    /// Each grid dimension must be a multiple of the matching cluster dimension.
+    pub cluster_dim: Dim3,
+    /// Cooperative launch: every block is resident at once and threads can
+    /// synchronize across the grid (`ThreadCtx::grid_sync`). Only set by
+    /// `GPU::launch_cooperative_kernel`, which checks that the grid fits, so
+    /// no other launch path can bypass the check.
+    pub(crate) cooperative: bool,
     /// Milliseconds to pause after writing each block's live snapshot.
     /// Set > 0 to slow execution down for real-time visualisation.
     /// Default: 0 (no delay — full simulation speed).
@@ -49,6 +80,10 @@ impl LaunchConfig {
             block_dim,
             regs_per_thread: 0,
             smem_per_block: 0,
+            smem_carveout: None,
+            smem_bank_size: SmemBankSize::FourByte,
+            cluster_dim: Dim3::x(1),
+            cooperative: false,
             block_delay_ms: 0,
         }
     }
@@ -60,6 +95,28 @@ impl LaunchConfig {
         self
     }
 
+    /// Prefer a shared memory carveout of `percent` of the SM's maximum
+    /// shared memory (cudaFuncAttributePreferredSharedMemoryCarveout). It is
+    /// rounded up to a supported split, and to at least one block's worth.
+    pub fn with_smem_carveout(mut self, percent: u32) -> Self {
+        self.smem_carveout = Some(percent);
+        self
+    }
+
+    /// Set the shared memory bank width (cudaFuncSetSharedMemConfig).
+    pub fn with_smem_bank_size(mut self, bank_size: SmemBankSize) -> Self {
+        self.smem_bank_size = bank_size;
+        self
+    }
+
+    /// Group blocks into thread block clusters of `cluster_dim` blocks. All
+    /// blocks of a cluster are co-scheduled on one GPC and can access each
+    /// other's shared memory (see `ThreadCtx::cluster_smem`).
+    pub fn with_cluster(mut self, cluster_dim: Dim3) -> Self {
+        self.cluster_dim = cluster_dim;
+        self
+    }
+
     /// Set a per-block pause for live visualisation (e.g. `.with_delay(50)` = 50 ms/block).
     pub fn with_delay(mut self, ms: u64) -> Self {
         self.block_delay_ms = ms;
@@ -75,32 +132,778 @@ impl LaunchConfig {
     pub fn threads_per_block(&self) -> u32 {
         self.block_dim.size()
     }
+
+    /// Blocks per cluster (1 without clusters)
+    pub fn cluster_size(&self) -> u32 {
+        self.cluster_dim.size()
+    }
+
+    /// Total number of clusters in the grid
+    pub fn num_clusters(&self) -> u32 {
+        self.num_blocks() / self.cluster_size()
+    }
+
+    /// Check the cluster shape against the portable size limit, the grid,
+    /// and `gpc_sms`, the SM count of the smallest GPC: a cluster's blocks
+    /// must all fit in one GPC.
+    pub fn check_clusters(&self, gpc_sms: u32) -> Result<(), LaunchError> {
+        let (grid, cluster) = (self.grid_dim, self.cluster_dim);
+        let blocks = self.cluster_size();
+        if blocks > MAX_CLUSTER_SIZE {
+            return Err(LaunchError::ClusterTooLarge { blocks });
+        }
+        if grid.x % cluster.x != 0 || grid.y % cluster.y != 0 || grid.z % cluster.z != 0 {
+            return Err(LaunchError::GridNotClusterMultiple { grid, cluster });
+        }
+        if blocks > gpc_sms {
+            return Err(LaunchError::ClusterExceedsGpc { blocks, gpc_sms });
+        }
+        Ok(())
+    }
+}
+
+/// Most blocks a cluster may contain (the portable cluster size limit).
+pub const MAX_CLUSTER_SIZE: u32 = 8;
+
+/// Deepest nesting of device-side launches (cudaLimitDevRuntimeSyncDepth):
+/// host launches are at depth 0, their children at depth 1, and so on.
+pub const MAX_NESTING_DEPTH: u32 = 24;
+
+/// Why a device-side launch (`ThreadCtx::launch`) was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchError {
+    /// The child would be nested deeper than `MAX_NESTING_DEPTH`
+    NestingDepthExceeded { depth: u32 },
+    /// The cluster has more than `MAX_CLUSTER_SIZE` blocks
+    ClusterTooLarge { blocks: u32 },
+    /// A grid dimension is not a multiple of the matching cluster dimension
+    GridNotClusterMultiple { grid: Dim3, cluster: Dim3 },
+    /// The cluster has more blocks than the smallest GPC has SMs
+    ClusterExceedsGpc { blocks: u32, gpc_sms: u32 },
+    /// A cooperative grid has more blocks than can be resident at once
+    /// (cudaErrorCooperativeLaunchTooLarge)
+    CooperativeLaunchTooLarge { blocks: u32, max_resident: u32 },
+}
+
+impl std::fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchError::NestingDepthExceeded { depth } => write!(
+                f,
+                "child launch at nesting depth {} exceeds the limit of {}",
+                depth, MAX_NESTING_DEPTH,
+            ),
+            LaunchError::ClusterTooLarge { blocks } => write!(
+                f,
+                "cluster of {} blocks exceeds the limit of {}",
+                blocks, MAX_CLUSTER_SIZE,
+            ),
+            LaunchError::GridNotClusterMultiple { grid, cluster } => write!(
+                f,
+                "grid ({},{},{}) is not a multiple of cluster ({},{},{})",
+                grid.x, grid.y, grid.z, cluster.x, cluster.y, cluster.z,
+            ),
+            LaunchError::ClusterExceedsGpc { blocks, gpc_sms } => write!(
+                f,
+                "cluster of {} blocks does not fit in a GPC of {} SMs",
+                blocks, gpc_sms,
+            ),
+            LaunchError::CooperativeLaunchTooLarge { blocks, max_resident } => write!(
+                f,
+                "cooperative grid of {} blocks exceeds the {} blocks that can be resident at once",
+                blocks, max_resident,
+            ),
+        }
+    }
+}
+
+/// Child grids launched by the threads of one block, collected for the
+/// executor while the block runs.
+pub(crate) struct ChildLaunches {
+    /// Nesting depth of the launch the block belongs to
+    pub depth: u32,
+    /// SMs in the smallest GPC, which bounds the cluster size of a child
+    pub gpc_sms: u32,
+    pub queued: RefCell<Vec<(Kernel, LaunchConfig)>>,
+}
+
+impl ChildLaunches {
+    pub fn new(depth: u32, gpc_sms: u32) -> Self {
+        ChildLaunches { depth, gpc_sms, queued: RefCell::new(Vec::new()) }
+    }
+}
+
+/// Global memory as seen by the threads of one dispatch unit, with the
+/// sanitizers enabled for the launch.
+pub(crate) struct DeviceMemory<'a> {
+    pub hbm: RefCell<&'a mut HBM>,
+    pub constant: &'a ConstantMemory,
+    pub memcheck: Option<Memcheck<'a>>,
+    pub racecheck: Option<RefCell<RaceState>>,
+}
+
+/// Coordinates of a thread, for sanitizer reports.
+#[derive(Clone, Copy)]
+pub(crate) struct ThreadCoords {
+    pub block_idx: Dim3,
+    pub thread_idx: Dim3,
+    pub cluster_idx: Dim3,
+}
+
+impl ThreadCoords {
+    /// Record an access of `size` bytes at `addr` for race detection, when
+    /// enabled. Atomics are not checked.
+    fn racecheck(
+        &self,
+        mem: &DeviceMemory,
+        lane: &LaneState,
+        space: MemorySpace,
+        owner: Dim3,
+        op: &LaneOp,
+    ) {
+        let write = match op.kind {
+            InstrKind::GlobalStore | InstrKind::SharedStore | InstrKind::DsmemStore => true,
+            InstrKind::GlobalLoad | InstrKind::SharedLoad | InstrKind::DsmemLoad => false,
+            _ => return,
+        };
+        if let Some(race) = &mem.racecheck {
+            let who = Access {
+                block_idx: self.block_idx,
+                thread_idx: self.thread_idx,
+                cluster_idx: self.cluster_idx,
+                epoch: lane.epoch.get(),
+                site: op.site,
+            };
+            race.borrow_mut().access(space, owner, op.addr, op.size as usize, write, who);
+        }
+    }
+}
+
+/// A thread's view of global memory (HBM).
+/// Every access is recorded in the thread's instruction trace for the timing model.
+pub struct GlobalMem<'a> {
+    mem: &'a DeviceMemory<'a>,
+    lane: &'a LaneState,
+    coords: ThreadCoords,
+}
+
+impl<'a> GlobalMem<'a> {
+    /// Record an access in the thread's trace and check it with the enabled
+    /// sanitizers. `align` is the size of the accessed type (1 for raw bytes).
+    #[track_caller]
+    fn access(&self, kind: InstrKind, addr: usize, len: usize, align: usize) {
+        let op = LaneOp::mem(kind, addr, len);
+        let c = &self.coords;
+        if let Some(memcheck) = &self.mem.memcheck {
+            memcheck.check(&self.mem.hbm.borrow(), &op, align, c.block_idx, c.thread_idx);
+        }
+        c.racecheck(self.mem, self.lane, MemorySpace::Global, c.block_idx, &op);
+        self.lane.trace.borrow_mut().push(op);
+    }
+
+    #[track_caller]
+    fn read_aligned(&self, addr: usize, len: usize, align: usize) -> Vec<u8> {
+        self.access(InstrKind::GlobalLoad, addr, len, align);
+        self.mem.hbm.borrow().read(addr, len)
+    }
+
+    #[track_caller]
+    fn write_aligned(&self, addr: usize, bytes: &[u8], align: usize) {
+        self.access(InstrKind::GlobalStore, addr, bytes.len(), align);
+        self.mem.hbm.borrow_mut().write(addr, bytes);
+    }
+
+    #[track_caller]
+    pub fn read(&self, addr: usize, len: usize) -> Vec<u8> {
+        self.read_aligned(addr, len, 1)
+    }
+
+    #[track_caller]
+    pub fn write(&self, addr: usize, bytes: &[u8]) {
+        self.write_aligned(addr, bytes, 1)
+    }
+
+    /// Typed read of a `T` at `addr`.
+    #[track_caller]
+    pub fn load<T: DeviceValue>(&self, addr: usize) -> T {
+        T::from_le(&self.read_aligned(addr, T::SIZE, T::SIZE))
+    }
+
+    /// Typed write of `value` at `addr`.
+    #[track_caller]
+    pub fn store<T: DeviceValue>(&self, addr: usize, value: T) {
+        self.write_aligned(addr, &value.to_le(), T::SIZE);
+    }
+
+    /// Read `len` consecutive `T`s starting at `addr` as one access.
+    #[track_caller]
+    pub fn load_slice<T: DeviceValue>(&self, addr: usize, len: usize) -> Vec<T> {
+        decode_slice(&self.read_aligned(addr, len * T::SIZE, T::SIZE))
+    }
+
+    /// Read the texels of a texture fetch and blend them by their filter
+    /// weights, recording one texture request per row of texels.
+    #[track_caller]
+    fn texture_fetch(&self, fetch: TexelFetch) -> f32 {
+        for &(addr, len) in &fetch.rows {
+            if len > 0 {
+                self.access(InstrKind::TextureFetch, addr, len, 4);
+            } else {
+                self.lane.trace.borrow_mut().push(LaneOp::mem(InstrKind::TextureFetch, addr, 0));
+            }
+        }
+        let hbm = self.mem.hbm.borrow();
+        fetch
+            .taps
+            .iter()
+            .fold(0.0, |acc, &(addr, weight)| acc + weight * f32::from_le(&hbm.read(addr, 4)))
+    }
+
+    /// Write `values` consecutively starting at `addr` as one access.
+    #[track_caller]
+    pub fn store_slice<T: DeviceValue>(&self, addr: usize, values: &[T]) {
+        self.write_aligned(addr, &encode_slice(values), T::SIZE);
+    }
+
+    /// Apply `f` to the value at `addr` atomically; returns the old value.
+    #[track_caller]
+    fn rmw<T: AtomicValue>(&self, addr: usize, f: impl FnOnce(T) -> T) -> T {
+        self.access(InstrKind::GlobalAtomic, addr, T::SIZE, T::SIZE);
+        let mut hbm = self.mem.hbm.borrow_mut();
+        let old = T::from_le(&hbm.read(addr, T::SIZE));
+        hbm.write(addr, &f(old).to_le());
+        old
+    }
+
+    /// atomicAdd — returns the old value.
+    #[track_caller]
+    pub fn atomic_add<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_add(val))
+    }
+
+    /// atomicMin — returns the old value.
+    #[track_caller]
+    pub fn atomic_min<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_min(val))
+    }
+
+    /// atomicMax — returns the old value.
+    #[track_caller]
+    pub fn atomic_max<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_max(val))
+    }
+
+    /// atomicExch — stores `val`, returns the old value.
+    #[track_caller]
+    pub fn atomic_exch<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |_: T| val)
+    }
+
+    /// atomicCAS — stores `val` if the current value equals `compare`; returns the old value.
+    #[track_caller]
+    pub fn atomic_cas<T: AtomicValue>(&self, addr: usize, compare: T, val: T) -> T {
+        self.rmw(addr, |old: T| if old == compare { val } else { old })
+    }
+
+    /// atomicAnd — returns the old value.
+    #[track_caller]
+    pub fn atomic_and<T: AtomicBits>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_and(val))
+    }
+
+    /// atomicOr — returns the old value.
+    #[track_caller]
+    pub fn atomic_or<T: AtomicBits>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_or(val))
+    }
+
+    /// atomicXor — returns the old value.
+    #[track_caller]
+    pub fn atomic_xor<T: AtomicBits>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_xor(val))
+    }
+}
+
+/// A thread's view of constant memory: 64 KB, read-only.
+/// Every access is recorded in the thread's instruction trace for the timing model.
+pub struct ConstMem<'a> {
+    data: &'a ConstantMemory,
+    lane: &'a LaneState,
+}
+
+impl<'a> ConstMem<'a> {
+    /// Panics if the access falls outside constant memory.
+    #[track_caller]
+    pub fn read(&self, offset: usize, len: usize) -> Vec<u8> {
+        let bytes = self.data.read(offset, len).to_vec();
+        self.lane.trace.borrow_mut().push(LaneOp::mem(InstrKind::ConstantLoad, offset, len));
+        bytes
+    }
+
+    /// Typed read of a `T` at byte `offset`.
+    #[track_caller]
+    pub fn load<T: DeviceValue>(&self, offset: usize) -> T {
+        T::from_le(&self.read(offset, T::SIZE))
+    }
+
+    /// Read `len` consecutive `T`s starting at byte `offset` as one access.
+    #[track_caller]
+    pub fn load_slice<T: DeviceValue>(&self, offset: usize, len: usize) -> Vec<T> {
+        decode_slice(&self.read(offset, len * T::SIZE))
+    }
+}
+
+/// A thread's view of a block's shared memory (SMEM) — its own block's, or
+/// another block's in the same cluster (distributed shared memory).
+/// Every access is recorded in the thread's instruction trace for the timing model.
+pub struct SharedMem<'a> {
+    data: &'a RefCell<Vec<u8>>,
+    lane: &'a LaneState,
+    /// Whether the memory belongs to another block of the cluster
+    remote: bool,
+    /// Block the memory belongs to
+    owner: Dim3,
+    mem: &'a DeviceMemory<'a>,
+    coords: ThreadCoords,
+}
+
+impl<'a> SharedMem<'a> {
+    /// Instruction kind of an access: `local` for the thread's own block,
+    /// `remote` for distributed shared memory.
+    fn kind(&self, local: InstrKind, remote: InstrKind) -> InstrKind {
+        if self.remote { remote } else { local }
+    }
+
+    /// Size of the block's shared memory allocation in bytes
+    pub fn len(&self) -> usize {
+        self.data.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record an access in the thread's trace and check it for races when
+    /// racecheck is enabled.
+    #[track_caller]
+    fn access(&self, kind: InstrKind, addr: usize, len: usize) {
+        let op = LaneOp::mem(kind, addr, len);
+        self.coords.racecheck(self.mem, self.lane, MemorySpace::Shared, self.owner, &op);
+        self.lane.trace.borrow_mut().push(op);
+    }
+
+    /// Panics if the access falls outside the block's allocation.
+    #[track_caller]
+    pub fn read(&self, addr: usize, len: usize) -> Vec<u8> {
+        self.access(self.kind(InstrKind::SharedLoad, InstrKind::DsmemLoad), addr, len);
+        self.data.borrow()[addr..addr + len].to_vec()
+    }
+
+    /// Panics if the access falls outside the block's allocation.
+    #[track_caller]
+    pub fn write(&self, addr: usize, bytes: &[u8]) {
+        self.access(self.kind(InstrKind::SharedStore, InstrKind::DsmemStore), addr, bytes.len());
+        self.data.borrow_mut()[addr..addr + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Typed read of a `T` at `addr`.
+    #[track_caller]
+    pub fn load<T: DeviceValue>(&self, addr: usize) -> T {
+        T::from_le(&self.read(addr, T::SIZE))
+    }
+
+    /// Typed write of `value` at `addr`.
+    #[track_caller]
+    pub fn store<T: DeviceValue>(&self, addr: usize, value: T) {
+        self.write(addr, &value.to_le());
+    }
+
+    /// Read `len` consecutive `T`s starting at `addr` as one access.
+    #[track_caller]
+    pub fn load_slice<T: DeviceValue>(&self, addr: usize, len: usize) -> Vec<T> {
+        decode_slice(&self.read(addr, len * T::SIZE))
+    }
+
+    /// Write `values` consecutively starting at `addr` as one access.
+    #[track_caller]
+    pub fn store_slice<T: DeviceValue>(&self, addr: usize, values: &[T]) {
+        self.write(addr, &encode_slice(values));
+    }
+
+    /// Apply `f` to the value at `addr` atomically; returns the old value.
+    #[track_caller]
+    fn rmw<T: AtomicValue>(&self, addr: usize, f: impl FnOnce(T) -> T) -> T {
+        self.access(self.kind(InstrKind::SharedAtomic, InstrKind::DsmemAtomic), addr, T::SIZE);
+        let mut data = self.data.borrow_mut();
+        let old = T::from_le(&data[addr..addr + T::SIZE]);
+        data[addr..addr + T::SIZE].copy_from_slice(&f(old).to_le());
+        old
+    }
+
+    /// atomicAdd — returns the old value.
+    #[track_caller]
+    pub fn atomic_add<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_add(val))
+    }
+
+    /// atomicMin — returns the old value.
+    #[track_caller]
+    pub fn atomic_min<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_min(val))
+    }
+
+    /// atomicMax — returns the old value.
+    #[track_caller]
+    pub fn atomic_max<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_max(val))
+    }
+
+    /// atomicExch — stores `val`, returns the old value.
+    #[track_caller]
+    pub fn atomic_exch<T: AtomicValue>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |_: T| val)
+    }
+
+    /// atomicCAS — stores `val` if the current value equals `compare`; returns the old value.
+    #[track_caller]
+    pub fn atomic_cas<T: AtomicValue>(&self, addr: usize, compare: T, val: T) -> T {
+        self.rmw(addr, |old: T| if old == compare { val } else { old })
+    }
+
+    /// atomicAnd — returns the old value.
+    #[track_caller]
+    pub fn atomic_and<T: AtomicBits>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_and(val))
+    }
+
+    /// atomicOr — returns the old value.
+    #[track_caller]
+    pub fn atomic_or<T: AtomicBits>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_or(val))
+    }
+
+    /// atomicXor — returns the old value.
+    #[track_caller]
+    pub fn atomic_xor<T: AtomicBits>(&self, addr: usize, val: T) -> T {
+        self.rmw(addr, |old: T| old.atomic_xor(val))
+    }
 }
 
 /// Per-thread context passed into the kernel function.
-/// Contains thread/block coordinates and access to shared + global memory.
+/// Contains thread/block coordinates and access to shared, global and constant memory.
 pub struct ThreadCtx<'a> {
     pub thread_idx: Dim3,
     pub block_idx: Dim3,
     pub block_dim: Dim3,
     pub grid_dim: Dim3,
+    /// Blocks per cluster — (1, 1, 1) without clusters
+    pub cluster_dim: Dim3,
+    /// Whether the grid was launched cooperatively (grid_sync allowed)
+    cooperative: bool,
     /// Per-block shared memory (SMEM) — shared among all threads in the block
-    pub smem: &'a mut Vec<u8>,
+    pub smem: SharedMem<'a>,
     /// Global memory (HBM)
-    pub gmem: &'a mut HBM,
+    pub gmem: GlobalMem<'a>,
+    /// Constant memory (64 KB, written by the host)
+    pub cmem: ConstMem<'a>,
+    /// Instruction trace and sync state, shared with the executor
+    lane: &'a LaneState,
+    /// Shared memory of every block in the cluster, indexed by block rank
+    cluster_smem: &'a [RefCell<Vec<u8>>],
+    /// Child grids launched by the block
+    children: &'a ChildLaunches,
 }
 
 impl<'a> ThreadCtx<'a> {
+    /// `cluster_smem` holds the shared memory of every block in the thread's
+    /// cluster (just its own block's without clusters), indexed by block rank.
+    pub(crate) fn new(
+        thread_idx: Dim3,
+        block_idx: Dim3,
+        config: &LaunchConfig,
+        cluster_smem: &'a [RefCell<Vec<u8>>],
+        mem: &'a DeviceMemory<'a>,
+        lane: &'a LaneState,
+        children: &'a ChildLaunches,
+    ) -> Self {
+        let rank = cluster_block_rank(block_idx, config.cluster_dim);
+        let c = config.cluster_dim;
+        let cluster_idx = Dim3::new(block_idx.x / c.x, block_idx.y / c.y, block_idx.z / c.z);
+        let coords = ThreadCoords { block_idx, thread_idx, cluster_idx };
+        ThreadCtx {
+            thread_idx,
+            block_idx,
+            block_dim: config.block_dim,
+            grid_dim: config.grid_dim,
+            cluster_dim: config.cluster_dim,
+            cooperative: config.cooperative,
+            smem: SharedMem {
+                data: &cluster_smem[rank as usize],
+                lane,
+                remote: false,
+                owner: block_idx,
+                mem,
+                coords,
+            },
+            gmem: GlobalMem { mem, lane, coords },
+            cmem: ConstMem { data: mem.constant, lane },
+            lane,
+            cluster_smem,
+            children,
+        }
+    }
+
     /// Flat 1D global thread index: blockIdx.x * blockDim.x + threadIdx.x
     pub fn global_id(&self) -> u32 {
         self.block_idx.x * self.block_dim.x + self.thread_idx.x
     }
+
+    /// 3D global thread index: blockIdx * blockDim + threadIdx, per axis
+    pub fn global_idx(&self) -> Dim3 {
+        let (b, d, t) = (self.block_idx, self.block_dim, self.thread_idx);
+        Dim3::new(b.x * d.x + t.x, b.y * d.y + t.y, b.z * d.z + t.z)
+    }
+
+    /// Total number of threads along each axis: gridDim * blockDim
+    pub fn global_dim(&self) -> Dim3 {
+        let (g, d) = (self.grid_dim, self.block_dim);
+        Dim3::new(g.x * d.x, g.y * d.y, g.z * d.z)
+    }
+
+    /// Flat global thread index over the whole grid (x fastest, then y, then
+    /// z) — for row-major indexing of 2D/3D arrays of size `global_dim`
+    pub fn global_linear_id(&self) -> u64 {
+        let (i, n) = (self.global_idx(), self.global_dim());
+        (i.z as u64 * n.y as u64 + i.y as u64) * n.x as u64 + i.x as u64
+    }
+
+    /// Flat thread index within the block (x fastest, then y, then z)
+    pub fn thread_rank(&self) -> u32 {
+        (self.thread_idx.z * self.block_dim.y + self.thread_idx.y) * self.block_dim.x
+            + self.thread_idx.x
+    }
+
+    /// Lane index within the warp [0, 32)
+    pub fn lane_id(&self) -> u32 {
+        self.thread_rank() % WARP_SIZE as u32
+    }
+
+    /// Warp index within the block
+    pub fn warp_id(&self) -> u32 {
+        self.thread_rank() / WARP_SIZE as u32
+    }
+
+    // -----------------------------------------------------------------------
+    // Thread block clusters (Hopper)
+    // -----------------------------------------------------------------------
+
+    /// Rank of this thread's block within its cluster (x fastest), in
+    /// [0, cluster size)
+    pub fn cluster_block_rank(&self) -> u32 {
+        cluster_block_rank(self.block_idx, self.cluster_dim)
+    }
+
+    /// Index of this thread's cluster within the grid
+    pub fn cluster_idx(&self) -> Dim3 {
+        let (b, c) = (self.block_idx, self.cluster_dim);
+        Dim3::new(b.x / c.x, b.y / c.y, b.z / c.z)
+    }
+
+    /// Shared memory of the block with rank `rank` in this thread's cluster
+    /// (cluster.map_shared_rank). Accesses to another block's shared memory go
+    /// over the SM-to-SM network and are slower than local ones.
+    /// Panics if `rank` is not a block of the cluster.
+    pub fn cluster_smem(&self, rank: u32) -> SharedMem<'a> {
+        let remote = rank != self.cluster_block_rank();
+        let data = self.cluster_smem.get(rank as usize).unwrap_or_else(|| {
+            panic!("cluster block rank {} out of range (cluster size {})", rank, self.cluster_dim.size())
+        });
+        let (origin, c) = (self.cluster_idx(), self.cluster_dim);
+        let owner = Dim3::new(
+            origin.x * c.x + rank % c.x,
+            origin.y * c.y + rank / c.x % c.y,
+            origin.z * c.z + rank / (c.x * c.y),
+        );
+        SharedMem { data, lane: self.lane, remote, owner, mem: self.gmem.mem, coords: self.gmem.coords }
+    }
+
+    /// Grid-wide barrier (grid.sync()). Only available to async kernels in a
+    /// cooperative launch (`GPU::launch_cooperative_kernel`): the calling
+    /// thread is suspended until every live thread of the grid has reached
+    /// the barrier.
+    #[track_caller]
+    pub fn grid_sync(&self) -> SyncFuture<'a> {
+        assert!(self.cooperative, "grid_sync requires a cooperative launch");
+        SyncFuture::grid(self.lane)
+    }
+
+    /// Cluster-wide barrier (cluster.sync()). Only available to async kernels:
+    /// the calling thread is suspended until every live thread of every block
+    /// in the cluster has reached the barrier. Without clusters it behaves
+    /// like `syncthreads`.
+    #[track_caller]
+    pub fn cluster_sync(&self) -> SyncFuture<'a> {
+        SyncFuture::cluster(self.lane)
+    }
+
+    /// Declare `n` arithmetic instructions for the timing model.
+    /// The closure body is opaque to the simulator, so compute-heavy kernels
+    /// call this to account for the work between memory accesses.
+    #[track_caller]
+    pub fn alu(&self, n: u32) {
+        let mut trace = self.lane.trace.borrow_mut();
+        trace.extend(std::iter::repeat_n(LaneOp::new(InstrKind::Alu), n as usize));
+    }
+
+    /// Launch a child grid (dynamic parallelism, `kernel<<<...>>>` in device
+    /// code). The child starts after the device-side launch latency and may
+    /// run concurrently with the rest of the parent grid; it sees every global
+    /// memory write the parent block made. The parent grid only completes once
+    /// all of its children (and theirs) have completed.
+    #[track_caller]
+    pub fn launch(&self, kernel: &Kernel, config: &LaunchConfig) -> Result<(), LaunchError> {
+        let depth = self.children.depth + 1;
+        if depth > MAX_NESTING_DEPTH {
+            return Err(LaunchError::NestingDepthExceeded { depth });
+        }
+        config.check_clusters(self.children.gpc_sms)?;
+        self.lane.trace.borrow_mut().push(LaneOp::new(InstrKind::Launch));
+        self.children.queued.borrow_mut().push((kernel.clone(), config.clone()));
+        Ok(())
+    }
+
+    /// Nesting depth of the running grid (0 for a launch from the host).
+    pub fn nesting_depth(&self) -> u32 {
+        self.children.depth
+    }
+
+    /// Block-wide barrier (__syncthreads). Only available to async kernels:
+    /// the calling thread is suspended until every live thread of the block
+    /// has reached the barrier.
+    #[track_caller]
+    pub fn syncthreads(&self) -> SyncFuture<'a> {
+        SyncFuture::new(self.lane)
+    }
+
+    // -----------------------------------------------------------------------
+    // Texture and surface objects (see texture.rs)
+    // -----------------------------------------------------------------------
+
+    /// tex1D: fetch from a texture at `x`; a 2D texture is read along its
+    /// first row.
+    #[track_caller]
+    pub fn tex1d(&self, tex: &TextureObject, x: f32) -> f32 {
+        self.gmem.texture_fetch(tex.fetch(x, None))
+    }
+
+    /// tex2D: fetch from a 2D texture at (`x`, `y`).
+    #[track_caller]
+    pub fn tex2d(&self, tex: &TextureObject, x: f32, y: f32) -> f32 {
+        self.gmem.texture_fetch(tex.fetch(x, Some(y)))
+    }
+
+    /// surf2Dread: read texel (`x`, `y`) of a surface through the texture
+    /// cache. Coordinates are in texels (CUDA takes x in bytes).
+    #[track_caller]
+    pub fn surf2d_read(&self, surf: &SurfaceObject, x: i32, y: i32) -> f32 {
+        self.gmem.texture_fetch(TexelFetch::single(surf.texel(x, y)))
+    }
+
+    /// surf2Dwrite: write texel (`x`, `y`) of a surface as a global store.
+    #[track_caller]
+    pub fn surf2d_write(&self, surf: &SurfaceObject, x: i32, y: i32, value: f32) {
+        if let Some(addr) = surf.texel(x, y) {
+            self.gmem.store(addr, value);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Warp intrinsics (async kernels only)
+    //
+    // Every lane named in `mask` must call the same intrinsic; the op is then
+    // evaluated over those lanes in lockstep. Reading from a lane outside the
+    // mask (or one that has exited) returns the caller's own value.
+    // -----------------------------------------------------------------------
+
+    /// __shfl_sync: read `value` from lane `src_lane`.
+    #[track_caller]
+    pub fn shfl_sync<T: WarpValue>(&self, mask: u32, value: T, src_lane: u32) -> WarpFuture<'a, T> {
+        WarpFuture::new(self.lane, WarpOp::Shfl { src_lane }, mask, value.to_bits())
+    }
+
+    /// __shfl_up_sync: read `value` from lane `lane_id - delta`.
+    #[track_caller]
+    pub fn shfl_up_sync<T: WarpValue>(&self, mask: u32, value: T, delta: u32) -> WarpFuture<'a, T> {
+        WarpFuture::new(self.lane, WarpOp::ShflUp { delta }, mask, value.to_bits())
+    }
+
+    /// __shfl_down_sync: read `value` from lane `lane_id + delta`.
+    #[track_caller]
+    pub fn shfl_down_sync<T: WarpValue>(&self, mask: u32, value: T, delta: u32) -> WarpFuture<'a, T> {
+        WarpFuture::new(self.lane, WarpOp::ShflDown { delta }, mask, value.to_bits())
+    }
+
+    /// __shfl_xor_sync: read `value` from lane `lane_id ^ lane_mask`.
+    #[track_caller]
+    pub fn shfl_xor_sync<T: WarpValue>(&self, mask: u32, value: T, lane_mask: u32) -> WarpFuture<'a, T> {
+        WarpFuture::new(self.lane, WarpOp::ShflXor { lane_mask }, mask, value.to_bits())
+    }
+
+    /// __ballot_sync: bit i is set if lane i is in `mask` and its `predicate` is true.
+    #[track_caller]
+    pub fn ballot_sync(&self, mask: u32, predicate: bool) -> WarpFuture<'a, u32> {
+        WarpFuture::new(self.lane, WarpOp::Ballot, mask, predicate as u64)
+    }
+
+    /// __any_sync: true if `predicate` is true on any lane in `mask`.
+    #[track_caller]
+    pub fn any_sync(&self, mask: u32, predicate: bool) -> WarpFuture<'a, bool> {
+        WarpFuture::new(self.lane, WarpOp::Any, mask, predicate as u64)
+    }
+
+    /// __all_sync: true if `predicate` is true on every lane in `mask`.
+    #[track_caller]
+    pub fn all_sync(&self, mask: u32, predicate: bool) -> WarpFuture<'a, bool> {
+        WarpFuture::new(self.lane, WarpOp::All, mask, predicate as u64)
+    }
+}
+
+/// Rank of block `block_idx` within its cluster, x fastest.
+pub(crate) fn cluster_block_rank(block_idx: Dim3, cluster_dim: Dim3) -> u32 {
+    let (b, c) = (block_idx, cluster_dim);
+    ((b.z % c.z) * c.y + b.y % c.y) * c.x + b.x % c.x
+}
+
+/// A suspended thread of an async kernel. Owns the thread's `ThreadCtx`.
+pub type ThreadTask<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// The body of a kernel. Shared, so queued launches can hold on to it.
+#[derive(Clone)]
+pub enum KernelFn {
+    /// Runs each thread to completion in one call. Cannot synchronize.
+    Sync(Rc<dyn Fn(&mut ThreadCtx<'_>)>),
+    /// Runs each thread as a resumable task that can suspend at sync points
+    /// (`ctx.syncthreads().await`).
+    Async(Rc<dyn for<'a> Fn(ThreadCtx<'a>) -> ThreadTask<'a>>),
+}
+
+impl KernelFn {
+    /// Whether both are the same function, i.e. clones of one kernel.
+    pub fn same_as(&self, other: &KernelFn) -> bool {
+        match (self, other) {
+            (KernelFn::Sync(a), KernelFn::Sync(b)) => Rc::ptr_eq(a, b),
+            (KernelFn::Async(a), KernelFn::Async(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
 /// A GPU kernel: a named function executed by every thread in the launch grid.
+/// Cloning is cheap — clones share the same function.
+#[derive(Clone)]
 pub struct Kernel {
     pub name: String,
-    pub func: Box<dyn Fn(&mut ThreadCtx<'_>)>,
+    pub func: KernelFn,
 }
 
 impl Kernel {
@@ -110,7 +913,23 @@ impl Kernel {
     {
         Kernel {
             name: name.to_string(),
-            func: Box::new(func),
+            func: KernelFn::Sync(Rc::new(func)),
+        }
+    }
+
+    /// Create a kernel from an async closure. Needed by kernels that
+    /// synchronize, e.g. `ctx.syncthreads().await`.
+    pub fn new_async<F>(name: &str, func: F) -> Self
+    where
+        F: AsyncFn(&mut ThreadCtx<'_>) + 'static,
+    {
+        let func = Rc::new(func);
+        Kernel {
+            name: name.to_string(),
+            func: KernelFn::Async(Rc::new(move |mut ctx| {
+                let func = Rc::clone(&func);
+                Box::pin(async move { func(&mut ctx).await })
+            })),
         }
     }
 }
@@ -1,12 +1,29 @@
+pub mod allocator;
+pub mod atomic;
+pub mod banks;
+pub mod cache;
 pub mod cluster;
+pub mod coalescing;
+pub mod constant;
+pub mod dram;
 pub mod executor;
 pub mod gpu;
+pub mod graph;
 pub mod interconnect;
 pub mod kernel;
+pub mod managed;
+pub mod memcheck;
 pub mod memory;
 pub mod metrics;
 pub mod occupancy;
+pub mod pcie;
+pub mod racecheck;
 pub mod scheduler;
+pub mod simt;
 pub mod sm;
+pub mod stream;
+pub mod sync;
 pub mod tensor_core;
+pub mod texture;
+pub mod timing;
 pub mod warp;
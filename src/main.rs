@@ -1,12 +1,149 @@
 use gpusim::cluster::{Cluster, DeviceId};
 use gpusim::gpu::GPU;
+use gpusim::graph::Graph;
 use gpusim::interconnect::AllReduceAlgorithm;
 use gpusim::kernel::{Dim3, Kernel, LaunchConfig};
+use gpusim::memory::DeviceBuffer;
 use gpusim::scheduler::SchedulingPolicy;
 use std::thread::sleep;
 use std::time::Duration;
 
+// 32 768 elements → 256 blocks of 128 threads.
+// Large enough that the SM heatmap has time to animate in the visualiser.
+const N: u32 = 32_768;
+
+/// Device buffers of the vector addition demo.
+#[derive(Clone, Copy)]
+struct VecAddBuffers {
+    a: DeviceBuffer<f32>,
+    b: DeviceBuffer<f32>,
+    c: DeviceBuffer<f32>,
+    /// Where the captured demo graph snapshots the result
+    out: DeviceBuffer<f32>,
+}
+
+/// Allocate the demo buffers. The allocator is deterministic, so a fresh GPU
+/// always hands out the same addresses — which lets a saved graph be
+/// replayed on a new GPU.
+fn alloc_buffers(gpu: &mut GPU) -> VecAddBuffers {
+    let mut alloc = || gpu.alloc_buffer::<f32>(N as usize).expect("demo buffers fit in HBM");
+    VecAddBuffers { a: alloc(), b: alloc(), c: alloc(), out: alloc() }
+}
+
+/// Fill the demo input vectors: a[i] = i, b[i] = N - i.
+fn init_inputs(gpu: &mut GPU, bufs: &VecAddBuffers) {
+    let a: Vec<f32> = (0..N).map(|i| i as f32).collect();
+    let b: Vec<f32> = (0..N).map(|i| (N - i) as f32).collect();
+    bufs.a.copy_from_host(gpu, &a);
+    bufs.b.copy_from_host(gpu, &b);
+}
+
+/// c[i] = a[i] + b[i] over the demo vectors.
+fn vec_add_kernel(bufs: VecAddBuffers) -> Kernel {
+    Kernel::new("vec_add", move |ctx| {
+        let i = ctx.global_id() as usize;
+        if i >= N as usize {
+            return;
+        }
+        let a: f32 = ctx.gmem.load(bufs.a.at(i));
+        let b: f32 = ctx.gmem.load(bufs.b.at(i));
+        ctx.gmem.store(bufs.c.at(i), a + b);
+    })
+}
+
+/// `gpusim capture <out.json>`: capture a chain of vec_add launches and a
+/// copy of the result into a graph and save it as JSON.
+fn capture(path: &str) {
+    let mut gpu = GPU::h100();
+    let bufs = alloc_buffers(&mut gpu);
+    let kernel = vec_add_kernel(bufs);
+    let config = LaunchConfig::new(Dim3::x(N.div_ceil(128)), Dim3::x(128)).with_resources(32, 0);
+
+    let stream = gpu.create_stream();
+    gpu.begin_capture(stream);
+    for _ in 0..4 {
+        gpu.launch_kernel_async(&kernel, &config, stream);
+    }
+    gpu.memcpy_dtod_async(bufs.out.addr(), bufs.c.addr(), bufs.c.size_bytes(), stream);
+    let graph = match gpu.end_capture(stream) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("gpusim: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match graph.save(path) {
+        Ok(()) => println!("Captured {} graph nodes into {}", graph.nodes.len(), path),
+        Err(e) => eprintln!("gpusim: cannot write {}: {}", path, e),
+    }
+}
+
+/// `gpusim replay <graph.json> [N]`: load a graph, bind the built-in
+/// kernels, and launch it N times back to back.
+fn replay(path: &str, times: u32) {
+    let mut graph = match Graph::load(path) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("gpusim: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut gpu = GPU::h100();
+    let bufs = alloc_buffers(&mut gpu);
+    init_inputs(&mut gpu, &bufs);
+    if let Err(e) = graph.bind_kernel(&vec_add_kernel(bufs)) {
+        eprintln!("gpusim: {}", e);
+        std::process::exit(1);
+    }
+    let exec = match graph.instantiate() {
+        Ok(exec) => exec,
+        Err(e) => {
+            eprintln!("gpusim: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let stream = gpu.create_stream();
+    let (start, end) = (gpu.create_event(), gpu.create_event());
+    gpu.record_event(start, stream);
+    for _ in 0..times {
+        gpu.launch_graph(&exec, stream);
+    }
+    gpu.record_event(end, stream);
+    let launches = gpu.synchronize();
+
+    let ms = gpu.event_elapsed(start, end).unwrap_or(0.0);
+    println!(
+        "Replayed {} ({} nodes) {} times: {} kernel launches in {:.3} ms ({:.3} ms per replay)",
+        path,
+        graph.nodes.len(),
+        times,
+        launches.len(),
+        ms,
+        ms / times.max(1) as f64,
+    );
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("capture") => {
+            capture(args.get(2).map_or("graph.json", String::as_str));
+            return;
+        }
+        Some("replay") => {
+            let Some(path) = args.get(2) else {
+                eprintln!("usage: gpusim replay <graph.json> [N]");
+                std::process::exit(2);
+            };
+            let times = args.get(3).and_then(|n| n.parse().ok()).unwrap_or(1);
+            replay(path, times);
+            return;
+        }
+        _ => {}
+    }
+
     // -----------------------------------------------------------------------
     // Single-GPU vector addition demo
     // -----------------------------------------------------------------------
@@ -18,31 +155,10 @@ fn main() {
         gpu.hbm.size_bytes / (1024 * 1024 * 1024),
     );
 
-    // 32 768 elements → 256 blocks of 128 threads.
-    // Large enough that the SM heatmap has time to animate in the visualiser.
-    let n: u32 = 32_768;
-    let stride = std::mem::size_of::<f32>();
-
-    let base_a: usize = 0;
-    let base_b: usize = n as usize * stride;
-    let base_c: usize = 2 * n as usize * stride;
-
-    for i in 0..n as usize {
-        let a_val: f32 = i as f32;
-        let b_val: f32 = (n as usize - i) as f32;
-        gpu.hbm.write(base_a + i * stride, &a_val.to_le_bytes());
-        gpu.hbm.write(base_b + i * stride, &b_val.to_le_bytes());
-    }
-
-    let kernel = Kernel::new("vec_add", move |ctx| {
-        let i = ctx.global_id() as usize;
-        if i >= n as usize {
-            return;
-        }
-        let a = f32::from_le_bytes(ctx.gmem.read(base_a + i * stride, 4).try_into().unwrap());
-        let b = f32::from_le_bytes(ctx.gmem.read(base_b + i * stride, 4).try_into().unwrap());
-        ctx.gmem.write(base_c + i * stride, &(a + b).to_le_bytes());
-    });
+    let n = N;
+    let bufs = alloc_buffers(&mut gpu);
+    init_inputs(&mut gpu, &bufs);
+    let kernel = vec_add_kernel(bufs);
 
     let threads_per_block = 128u32;
     let num_blocks = n.div_ceil(threads_per_block);
@@ -57,9 +173,7 @@ fn main() {
 
     // Verify results (single-GPU run only)
     let mut all_correct = true;
-    for i in 0..n as usize {
-        let bytes = gpu.hbm.read(base_c + i * stride, 4);
-        let c = f32::from_le_bytes(bytes.try_into().unwrap());
+    for (i, c) in bufs.c.copy_to_host(&mut gpu).into_iter().enumerate() {
         if (c - n as f32).abs() > 1e-5 {
             println!("MISMATCH at i={}: got {}, expected {}", i, c, n);
             all_correct = false;
@@ -69,14 +183,36 @@ fn main() {
         println!("Verification PASSED: all {} results correct (each = {})", n, n);
     }
     println!(
-        "Stats: {} blocks | {} warps | {} threads | occupancy={:.1}% (limited by {}) | policy={}",
+        "Stats: {} blocks | {} warps | {} threads | occupancy={:.1}% (limited by {}, achieved {:.1}%) | policy={}",
         stats.blocks_executed,
         stats.warps_executed,
         stats.threads_executed,
         stats.theoretical_occupancy * 100.0,
         stats.occupancy_limiter,
+        stats.achieved_occupancy * 100.0,
         stats.scheduling_policy,
     );
+    println!(
+        "Timing: {} cycles | {} warp instructions | ~{:.2} µs at {} MHz",
+        stats.total_cycles,
+        stats.instructions_issued,
+        stats.wall_time_us,
+        gpu.sm_config.clock_mhz,
+    );
+    println!(
+        "SIMT:   {:.1}% lane efficiency | {} divergent branches in {} warps",
+        stats.simt_efficiency * 100.0,
+        stats.divergent_branches,
+        stats.divergent_warps,
+    );
+    let mem = gpu.mem_info();
+    println!(
+        "Memory: {} allocations | {:.2} MB used | {:.2} MB peak | {:.1}% fragmentation",
+        mem.allocations,
+        mem.used as f64 / (1024.0 * 1024.0),
+        mem.peak as f64 / (1024.0 * 1024.0),
+        mem.fragmentation * 100.0,
+    );
 
     // -----------------------------------------------------------------------
     // Multi-GPU cluster demo — runs in an infinite loop so the visualiser
@@ -0,0 +1,573 @@
+//! Unified (managed) memory with on-demand page migration.
+//!
+//! A managed allocation (cf. cudaMallocManaged) is one address range that
+//! both the host and the device use. Its contents live in HBM like any other
+//! allocation, but the model tracks where each `MANAGED_PAGE_SIZE` page is
+//! resident and charges the migrations a real driver would perform:
+//!   - a device access to a page that is not resident on the device faults:
+//!     the warp waits `fault_latency_ns` for the driver, then for the page to
+//!     cross the host link (pinned bandwidth, one page at a time per
+//!     direction). Faults of different warps overlap; their transfers queue.
+//!   - a host access to a page resident only on the device faults the same
+//!     way in the other direction, one page after another.
+//!   - the first touch of a page anywhere populates it there: it faults on the
+//!     device, but nothing crosses the link.
+//!
+//! Hints change this per page (cf. cudaMemAdvise, cudaMemPrefetchAsync):
+//!   - read-mostly pages are duplicated rather than moved on a read; a write
+//!     by either side invalidates the other copy
+//!   - pages with a preferred location stay there; the other side reads and
+//!     writes them remotely over the link (latency plus the bytes touched)
+//!     instead of migrating them
+//!   - a prefetch moves a range to one side in bulk, as a single copy, so
+//!     later accesses do not fault
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use crate::gpu::GPU;
+use crate::memory::{DeviceBuffer, DeviceValue};
+use crate::pcie::{copy_cycles, CopyDirection, PcieConfig};
+
+/// Bytes per managed page, the unit of residency and migration
+pub const MANAGED_PAGE_SIZE: usize = 64 * 1024;
+
+/// Time the driver takes to service a page fault before the page moves
+pub const PAGE_FAULT_LATENCY_NS: u64 = 20_000;
+
+/// Where a managed page can be resident.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemLocation {
+    Host,
+    Device,
+}
+
+impl MemLocation {
+    /// Direction of a migration to this location
+    pub fn copy_direction(self) -> CopyDirection {
+        match self {
+            MemLocation::Device => CopyDirection::HostToDevice,
+            MemLocation::Host => CopyDirection::DeviceToHost,
+        }
+    }
+}
+
+/// Usage hint for a range of managed memory (cf. cudaMemAdvise).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemAdvice {
+    /// Reads duplicate pages instead of migrating them
+    SetReadMostly,
+    UnsetReadMostly,
+    /// Keep pages at the location; the other side accesses them remotely
+    SetPreferredLocation(MemLocation),
+    UnsetPreferredLocation,
+}
+
+/// Page fault and migration counts of managed memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ManagedStats {
+    /// Device accesses to pages not resident on the device, per page and
+    /// warp instruction
+    pub gpu_page_faults: u64,
+    /// Host accesses to pages not resident on the host, per page
+    pub cpu_page_faults: u64,
+    /// Bytes migrated or duplicated from host to device, prefetches included
+    pub bytes_htod: u64,
+    /// Bytes migrated or duplicated from device to host, prefetches included
+    pub bytes_dtoh: u64,
+    /// Accesses served remotely over the link instead of migrating: per page
+    /// and warp instruction on the device, per page on the host
+    pub remote_accesses: u64,
+}
+
+impl ManagedStats {
+    pub fn page_faults(&self) -> u64 {
+        self.gpu_page_faults + self.cpu_page_faults
+    }
+
+    pub fn migrated_bytes(&self) -> u64 {
+        self.bytes_htod + self.bytes_dtoh
+    }
+}
+
+impl std::ops::AddAssign for ManagedStats {
+    fn add_assign(&mut self, other: ManagedStats) {
+        self.gpu_page_faults += other.gpu_page_faults;
+        self.cpu_page_faults += other.cpu_page_faults;
+        self.bytes_htod += other.bytes_htod;
+        self.bytes_dtoh += other.bytes_dtoh;
+        self.remote_accesses += other.remote_accesses;
+    }
+}
+
+/// Residency and hints of one managed page. A page resident nowhere has not
+/// been touched yet.
+#[derive(Debug, Clone, Copy, Default)]
+struct Page {
+    host: bool,
+    device: bool,
+    /// Cycle from which the device copy is usable (a migration in flight)
+    ready_at: u64,
+    read_mostly: bool,
+    preferred: Option<MemLocation>,
+}
+
+#[derive(Debug, Clone)]
+struct ManagedAlloc {
+    len: usize,
+    pages: Vec<Page>,
+}
+
+impl ManagedAlloc {
+    /// Bytes of page `page` (the last page may be partial)
+    fn page_bytes(&self, page: usize) -> usize {
+        MANAGED_PAGE_SIZE.min(self.len - page * MANAGED_PAGE_SIZE)
+    }
+
+    /// Bytes of `[offset, offset + len)`, offsets into the allocation, that
+    /// fall in page `page`
+    fn bytes_in_page(&self, page: usize, offset: usize, len: usize) -> usize {
+        let start = page * MANAGED_PAGE_SIZE;
+        let end = start + self.page_bytes(page);
+        (offset + len).min(end).saturating_sub(offset.max(start))
+    }
+}
+
+/// Where a range lies in a managed allocation.
+#[derive(Debug, Clone, Copy)]
+struct PageSpan {
+    /// Offset of the range's first byte into the allocation
+    offset: usize,
+    /// First and last page the range covers
+    first: usize,
+    last: usize,
+}
+
+/// Page residency of every managed allocation of a GPU, and the migrations
+/// scheduled on the host link so far.
+#[derive(Debug, Clone)]
+pub struct ManagedMemory {
+    /// Allocations by start address
+    allocs: BTreeMap<usize, ManagedAlloc>,
+    /// Link pages migrate over
+    link: PcieConfig,
+    /// Driver time per page fault
+    pub fault_latency_ns: u64,
+    /// Cycle from which the host-to-device and device-to-host halves of the
+    /// link are free for the next migration
+    link_free_at: [u64; 2],
+    /// Totals since the GPU was created
+    stats: ManagedStats,
+}
+
+impl ManagedMemory {
+    pub fn new(link: PcieConfig) -> Self {
+        ManagedMemory {
+            allocs: BTreeMap::new(),
+            link,
+            fault_latency_ns: PAGE_FAULT_LATENCY_NS,
+            link_free_at: [0; 2],
+            stats: ManagedStats::default(),
+        }
+    }
+
+    pub fn set_link(&mut self, link: PcieConfig) {
+        self.link = link;
+    }
+
+    /// Totals since the GPU was created
+    pub fn stats(&self) -> ManagedStats {
+        self.stats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocs.is_empty()
+    }
+
+    /// Track `len` bytes at `addr` as a new managed allocation, resident
+    /// nowhere.
+    pub fn register(&mut self, addr: usize, len: usize) {
+        let pages = vec![Page::default(); len.div_ceil(MANAGED_PAGE_SIZE)];
+        self.allocs.insert(addr, ManagedAlloc { len, pages });
+    }
+
+    /// Stop tracking the allocation at `addr`. False if it was not managed.
+    pub fn unregister(&mut self, addr: usize) -> bool {
+        self.allocs.remove(&addr).is_some()
+    }
+
+    /// The managed allocation holding all of `[addr, addr + len)`, if one
+    /// does, with the offset of `addr` into it and the first and last page of
+    /// the range.
+    fn pages_of(&mut self, addr: usize, len: usize) -> Option<(&mut ManagedAlloc, PageSpan)> {
+        let (&base, alloc) = self.allocs.range_mut(..=addr).next_back()?;
+        if alloc.pages.is_empty() || addr + len > base + alloc.len {
+            return None;
+        }
+        let offset = addr - base;
+        let first = offset / MANAGED_PAGE_SIZE;
+        let last = (offset + len.max(1) - 1) / MANAGED_PAGE_SIZE;
+        Some((alloc, PageSpan { offset, first, last }))
+    }
+
+    /// True if `[addr, addr + len)` lies inside one managed allocation.
+    pub fn contains(&self, addr: usize, len: usize) -> bool {
+        self.allocs
+            .range(..=addr)
+            .next_back()
+            .is_some_and(|(&base, alloc)| addr + len <= base + alloc.len)
+    }
+
+    /// Apply `advice` to the pages of `[addr, addr + len)`. False if the
+    /// range is not managed.
+    pub fn advise(&mut self, addr: usize, len: usize, advice: MemAdvice) -> bool {
+        let Some((alloc, span)) = self.pages_of(addr, len) else { return false };
+        for p in &mut alloc.pages[span.first..=span.last] {
+            match advice {
+                MemAdvice::SetReadMostly => p.read_mostly = true,
+                MemAdvice::UnsetReadMostly => p.read_mostly = false,
+                MemAdvice::SetPreferredLocation(location) => p.preferred = Some(location),
+                MemAdvice::UnsetPreferredLocation => p.preferred = None,
+            }
+        }
+        true
+    }
+
+    /// Make the pages of `[addr, addr + len)` resident at `location`.
+    /// Returns the bytes that have to cross the link, which the caller
+    /// schedules as one copy; the pages count as resident right away. None if
+    /// the range is not managed.
+    pub fn prefetch(&mut self, addr: usize, len: usize, location: MemLocation) -> Option<usize> {
+        let (alloc, span) = self.pages_of(addr, len)?;
+        let mut bytes = 0;
+        for page in span.first..=span.last {
+            let page_bytes = alloc.page_bytes(page);
+            let p = &mut alloc.pages[page];
+            match location {
+                MemLocation::Device if !p.device => {
+                    if p.host {
+                        bytes += page_bytes;
+                    }
+                    p.device = true;
+                    p.host = p.host && p.read_mostly;
+                }
+                MemLocation::Host if !p.host => {
+                    if p.device {
+                        bytes += page_bytes;
+                    }
+                    p.host = true;
+                    p.device = p.device && p.read_mostly;
+                }
+                _ => {}
+            }
+        }
+        match location {
+            MemLocation::Device => self.stats.bytes_htod += bytes as u64,
+            MemLocation::Host => self.stats.bytes_dtoh += bytes as u64,
+        }
+        Some(bytes)
+    }
+
+    /// Resolve a device instruction issued at cycle `now` whose lanes access
+    /// `ranges` (address, size), counting faults and migrations in `stats`.
+    /// Returns the cycle the last page it needs is usable, if that is after
+    /// `now`. Accesses outside managed memory are ignored.
+    pub fn device_access(
+        &mut self,
+        ranges: &[(usize, usize)],
+        write: bool,
+        now: u64,
+        clock_mhz: u32,
+        stats: &mut ManagedStats,
+    ) -> Option<u64> {
+        // Bytes the instruction touches in each managed page: (base, page, bytes)
+        let mut touched: Vec<(usize, usize, usize)> = Vec::new();
+        for &(addr, size) in ranges {
+            let Some((&base, alloc)) = self.allocs.range(..=addr).next_back() else { continue };
+            if addr >= base + alloc.len {
+                continue;
+            }
+            // A lane's range may cross into the next page: count the bytes it
+            // touches in every page it covers
+            let offset = addr - base;
+            let len = size.max(1).min(alloc.len - offset);
+            let last = (offset + len - 1) / MANAGED_PAGE_SIZE;
+            for page in offset / MANAGED_PAGE_SIZE..=last {
+                let bytes = alloc.bytes_in_page(page, offset, len);
+                match touched.iter_mut().find(|t| t.0 == base && t.1 == page) {
+                    Some(t) => t.2 += bytes,
+                    None => touched.push((base, page, bytes)),
+                }
+            }
+        }
+
+        let fault_cycles = self.fault_latency_ns * clock_mhz as u64 / 1000;
+        let link_latency = self.link.latency_ns * clock_mhz as u64 / 1000;
+        let bandwidth = self.link.pinned_bandwidth_bps;
+        let mut access = ManagedStats::default();
+        let mut ready = now;
+        for (base, page, bytes) in touched {
+            let alloc = self.allocs.get_mut(&base).expect("managed allocation");
+            let page_bytes = alloc.page_bytes(page);
+            let p = &mut alloc.pages[page];
+            if p.device {
+                if write {
+                    // Invalidates a read-mostly duplicate on the host
+                    p.host = false;
+                }
+                ready = ready.max(p.ready_at);
+                continue;
+            }
+            if p.host && p.preferred == Some(MemLocation::Host) {
+                // Writes travel device to host, reads the other way round
+                let direction = write as usize;
+                let cycles = copy_cycles(bytes, bandwidth, 0, clock_mhz);
+                let start = now.max(self.link_free_at[direction]);
+                self.link_free_at[direction] = start + cycles;
+                access.remote_accesses += 1;
+                ready = ready.max(start + cycles + link_latency);
+                continue;
+            }
+            access.gpu_page_faults += 1;
+            p.ready_at = if p.host {
+                let cycles = copy_cycles(page_bytes, bandwidth, 0, clock_mhz);
+                let start = (now + fault_cycles).max(self.link_free_at[0]);
+                self.link_free_at[0] = start + cycles;
+                access.bytes_htod += page_bytes as u64;
+                start + cycles
+            } else {
+                now + fault_cycles
+            };
+            p.device = true;
+            p.host = p.host && p.read_mostly && !write;
+            ready = ready.max(p.ready_at);
+        }
+        self.stats += access;
+        *stats += access;
+        (ready > now).then_some(ready)
+    }
+
+    /// Resolve a host access to `[addr, addr + len)` starting at cycle
+    /// `now`. The host handles its faults one page after another; returns
+    /// the cycle it is done. Panics if the range is not managed.
+    pub fn host_access(
+        &mut self,
+        addr: usize,
+        len: usize,
+        write: bool,
+        now: u64,
+        clock_mhz: u32,
+    ) -> u64 {
+        let fault_cycles = self.fault_latency_ns * clock_mhz as u64 / 1000;
+        let link_latency = self.link.latency_ns * clock_mhz as u64 / 1000;
+        let bandwidth = self.link.pinned_bandwidth_bps;
+        let mut link_free_at = self.link_free_at[1];
+        let (alloc, span) = self
+            .pages_of(addr, len)
+            .unwrap_or_else(|| panic!("{:#x}+{} is not managed memory", addr, len));
+        let mut access = ManagedStats::default();
+        let mut now = now;
+        for page in span.first..=span.last {
+            let page_bytes = alloc.page_bytes(page);
+            let p = &mut alloc.pages[page];
+            if p.host || !p.device {
+                if write {
+                    // Invalidates a read-mostly duplicate on the device
+                    p.device = false;
+                }
+                p.host = true;
+                continue;
+            }
+            if p.preferred == Some(MemLocation::Device) {
+                // Only the bytes of the range that fall in this page
+                let bytes = alloc.bytes_in_page(page, span.offset, len);
+                now += link_latency + copy_cycles(bytes, bandwidth, 0, clock_mhz);
+                access.remote_accesses += 1;
+                continue;
+            }
+            access.cpu_page_faults += 1;
+            access.bytes_dtoh += page_bytes as u64;
+            let start = (now + fault_cycles).max(link_free_at);
+            link_free_at = start + copy_cycles(page_bytes, bandwidth, 0, clock_mhz);
+            now = link_free_at;
+            p.host = true;
+            p.device = p.read_mostly && !write;
+        }
+        self.link_free_at[1] = link_free_at;
+        self.stats += access;
+        now
+    }
+}
+
+/// Typed handle to `len` managed `T`s at address `addr`, usable by kernels
+/// (like a `DeviceBuffer`) and by the host through the GPU it belongs to.
+#[derive(Debug)]
+pub struct ManagedBuffer<T> {
+    addr: usize,
+    len: usize,
+    _elem: PhantomData<T>,
+}
+
+impl<T> Clone for ManagedBuffer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ManagedBuffer<T> {}
+
+impl<T: DeviceValue> ManagedBuffer<T> {
+    pub(crate) const fn new(addr: usize, len: usize) -> Self {
+        ManagedBuffer { addr, len, _elem: PhantomData }
+    }
+
+    /// Address of the first element
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Number of elements
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size of the buffer in bytes
+    pub fn size_bytes(&self) -> usize {
+        self.len * T::SIZE
+    }
+
+    /// Address of element `i`. Panics if `i` is out of bounds.
+    pub fn at(&self, i: usize) -> usize {
+        assert!(i < self.len, "index {} out of bounds for a buffer of {} elements", i, self.len);
+        self.addr + i * T::SIZE
+    }
+
+    /// The same memory as a plain device buffer
+    pub fn as_device(&self) -> DeviceBuffer<T> {
+        DeviceBuffer::new(self.addr, self.len)
+    }
+
+    /// Write `data` into the start of the buffer from the host, faulting in
+    /// pages resident on the device. Panics if `data` is longer than the
+    /// buffer.
+    pub fn write(&self, gpu: &mut GPU, data: &[T]) {
+        assert!(
+            data.len() <= self.len,
+            "writing {} elements into a buffer of {}",
+            data.len(),
+            self.len,
+        );
+        gpu.managed_host_access(self.addr, data.len() * T::SIZE, true);
+        gpu.hbm.store_slice(self.addr, data);
+    }
+
+    /// Read the whole buffer from the host, faulting in pages resident on
+    /// the device.
+    pub fn read(&self, gpu: &mut GPU) -> Vec<T> {
+        gpu.managed_host_access(self.addr, self.size_bytes(), false);
+        gpu.hbm.load_slice(self.addr, self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionStats;
+    use crate::kernel::{Dim3, Kernel, LaunchConfig};
+    use crate::scheduler::SchedulingPolicy;
+
+    const BASE: usize = 1 << 20;
+    const CLOCK_MHZ: u32 = 1000;
+
+    fn two_pages() -> ManagedMemory {
+        let mut managed = ManagedMemory::new(PcieConfig::gen5_x16());
+        managed.register(BASE, 2 * MANAGED_PAGE_SIZE);
+        managed
+    }
+
+    #[test]
+    fn device_access_across_a_page_boundary_faults_both_pages() {
+        let mut managed = two_pages();
+        let mut stats = ManagedStats::default();
+        let ranges = [(BASE + MANAGED_PAGE_SIZE - 4, 8)];
+        assert!(managed.device_access(&ranges, false, 0, CLOCK_MHZ, &mut stats).is_some());
+        assert_eq!(stats.gpu_page_faults, 2);
+        // First touch populates the pages on the device: nothing migrates
+        assert_eq!(stats.bytes_htod, 0);
+        assert!(managed.device_access(&ranges, false, 0, CLOCK_MHZ, &mut stats).is_some());
+        assert_eq!(stats.gpu_page_faults, 2);
+    }
+
+    #[test]
+    fn device_read_migrates_a_host_page_once() {
+        let mut managed = two_pages();
+        managed.host_access(BASE, 16, true, 0, CLOCK_MHZ);
+        let mut stats = ManagedStats::default();
+        let ready = managed.device_access(&[(BASE, 16)], false, 0, CLOCK_MHZ, &mut stats);
+        assert!(ready.is_some());
+        assert_eq!(stats.gpu_page_faults, 1);
+        assert_eq!(stats.bytes_htod, MANAGED_PAGE_SIZE as u64);
+        let later = ready.unwrap();
+        assert_eq!(managed.device_access(&[(BASE, 16)], false, later, CLOCK_MHZ, &mut stats), None);
+    }
+
+    #[test]
+    fn remote_host_access_pays_only_the_bytes_touched_in_each_page() {
+        let mut managed = two_pages();
+        let len = 2 * MANAGED_PAGE_SIZE;
+        managed.advise(BASE, len, MemAdvice::SetPreferredLocation(MemLocation::Device));
+        managed.prefetch(BASE, len, MemLocation::Device);
+        let done = managed.host_access(BASE + MANAGED_PAGE_SIZE - 32, 64, false, 0, CLOCK_MHZ);
+        let link = PcieConfig::gen5_x16();
+        let per_page = link.latency_ns * CLOCK_MHZ as u64 / 1000
+            + copy_cycles(32, link.pinned_bandwidth_bps, 0, CLOCK_MHZ);
+        assert_eq!(done, 2 * per_page);
+        assert_eq!(managed.stats().remote_accesses, 2);
+        assert_eq!(managed.stats().cpu_page_faults, 0);
+    }
+
+    #[test]
+    fn prefetch_counts_only_pages_that_move() {
+        let mut managed = two_pages();
+        managed.host_access(BASE, 16, true, 0, CLOCK_MHZ);
+        let len = 2 * MANAGED_PAGE_SIZE;
+        assert_eq!(managed.prefetch(BASE, len, MemLocation::Device), Some(MANAGED_PAGE_SIZE));
+        assert_eq!(managed.prefetch(BASE, len, MemLocation::Device), Some(0));
+        assert_eq!(managed.prefetch(BASE + len, 1, MemLocation::Device), None);
+    }
+
+    /// Run a kernel reading two pages of managed memory the host wrote,
+    /// optionally prefetching them to the device first.
+    fn read_host_written_pages(prefetch: bool) -> ExecutionStats {
+        let mut gpu = GPU::h100();
+        let n = 2 * MANAGED_PAGE_SIZE / 4;
+        let buf = gpu.alloc_managed::<f32>(n).unwrap();
+        buf.write(&mut gpu, &vec![1.0; n]);
+        if prefetch {
+            gpu.mem_prefetch(buf.addr(), buf.size_bytes(), MemLocation::Device).unwrap();
+        }
+        let kernel = Kernel::new("read", move |ctx| {
+            let _: f32 = ctx.gmem.load(buf.at(ctx.global_id() as usize));
+        });
+        let config = LaunchConfig::new(Dim3::x(n as u32 / 256), Dim3::x(256));
+        gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto)
+    }
+
+    #[test]
+    fn kernels_fault_host_pages_over_unless_they_are_prefetched() {
+        let faulting = read_host_written_pages(false);
+        assert_eq!(faulting.managed.gpu_page_faults, 2);
+        assert_eq!(faulting.managed.bytes_htod, 2 * MANAGED_PAGE_SIZE as u64);
+
+        let prefetched = read_host_written_pages(true);
+        assert_eq!(prefetched.managed.page_faults(), 0);
+        assert_eq!(prefetched.managed.migrated_bytes(), 0);
+        assert!(prefetched.total_cycles < faulting.total_cycles);
+    }
+}
@@ -0,0 +1,218 @@
+//! Memcheck: an opt-in sanitizer for global memory accesses (cf.
+//! compute-sanitizer --tool memcheck).
+//!
+//! With `GPU::memcheck` set, every global memory access a thread makes is
+//! checked against the device allocator before it is performed:
+//!   - the address must be aligned to the accessed type,
+//!   - the whole access must fall inside one live allocation — otherwise it
+//!     is reported as a use-after-free if it hits a freed allocation, and as
+//!     out of bounds if not,
+//!   - every byte read must have been written since it was allocated.
+//!
+//! Errors are collected in `ExecutionStats::memcheck_errors` with the
+//! offending thread's coordinates and source line; the access itself still
+//! goes ahead. Memory obtained by picking addresses by hand rather than from
+//! `GPU::malloc` counts as out of bounds.
+use std::cell::RefCell;
+
+use crate::allocator::DeviceAllocator;
+use crate::kernel::Dim3;
+use crate::memory::HBM;
+use crate::timing::{InstrKind, LaneOp, Site};
+
+/// Errors printed when a kernel completes; the rest are only counted.
+pub const MAX_PRINTED_ERRORS: usize = 32;
+
+/// What was wrong with an access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemcheckErrorKind {
+    /// Outside every live allocation. `nearest` is the closest live
+    /// allocation, as (start address, size).
+    OutOfBounds { nearest: Option<(usize, usize)> },
+    /// Inside an allocation that has been freed, as (start address, size)
+    UseAfterFree { allocation: (usize, usize) },
+    /// Address not a multiple of the accessed type's size
+    Misaligned { align: usize },
+    /// Read of bytes never written since they were allocated
+    UninitializedRead,
+}
+
+/// One invalid global memory access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemcheckError {
+    pub kind: MemcheckErrorKind,
+    /// GlobalLoad, GlobalStore or GlobalAtomic
+    pub access: InstrKind,
+    pub addr: usize,
+    /// Access size in bytes
+    pub size: usize,
+    pub kernel: String,
+    pub block_idx: Dim3,
+    pub thread_idx: Dim3,
+    /// Call site in the kernel source
+    pub site: Site,
+}
+
+impl std::fmt::Display for MemcheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let access = match self.access {
+            InstrKind::GlobalStore => "write",
+            InstrKind::GlobalAtomic => "atomic",
+            _ => "read",
+        };
+        let what = match self.kind {
+            MemcheckErrorKind::UninitializedRead => "Uninitialized",
+            _ => "Invalid",
+        };
+        let (b, t) = (self.block_idx, self.thread_idx);
+        write!(
+            f,
+            "{} global {} of size {} at {} in kernel '{}' by thread ({},{},{}) in block ({},{},{}): address {:#x} ",
+            what, access, self.size, self.site, self.kernel, t.x, t.y, t.z, b.x, b.y, b.z, self.addr,
+        )?;
+        match self.kind {
+            MemcheckErrorKind::OutOfBounds { nearest: None } => {
+                write!(f, "is out of bounds (no live allocations)")
+            }
+            MemcheckErrorKind::OutOfBounds { nearest: Some((start, size)) } => {
+                if self.addr < start {
+                    write!(f, "is {} bytes before", start - self.addr)?;
+                } else if self.addr >= start + size {
+                    write!(f, "is {} bytes after", self.addr - (start + size))?;
+                } else {
+                    write!(f, "overruns the end of")?;
+                }
+                write!(f, " the allocation at {:#x} of {} bytes", start, size)
+            }
+            MemcheckErrorKind::UseAfterFree { allocation: (start, size) } => {
+                write!(f, "is in the freed allocation at {:#x} of {} bytes", start, size)
+            }
+            MemcheckErrorKind::Misaligned { align } => {
+                write!(f, "is misaligned (requires {}-byte alignment)", align)
+            }
+            MemcheckErrorKind::UninitializedRead => write!(f, "has not been written"),
+        }
+    }
+}
+
+/// Checks the global memory accesses of one dispatch unit.
+pub(crate) struct Memcheck<'a> {
+    allocator: &'a DeviceAllocator,
+    kernel: &'a str,
+    pub errors: RefCell<Vec<MemcheckError>>,
+}
+
+impl<'a> Memcheck<'a> {
+    pub fn new(allocator: &'a DeviceAllocator, kernel: &'a str) -> Self {
+        Memcheck { allocator, kernel, errors: RefCell::new(Vec::new()) }
+    }
+
+    /// Check the access `op` of a thread before it is performed. `align` is
+    /// the size of the accessed type (1 for raw bytes).
+    pub fn check(&self, hbm: &HBM, op: &LaneOp, align: usize, block_idx: Dim3, thread_idx: Dim3) {
+        let (access, addr, size) = (op.kind, op.addr, op.size as usize);
+        let kind = if addr % align != 0 {
+            Some(MemcheckErrorKind::Misaligned { align })
+        } else if self
+            .allocator
+            .find(addr)
+            .is_some_and(|(start, len)| addr + size.max(1) <= start + len)
+        {
+            let reads = access != InstrKind::GlobalStore;
+            (reads && !hbm.is_initialized(addr, size)).then_some(MemcheckErrorKind::UninitializedRead)
+        } else if let Some(allocation) = self.allocator.find_freed(addr) {
+            Some(MemcheckErrorKind::UseAfterFree { allocation })
+        } else {
+            let nearest = self.allocator.find(addr).or_else(|| self.allocator.nearest(addr));
+            Some(MemcheckErrorKind::OutOfBounds { nearest })
+        };
+        if let Some(kind) = kind {
+            self.errors.borrow_mut().push(MemcheckError {
+                kind,
+                access,
+                addr,
+                size,
+                kernel: self.kernel.to_string(),
+                block_idx,
+                thread_idx,
+                site: op.site,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::DEFAULT_ALIGNMENT;
+
+    fn load(addr: usize, size: usize) -> LaneOp {
+        LaneOp::mem(InstrKind::GlobalLoad, addr, size)
+    }
+
+    fn store(addr: usize, size: usize) -> LaneOp {
+        LaneOp::mem(InstrKind::GlobalStore, addr, size)
+    }
+
+    /// Check `op` against an allocator holding a 400-byte allocation at 0 and
+    /// a freed one at 512; the first 256 bytes of HBM are written.
+    fn check(op: LaneOp, align: usize) -> Vec<MemcheckErrorKind> {
+        let mut allocator = DeviceAllocator::new(4096);
+        allocator.alloc(400, DEFAULT_ALIGNMENT).unwrap();
+        let freed = allocator.alloc(64, DEFAULT_ALIGNMENT).unwrap();
+        allocator.free(freed).unwrap();
+        let mut hbm = HBM::new(4096);
+        hbm.store_slice(0, &[0u32; 64]);
+        let memcheck = Memcheck::new(&allocator, "k");
+        memcheck.check(&hbm, &op, align, Dim3::new(0, 0, 0), Dim3::new(0, 0, 0));
+        memcheck.errors.into_inner().into_iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn accesses_inside_a_live_allocation_pass() {
+        assert_eq!(check(load(252, 4), 4), []);
+        assert_eq!(check(store(396, 4), 4), []);
+    }
+
+    #[test]
+    fn reads_of_unwritten_bytes_are_reported() {
+        // Half written, half not
+        assert_eq!(check(load(254, 4), 2), [MemcheckErrorKind::UninitializedRead]);
+        assert_eq!(check(load(256, 4), 4), [MemcheckErrorKind::UninitializedRead]);
+    }
+
+    #[test]
+    fn misaligned_accesses_are_reported_first() {
+        assert_eq!(check(load(2, 4), 4), [MemcheckErrorKind::Misaligned { align: 4 }]);
+        assert_eq!(check(store(401, 4), 4), [MemcheckErrorKind::Misaligned { align: 4 }]);
+    }
+
+    #[test]
+    fn accesses_outside_live_allocations_are_classified() {
+        let overrun = MemcheckErrorKind::OutOfBounds { nearest: Some((0, 400)) };
+        assert_eq!(check(store(400, 4), 4), [overrun]);
+        // Straddling the end of the allocation
+        assert_eq!(check(store(398, 4), 2), [overrun]);
+        let freed = MemcheckErrorKind::UseAfterFree { allocation: (512, 64) };
+        assert_eq!(check(load(520, 4), 4), [freed]);
+    }
+
+    #[test]
+    fn errors_describe_the_access() {
+        let mut allocator = DeviceAllocator::new(4096);
+        allocator.alloc(400, DEFAULT_ALIGNMENT).unwrap();
+        let memcheck = Memcheck::new(&allocator, "overrun");
+        let op = load(400, 4);
+        memcheck.check(&HBM::new(4096), &op, 4, Dim3::new(0, 0, 0), Dim3::new(100, 0, 0));
+        let error = memcheck.errors.into_inner().remove(0);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Invalid global read of size 4 at {} in kernel 'overrun' by thread \
+                 (100,0,0) in block (0,0,0): address 0x190 is 0 bytes after the \
+                 allocation at 0x0 of 400 bytes",
+                op.site,
+            ),
+        );
+    }
+}
@@ -1,68 +1,595 @@
 /// Memory hierarchy simulation.
 /// Models the three tiers of GPU memory:
 ///   - SMEM: per-SM on-chip shared memory (in sm.rs)
-///   - L2Cache: shared across all SMs
-///   - HBM: main high-bandwidth memory
-
+///   - L2Cache: shared across all SMs, a tag model of the cache (cache.rs)
+///   - HBM: main high-bandwidth memory, with a channel and bank timing model
+///     (dram.rs)
+///
+/// HBM keeps its bytes in a `PagedStore`: sparse 4 KB pages allocated on
+/// first write. Memory holds raw little-endian bytes. `DeviceValue` types
+/// can be read and written directly with the typed `load` / `store`
+/// accessors, and `DeviceBuffer<T>` is a typed host-side handle to an array
+/// in HBM.
 use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::cache::{CacheAccess, CacheConfig, CacheStats, SectoredCache};
+use crate::dram::{Dram, DramConfig};
+use crate::gpu::GPU;
+use crate::pcie::CopyDirection;
+
+/// Plain values that can be stored in device memory as little-endian bytes.
+pub trait DeviceValue: Copy {
+    /// Size in bytes
+    const SIZE: usize;
+
+    fn from_le(bytes: &[u8]) -> Self;
+    /// Write the value's `SIZE` bytes into `out`.
+    fn write_le(self, out: &mut [u8]);
+
+    fn to_le(self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::SIZE];
+        self.write_le(&mut bytes);
+        bytes
+    }
+}
+
+impl DeviceValue for u8 {
+    const SIZE: usize = 1;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out[0] = self;
+    }
+}
+
+impl DeviceValue for i8 {
+    const SIZE: usize = 1;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        bytes[0] as i8
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out[0] = self as u8;
+    }
+}
+
+impl DeviceValue for u16 {
+    const SIZE: usize = 2;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        u16::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for i16 {
+    const SIZE: usize = 2;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        i16::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for u32 {
+    const SIZE: usize = 4;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for i32 {
+    const SIZE: usize = 4;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        i32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for u64 {
+    const SIZE: usize = 8;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for i64 {
+    const SIZE: usize = 8;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        i64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for f32 {
+    const SIZE: usize = 4;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        f32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl DeviceValue for f64 {
+    const SIZE: usize = 8;
+
+    fn from_le(bytes: &[u8]) -> Self {
+        f64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes())
+    }
+}
+
+/// Decode consecutive values from little-endian bytes.
+pub fn decode_slice<T: DeviceValue>(bytes: &[u8]) -> Vec<T> {
+    bytes.chunks_exact(T::SIZE).map(T::from_le).collect()
+}
+
+/// Encode values as consecutive little-endian bytes.
+pub fn encode_slice<T: DeviceValue>(values: &[T]) -> Vec<u8> {
+    let mut bytes = vec![0; values.len() * T::SIZE];
+    for (&v, out) in values.iter().zip(bytes.chunks_exact_mut(T::SIZE)) {
+        v.write_le(out);
+    }
+    bytes
+}
+
+/// Size of a page of the sparse backing store in bytes
+pub const PAGE_SIZE: usize = 4096;
+
+/// One page of backing memory, with a bit per byte recording whether it has
+/// been written.
+struct Page {
+    data: Box<[u8; PAGE_SIZE]>,
+    written: Box<[u64; PAGE_SIZE / 64]>,
+}
+
+impl Page {
+    fn new() -> Self {
+        Page { data: Box::new([0; PAGE_SIZE]), written: Box::new([0; PAGE_SIZE / 64]) }
+    }
+
+    /// Bit masks covering bytes [from, to) of the page, as (word, mask).
+    fn masks(from: usize, to: usize) -> impl Iterator<Item = (usize, u64)> {
+        let mut i = from;
+        std::iter::from_fn(move || {
+            if i >= to {
+                return None;
+            }
+            let (word, bit) = (i / 64, i % 64);
+            let n = (64 - bit).min(to - i);
+            let mask = if n == 64 { u64::MAX } else { ((1 << n) - 1) << bit };
+            i += n;
+            Some((word, mask))
+        })
+    }
+
+    /// Set (or clear) the written bits of bytes [from, to) of the page.
+    fn mark(&mut self, from: usize, to: usize, written: bool) {
+        for (word, mask) in Self::masks(from, to) {
+            if written {
+                self.written[word] |= mask;
+            } else {
+                self.written[word] &= !mask;
+            }
+        }
+    }
+
+    fn is_written(&self, from: usize, to: usize) -> bool {
+        Self::masks(from, to).all(|(word, mask)| self.written[word] & mask == mask)
+    }
+}
+
+/// Sparse byte-addressable memory: fixed-size pages, allocated on first
+/// write. Unwritten memory reads as zero, so a huge address space costs host
+/// memory only for the pages actually used.
+#[derive(Default)]
+pub struct PagedStore {
+    pages: HashMap<usize, Page>,
+}
+
+impl PagedStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split [addr, addr + len) at page boundaries into (page number, offset
+    /// in page, offset in range, length) chunks.
+    fn chunks(addr: usize, len: usize) -> impl Iterator<Item = (usize, usize, usize, usize)> {
+        let mut done = 0;
+        std::iter::from_fn(move || {
+            if done == len {
+                return None;
+            }
+            let a = addr + done;
+            let (page, offset) = (a / PAGE_SIZE, a % PAGE_SIZE);
+            let n = (PAGE_SIZE - offset).min(len - done);
+            let chunk = (page, offset, done, n);
+            done += n;
+            Some(chunk)
+        })
+    }
+
+    /// Fill `buf` with the bytes starting at `addr`.
+    pub fn read_into(&self, addr: usize, buf: &mut [u8]) {
+        for (page, offset, at, n) in Self::chunks(addr, buf.len()) {
+            let dst = &mut buf[at..at + n];
+            match self.pages.get(&page) {
+                Some(p) => dst.copy_from_slice(&p.data[offset..offset + n]),
+                None => dst.fill(0),
+            }
+        }
+    }
+
+    pub fn read(&self, addr: usize, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        self.read_into(addr, &mut buf);
+        buf
+    }
+
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) {
+        for (page, offset, at, n) in Self::chunks(addr, bytes.len()) {
+            let p = self.pages.entry(page).or_insert_with(Page::new);
+            p.data[offset..offset + n].copy_from_slice(&bytes[at..at + n]);
+            p.mark(offset, offset + n, true);
+        }
+    }
+
+    /// Whether every byte of [addr, addr + len) has been written since the
+    /// range was last discarded.
+    pub fn is_initialized(&self, addr: usize, len: usize) -> bool {
+        Self::chunks(addr, len).all(|(page, offset, _, n)| {
+            self.pages.get(&page).is_some_and(|p| p.is_written(offset, offset + n))
+        })
+    }
+
+    /// Forget the contents of [addr, addr + len): they read as zero and
+    /// count as uninitialized until written again. Pages no longer holding
+    /// any written byte are released.
+    pub fn discard(&mut self, addr: usize, len: usize) {
+        for (page, offset, _, n) in Self::chunks(addr, len) {
+            let Some(p) = self.pages.get_mut(&page) else {
+                continue;
+            };
+            p.data[offset..offset + n].fill(0);
+            p.mark(offset, offset + n, false);
+            if p.written.iter().all(|&w| w == 0) {
+                self.pages.remove(&page);
+            }
+        }
+    }
+
+    /// Host memory used for backing pages, in bytes
+    pub fn resident_bytes(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+}
 
 /// Shared L2 cache across all SMs (~50MB on H100).
 /// Slower than SMEM but shared across the entire GPU.
-/// Uses a sparse map to avoid eagerly allocating the full capacity.
+/// Every global memory access of the timing model looks up its sectors here
+/// (see cache.rs). The data itself always lives in HBM, so the L2 tracks tags
+/// only, and only for the sets in use.
 pub struct L2Cache {
     pub size_bytes: usize,
-    data: HashMap<usize, u8>,
+    cache: SectoredCache,
 }
 
 impl L2Cache {
+    /// An H100-style L2 (see `CacheConfig::h100_l2`) of `size_bytes`.
     pub fn new(size_bytes: usize) -> Self {
-        L2Cache {
-            size_bytes,
-            data: HashMap::new(),
-        }
+        Self::with_config(CacheConfig { size_bytes, ..CacheConfig::h100_l2() })
     }
 
-    pub fn read(&self, addr: usize, len: usize) -> Vec<u8> {
-        (addr..addr + len)
-            .map(|a| *self.data.get(&a).unwrap_or(&0))
-            .collect()
+    pub fn with_config(config: CacheConfig) -> Self {
+        L2Cache { size_bytes: config.size_bytes, cache: SectoredCache::new(config) }
     }
 
-    pub fn write(&mut self, addr: usize, bytes: &[u8]) {
-        for (i, &byte) in bytes.iter().enumerate() {
-            self.data.insert(addr + i, byte);
-        }
+    pub fn config(&self) -> &CacheConfig {
+        self.cache.config()
+    }
+
+    /// Look up the sectors covering `ranges` of (address, length), once each.
+    /// Returns the hits and misses of this access.
+    pub fn access(
+        &mut self,
+        ranges: impl IntoIterator<Item = (usize, usize)>,
+        write: bool,
+    ) -> CacheAccess {
+        self.cache.access(ranges, write)
+    }
+
+    /// Hits, misses and evictions since the GPU was created
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Drop every cached line, e.g. to time a kernel against a cold cache.
+    pub fn invalidate(&mut self) {
+        self.cache.invalidate();
     }
 }
 
 /// High Bandwidth Memory — the main GPU memory (e.g., 80GB on H100, 3.4 TB/s bandwidth).
 /// Slowest in the hierarchy but largest capacity.
-/// Uses a sparse map to simulate large address spaces without real allocation.
+/// Backed by a sparse paged store to simulate large address spaces without
+/// real allocation.
 pub struct HBM {
     pub size_bytes: usize,
-    data: HashMap<usize, u8>,
-    /// Simulated bandwidth in bytes per second
-    pub bandwidth_bps: u64,
+    data: PagedStore,
+    /// Channels, banks and open rows that time the L2's requests
+    pub dram: Dram,
 }
 
 impl HBM {
+    /// HBM of `size_bytes` with H100 channel timing (`DramConfig::h100`).
     pub fn new(size_bytes: usize) -> Self {
-        HBM {
-            size_bytes,
-            data: HashMap::new(),
-            bandwidth_bps: 3_400_000_000_000, // 3.4 TB/s (H100)
-        }
+        HBM { size_bytes, data: PagedStore::new(), dram: Dram::new(DramConfig::h100()) }
+    }
+
+    /// Peak bandwidth of the channels in bytes per second at an SM clock of
+    /// `clock_mhz`.
+    pub fn bandwidth_bps(&self, clock_mhz: u32) -> u64 {
+        (self.dram.config().peak_bytes_per_cycle() * clock_mhz as f64 * 1e6) as u64
+    }
+
+    /// Whether every byte of [addr, addr + len) has been written since the
+    /// range was last discarded.
+    pub fn is_initialized(&self, addr: usize, len: usize) -> bool {
+        self.data.is_initialized(addr, len)
+    }
+
+    /// Forget the contents of [addr, addr + len): they read as zero and
+    /// count as uninitialized until written again.
+    pub fn discard(&mut self, addr: usize, len: usize) {
+        self.data.discard(addr, len);
+    }
+
+    /// Host memory used to back the bytes written so far
+    pub fn resident_bytes(&self) -> usize {
+        self.data.resident_bytes()
+    }
+
+    /// Fill `buf` with the bytes starting at `addr`.
+    pub fn read_into(&self, addr: usize, buf: &mut [u8]) {
+        self.data.read_into(addr, buf);
     }
 
     pub fn read(&self, addr: usize, len: usize) -> Vec<u8> {
-        (addr..addr + len)
-            .map(|a| *self.data.get(&a).unwrap_or(&0))
-            .collect()
+        self.data.read(addr, len)
     }
 
     pub fn write(&mut self, addr: usize, bytes: &[u8]) {
-        for (i, &byte) in bytes.iter().enumerate() {
-            self.data.insert(addr + i, byte);
-        }
+        self.data.write(addr, bytes);
+    }
+
+    /// Read a `T` at `addr`.
+    pub fn load<T: DeviceValue>(&self, addr: usize) -> T {
+        let mut buf = [0u8; 16];
+        self.read_into(addr, &mut buf[..T::SIZE]);
+        T::from_le(&buf[..T::SIZE])
+    }
+
+    /// Write `value` at `addr`.
+    pub fn store<T: DeviceValue>(&mut self, addr: usize, value: T) {
+        self.write(addr, &value.to_le());
+    }
+
+    /// Read `len` consecutive `T`s starting at `addr`.
+    pub fn load_slice<T: DeviceValue>(&self, addr: usize, len: usize) -> Vec<T> {
+        decode_slice(&self.read(addr, len * T::SIZE))
+    }
+
+    /// Write `values` consecutively starting at `addr`.
+    pub fn store_slice<T: DeviceValue>(&mut self, addr: usize, values: &[T]) {
+        self.write(addr, &encode_slice(values));
+    }
+}
+
+/// Typed host-side handle to `len` consecutive `T`s in HBM, starting at
+/// device address `addr`. The handle is `Copy`, so kernels can capture it and
+/// compute element addresses with `at`.
+#[derive(Debug)]
+pub struct DeviceBuffer<T> {
+    addr: usize,
+    len: usize,
+    _elem: PhantomData<T>,
+}
+
+impl<T> Clone for DeviceBuffer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for DeviceBuffer<T> {}
+
+impl<T: DeviceValue> DeviceBuffer<T> {
+    /// A handle to `len` elements at device address `addr`.
+    pub const fn new(addr: usize, len: usize) -> Self {
+        DeviceBuffer { addr, len, _elem: PhantomData }
+    }
+
+    /// Device address of the first element
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Number of elements
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size of the buffer in bytes
+    pub fn size_bytes(&self) -> usize {
+        self.len * T::SIZE
+    }
+
+    /// Device address of element `i`. Panics if `i` is out of bounds.
+    pub fn at(&self, i: usize) -> usize {
+        assert!(i < self.len, "index {} out of bounds for a buffer of {} elements", i, self.len);
+        self.addr + i * T::SIZE
+    }
+
+    /// Copy `data` into the start of the buffer (cudaMemcpy host to device)
+    /// from pageable host memory. The copy is timed like `GPU::memcpy_htod`:
+    /// it advances the device clock and is logged in `GPU::copies`. Panics
+    /// if `data` is longer than the buffer.
+    pub fn copy_from_host(&self, gpu: &mut GPU, data: &[T]) {
+        assert!(
+            data.len() <= self.len,
+            "copying {} elements into a buffer of {}",
+            data.len(),
+            self.len,
+        );
+        gpu.host_copy(self.addr, data.len() * T::SIZE, CopyDirection::HostToDevice);
+        gpu.hbm.store_slice(self.addr, data);
+    }
+
+    /// Copy the whole buffer back to pageable host memory (cudaMemcpy device
+    /// to host), timed like `GPU::memcpy_dtoh`.
+    pub fn copy_to_host(&self, gpu: &mut GPU) -> Vec<T> {
+        gpu.host_copy(self.addr, self.size_bytes(), CopyDirection::DeviceToHost);
+        gpu.hbm.load_slice(self.addr, self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_values_round_trip_through_hbm() {
+        let mut hbm = HBM::new(1024);
+        hbm.store(0, -2i16);
+        hbm.store(8, 1.5f64);
+        assert_eq!(hbm.read(0, 2), vec![0xfe, 0xff]);
+        assert_eq!(hbm.load::<i16>(0), -2);
+        assert_eq!(hbm.load::<f64>(8), 1.5);
+
+        hbm.store_slice(64, &[1u32, 2, 3]);
+        assert_eq!(hbm.load_slice::<u32>(64, 3), vec![1, 2, 3]);
+        assert_eq!(decode_slice::<u32>(&encode_slice(&[7u32, 8])), vec![7, 8]);
+    }
+
+    #[test]
+    fn paged_store_allocates_pages_on_first_write() {
+        let mut store = PagedStore::new();
+        assert_eq!(store.read(1 << 40, 4), vec![0; 4]);
+        assert_eq!(store.resident_bytes(), 0);
+        // Straddles the boundary between pages 0 and 1
+        store.write(PAGE_SIZE - 2, &[1, 2, 3, 4]);
+        assert_eq!(store.resident_bytes(), 2 * PAGE_SIZE);
+        assert_eq!(store.read(PAGE_SIZE - 3, 6), vec![0, 1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn paged_store_tracks_written_bytes_until_discarded() {
+        let mut store = PagedStore::new();
+        store.write(60, &[9; 8]);
+        assert!(store.is_initialized(60, 8));
+        assert!(!store.is_initialized(59, 8));
+        store.discard(60, 4);
+        assert!(!store.is_initialized(60, 8));
+        assert!(store.is_initialized(64, 4));
+        assert_eq!(store.read(60, 8), vec![0, 0, 0, 0, 9, 9, 9, 9]);
+        // The page goes once none of its bytes are written
+        store.discard(64, 4);
+        assert_eq!(store.resident_bytes(), 0);
+    }
+
+    #[test]
+    fn hbm_does_not_allocate_its_capacity_up_front() {
+        let mut hbm = HBM::new(80 * 1024 * 1024 * 1024);
+        hbm.store((1 << 36) + 4, 7u32);
+        assert_eq!(hbm.load::<u32>((1 << 36) + 4), 7);
+        assert_eq!(hbm.resident_bytes(), PAGE_SIZE);
+    }
+
+    #[test]
+    fn device_buffer_addresses_elements_by_type() {
+        let buf: DeviceBuffer<f64> = DeviceBuffer::new(256, 4);
+        assert_eq!((buf.len(), buf.size_bytes()), (4, 32));
+        assert_eq!(buf.at(0), 256);
+        assert_eq!(buf.at(3), 256 + 3 * 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn device_buffer_index_past_the_end_panics() {
+        DeviceBuffer::<u32>::new(0, 4).at(4);
+    }
+
+    #[test]
+    fn device_buffer_copies_to_and_from_the_host() {
+        let mut gpu = GPU::h100();
+        let buf: DeviceBuffer<i32> = DeviceBuffer::new(128, 3);
+        buf.copy_from_host(&mut gpu, &[-1, 0, 1]);
+        assert_eq!(gpu.hbm.load::<i32>(buf.at(2)), 1);
+        assert_eq!(buf.copy_to_host(&mut gpu), vec![-1, 0, 1]);
+    }
+
+    #[test]
+    fn buffer_copies_are_timed_pageable_copies() {
+        let mut gpu = GPU::h100();
+        let buf = gpu.alloc_buffer::<f32>(1024).unwrap();
+        buf.copy_from_host(&mut gpu, &[1.5; 1024]);
+        let after_htod = gpu.cycle;
+        assert!(after_htod > 0);
+        assert_eq!(buf.copy_to_host(&mut gpu), vec![1.5; 1024]);
+        assert!(gpu.cycle > after_htod);
+
+        let copies: Vec<_> = gpu.copies.iter().map(|c| (c.direction, c.bytes, c.pinned)).collect();
+        let expected =
+            [(CopyDirection::HostToDevice, 4096, false), (CopyDirection::DeviceToHost, 4096, false)];
+        assert_eq!(copies, expected);
+    }
+
+    #[test]
+    fn buffer_copies_of_managed_memory_stay_off_the_copy_engines() {
+        let mut gpu = GPU::h100();
+        let managed = gpu.alloc_managed::<u32>(256).unwrap();
+        let buf = managed.as_device();
+        buf.copy_from_host(&mut gpu, &[7; 256]);
+        assert_eq!(buf.copy_to_host(&mut gpu), vec![7; 256]);
+        assert!(gpu.copies.is_empty());
     }
 }
@@ -50,6 +50,23 @@ pub struct CollectiveSnapshot {
     pub efficiency_pct: f64,
 }
 
+/// Snapshot of a host–device (or device-to-device) copy.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct CopySnapshot {
+    /// "HtoD", "DtoH" or "DtoD"
+    pub direction: String,
+    /// Data volume in MB
+    pub bytes_mb: f64,
+    /// Whether the host buffer was pinned
+    pub pinned: bool,
+    /// What carried the copy, e.g. "PCIe Gen5 x16" or "HBM"
+    pub link: String,
+    /// Transfer time in microseconds, after waiting for a copy engine
+    pub time_us: f64,
+    /// Achieved bandwidth in GB/s
+    pub bandwidth_gb_s: f64,
+}
+
 // ---------------------------------------------------------------------------
 // LiveMetrics
 // ---------------------------------------------------------------------------
@@ -84,6 +101,65 @@ pub struct LiveMetrics {
     pub threads_executed: u32,
     /// Active block count per SM — index = SM id
     pub sm_active_blocks: Vec<u32>,
+    /// Simulated cycles elapsed so far (max SM clock)
+    #[serde(default)]
+    pub total_cycles: u64,
+    /// Shared memory carveout of the kernel in KB; the rest of each SM's
+    /// unified array is L1
+    #[serde(default)]
+    pub smem_carveout_kb: u32,
+    /// L1 sector hits of the kernel's retired blocks so far, over all SMs
+    #[serde(default)]
+    pub l1_hits: u64,
+    /// L1 sector misses of the kernel's retired blocks so far
+    #[serde(default)]
+    pub l1_misses: u64,
+    /// l1_hits / (l1_hits + l1_misses) [0.0, 1.0]
+    #[serde(default)]
+    pub l1_hit_rate: f64,
+    /// L2 sector hits of the kernel's retired blocks so far
+    #[serde(default)]
+    pub l2_hits: u64,
+    /// L2 sector misses of the kernel's retired blocks so far
+    #[serde(default)]
+    pub l2_misses: u64,
+    /// L2 lines evicted by the kernel's retired blocks so far
+    #[serde(default)]
+    pub l2_evictions: u64,
+    /// l2_hits / (l2_hits + l2_misses) [0.0, 1.0]
+    #[serde(default)]
+    pub l2_hit_rate: f64,
+    /// Shared memory bank conflicts (extra wavefronts) of the kernel's
+    /// blocks dispatched so far
+    #[serde(default)]
+    pub smem_bank_conflicts: u64,
+    /// 32-byte sectors per warp-level global memory request of the kernel's
+    /// blocks dispatched so far (4 when 32 lanes load consecutive floats)
+    #[serde(default)]
+    pub sectors_per_request: f64,
+    /// Achieved HBM bandwidth of the kernel in GB/s; set when it completes
+    #[serde(default)]
+    pub hbm_bandwidth_gbps: f64,
+    /// Fraction of the kernel's DRAM requests that hit an open row [0.0, 1.0];
+    /// set when it completes
+    #[serde(default)]
+    pub hbm_row_hit_rate: f64,
+    /// Distinct addresses per warp-level constant load (1 = every load a
+    /// broadcast)
+    #[serde(default)]
+    pub const_addresses_per_request: f64,
+    /// Fraction of texture cache sector lookups that hit [0.0, 1.0]
+    #[serde(default)]
+    pub tex_hit_rate: f64,
+    /// Device page faults of the kernel's managed memory accesses
+    #[serde(default)]
+    pub page_faults: u64,
+    /// Managed memory the kernel migrated, in MB
+    #[serde(default)]
+    pub migrated_mb: f64,
+    /// Most recently completed copy (if any)
+    #[serde(default)]
+    pub last_copy: Option<CopySnapshot>,
     /// Unix timestamp in ms when this snapshot was written
     pub timestamp_ms: u64,
 
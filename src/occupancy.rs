@@ -1,6 +1,6 @@
-/// Occupancy calculation — determines how many thread blocks can simultaneously
-/// reside on an SM, given the kernel's resource requirements and SM hardware limits.
-/// Based on GPGPU-Sim's max_cta() logic and NVIDIA architecture whitepapers.
+//! Occupancy calculation — determines how many thread blocks can simultaneously
+//! reside on an SM, given the kernel's resource requirements and SM hardware limits.
+//! Based on GPGPU-Sim's max_cta() logic and NVIDIA architecture whitepapers.
 
 /// Hardware resource limits for a specific SM architecture.
 #[derive(Debug, Clone)]
@@ -15,10 +15,46 @@ pub struct SmConfig {
     pub total_regs: u32,
     /// Register allocation granularity (per warp, in registers)
     pub reg_alloc_granularity: u32,
-    /// Total shared memory (SMEM) per SM in bytes
+    /// Total shared memory (SMEM) per SM in bytes — the largest carveout.
+    /// The occupancy calculator sees the carveout of the launch here (see
+    /// `smem_carveout`).
     pub total_smem_bytes: u32,
+    /// Unified array per SM split between shared memory and the L1 data
+    /// cache, in bytes
+    pub l1_smem_bytes: u32,
+    /// Shared memory sizes the unified array can be split at, in bytes,
+    /// ascending; the rest of the array is L1
+    pub smem_carveouts: Vec<u32>,
     /// Shared memory allocation granularity in bytes
     pub smem_alloc_granularity: u32,
+    /// SM clock frequency in MHz, used to convert simulated cycles to wall time
+    pub clock_mhz: u32,
+    /// Warp schedulers per SM — at most this many warp instructions issue per cycle
+    pub issue_width: u32,
+    /// Cycles before a warp can issue again after an arithmetic instruction
+    pub alu_latency: u32,
+    /// Shared memory (SMEM) load latency in cycles
+    pub smem_latency: u32,
+    /// Global memory (HBM) load latency in cycles — an L2 miss that opens a
+    /// row in an idle DRAM bank; the DRAM model (dram.rs) shortens or
+    /// lengthens it
+    pub gmem_latency: u32,
+    /// Latency of a global memory access that hits in the L2, in cycles
+    pub l2_latency: u32,
+    /// Latency of a global memory load that hits in the SM's L1, in cycles
+    pub l1_latency: u32,
+    /// Latency of a constant memory load that hits in the SM's constant
+    /// cache, in cycles; a miss takes `l2_latency`
+    pub const_latency: u32,
+    /// Latency of a texture fetch that hits in the SM's texture cache, in
+    /// cycles (filtering included)
+    pub tex_latency: u32,
+    /// Cycles from the last warp arriving at a block barrier until the block is released
+    pub barrier_latency: u32,
+    /// Latency of an access to another block's shared memory in the cluster
+    /// (DSMEM) over the SM-to-SM network; also the release latency of a
+    /// cluster barrier
+    pub dsmem_latency: u32,
 }
 
 impl SmConfig {
@@ -31,7 +67,22 @@ impl SmConfig {
             total_regs: 65536,
             reg_alloc_granularity: 256,
             total_smem_bytes: 228 * 1024, // 228 KB
+            l1_smem_bytes: 256 * 1024,
+            smem_carveouts: [0, 8, 16, 32, 64, 100, 132, 164, 196, 228]
+                .map(|kb| kb * 1024)
+                .to_vec(),
             smem_alloc_granularity: 128,
+            clock_mhz: 1980, // 1.98 GHz boost (SXM5)
+            issue_width: 4,
+            alu_latency: 4,
+            smem_latency: 30,
+            gmem_latency: 480,
+            l2_latency: 260,
+            l1_latency: 33,
+            const_latency: 8,
+            tex_latency: 80,
+            barrier_latency: 20,
+            dsmem_latency: 200,
         }
     }
 
@@ -44,9 +95,28 @@ impl SmConfig {
             total_regs: 65536,
             reg_alloc_granularity: 256,
             total_smem_bytes: 164 * 1024, // 164 KB
+            l1_smem_bytes: 192 * 1024,
+            smem_carveouts: [0, 8, 16, 32, 64, 100, 132, 164].map(|kb| kb * 1024).to_vec(),
             smem_alloc_granularity: 128,
+            clock_mhz: 1410, // 1.41 GHz boost (SXM4)
+            issue_width: 4,
+            alu_latency: 4,
+            smem_latency: 30,
+            gmem_latency: 470,
+            l2_latency: 200,
+            l1_latency: 33,
+            const_latency: 8,
+            tex_latency: 80,
+            barrier_latency: 20,
+            dsmem_latency: 200, // no clusters before Hopper; unused
         }
     }
+
+    /// This configuration with the unified array split at `smem_bytes` of
+    /// shared memory, as the occupancy calculator should see it.
+    pub fn with_smem_carveout(&self, smem_bytes: u32) -> SmConfig {
+        SmConfig { total_smem_bytes: smem_bytes, ..self.clone() }
+    }
 }
 
 /// Resource requirements declared by a kernel at launch time.
@@ -86,7 +156,7 @@ fn round_up(val: u32, granularity: u32) -> u32 {
     if granularity == 0 {
         return val;
     }
-    ((val + granularity - 1) / granularity) * granularity
+    val.div_ceil(granularity) * granularity
 }
 
 /// Compute the maximum number of thread blocks that can simultaneously reside
@@ -113,11 +183,7 @@ pub fn max_blocks_per_sm(kernel: &KernelResources, sm: &SmConfig) -> (u32, Occup
     } else {
         let regs_per_warp = round_up(kernel.regs_per_thread * 32, sm.reg_alloc_granularity);
         let regs_per_block = regs_per_warp * warps_per_block;
-        if regs_per_block == 0 {
-            u32::MAX
-        } else {
-            sm.total_regs / regs_per_block
-        }
+        sm.total_regs.checked_div(regs_per_block).unwrap_or(u32::MAX)
     };
 
     // Limiter 4: shared memory
@@ -149,6 +215,55 @@ pub fn max_blocks_per_sm(kernel: &KernelResources, sm: &SmConfig) -> (u32, Occup
     (max, limiter)
 }
 
+/// Shared memory carveout for a launch: how many bytes of each SM's unified
+/// L1 / shared memory array go to shared memory, the rest being L1 (cf.
+/// cudaFuncAttributePreferredSharedMemoryCarveout).
+///
+/// `preferred` is a percentage of `total_smem_bytes`, rounded up to the next
+/// supported size. Without one, the smallest size that still reaches the
+/// occupancy of the full carveout is used, leaving L1 as large as possible.
+/// Either way the carveout holds at least one block when the SM can.
+pub fn smem_carveout(kernel: &KernelResources, sm: &SmConfig, preferred: Option<u32>) -> u32 {
+    let block = round_up(kernel.smem_per_block, sm.smem_alloc_granularity);
+    let wanted = match preferred {
+        Some(pct) => (sm.total_smem_bytes as u64 * pct.min(100) as u64 / 100) as u32,
+        None => block.saturating_mul(max_blocks_per_sm(kernel, sm).0),
+    };
+    sm.smem_carveouts
+        .iter()
+        .copied()
+        .find(|&size| size >= wanted.max(block))
+        .unwrap_or(sm.total_smem_bytes)
+}
+
+/// Resources one resident block of a kernel consumes on an SM, after
+/// allocation granularity.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockFootprint {
+    pub threads: u32,
+    pub warps: u32,
+    pub regs: u32,
+    pub smem_bytes: u32,
+    /// Shared memory carveout of the block's launch; an idle SM is split at
+    /// it before taking the block
+    pub smem_carveout: u32,
+}
+
+/// Compute the per-block resource footprint used to admit blocks onto an SM.
+/// `sm` is the configuration at the launch's carveout
+/// (`SmConfig::with_smem_carveout`).
+pub fn block_footprint(kernel: &KernelResources, sm: &SmConfig) -> BlockFootprint {
+    let threads = kernel.threads_per_block.max(1);
+    let warps = threads.div_ceil(32);
+    BlockFootprint {
+        threads,
+        warps,
+        regs: round_up(kernel.regs_per_thread * 32, sm.reg_alloc_granularity) * warps,
+        smem_bytes: round_up(kernel.smem_per_block, sm.smem_alloc_granularity),
+        smem_carveout: sm.total_smem_bytes,
+    }
+}
+
 /// Theoretical occupancy as a fraction [0.0, 1.0].
 /// occupancy = (resident warps) / (max warps per SM)
 pub fn occupancy(max_blocks: u32, warps_per_block: u32, max_warps_per_sm: u32) -> f32 {
@@ -0,0 +1,231 @@
+//! Host–device copies over PCIe.
+//!
+//! Copies between host and device memory cross the PCIe link, which carries
+//! one transfer per direction at a time at the link's bandwidth (it is full
+//! duplex). Pinned (page-locked) host buffers are read and written by DMA
+//! directly; pageable buffers are staged through a driver-owned pinned
+//! buffer, which roughly halves the achieved bandwidth. Every copy also pays
+//! a fixed setup latency.
+//!
+//! The GPU moves data with a limited number of copy engines. A copy waits for
+//! a free engine and, for host copies, for its direction of the link, so with
+//! one engine uploads and downloads serialize while with two they overlap.
+//! Copies run alongside kernels either way. Device-to-device copies also
+//! take an engine but move data at HBM bandwidth rather than over the link.
+use std::fmt;
+
+use crate::metrics::CopySnapshot;
+
+/// Bandwidth and latency of the host link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcieConfig {
+    /// Link name for reports, e.g. "PCIe Gen5 x16"
+    pub name: &'static str,
+    /// Achieved bandwidth per direction for pinned host memory, in bytes per
+    /// second
+    pub pinned_bandwidth_bps: u64,
+    /// Achieved bandwidth per direction for pageable host memory, in bytes
+    /// per second
+    pub pageable_bandwidth_bps: u64,
+    /// Fixed cost of every copy (DMA setup and completion) in nanoseconds
+    pub latency_ns: u64,
+}
+
+impl PcieConfig {
+    /// PCIe Gen4 x16 (A100): 32 GB/s raw, ~25 GB/s achieved from pinned
+    /// memory.
+    pub fn gen4_x16() -> Self {
+        PcieConfig {
+            name: "PCIe Gen4 x16",
+            pinned_bandwidth_bps: 25_000_000_000,
+            pageable_bandwidth_bps: 12_000_000_000,
+            latency_ns: 1_500,
+        }
+    }
+
+    /// PCIe Gen5 x16 (H100): 64 GB/s raw, ~50 GB/s achieved from pinned
+    /// memory.
+    pub fn gen5_x16() -> Self {
+        PcieConfig {
+            name: "PCIe Gen5 x16",
+            pinned_bandwidth_bps: 50_000_000_000,
+            pageable_bandwidth_bps: 20_000_000_000,
+            latency_ns: 1_200,
+        }
+    }
+
+    pub fn bandwidth_bps(&self, pinned: bool) -> u64 {
+        if pinned { self.pinned_bandwidth_bps } else { self.pageable_bandwidth_bps }
+    }
+}
+
+/// Which way a copy moves data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyDirection {
+    HostToDevice,
+    DeviceToHost,
+    DeviceToDevice,
+}
+
+impl fmt::Display for CopyDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyDirection::HostToDevice => write!(f, "HtoD"),
+            CopyDirection::DeviceToHost => write!(f, "DtoH"),
+            CopyDirection::DeviceToDevice => write!(f, "DtoD"),
+        }
+    }
+}
+
+/// Timing of one completed copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyStats {
+    pub direction: CopyDirection,
+    pub bytes: usize,
+    /// Whether the host buffer was pinned (always true for device-to-device)
+    pub pinned: bool,
+    /// Device cycle at which the copy was ready to run
+    pub submit_cycle: u64,
+    /// Device cycle at which it got a copy engine (and link direction)
+    pub start_cycle: u64,
+    /// Device cycle at which the last byte arrived
+    pub end_cycle: u64,
+    /// Time from start to end in microseconds
+    pub time_us: f64,
+    /// bytes / time_us, in GB/s
+    pub bandwidth_gb_s: f64,
+}
+
+impl CopyStats {
+    /// Stats of a copy of `bytes` ready at cycle `submit` that transferred
+    /// from `start` to `end` on a `clock_mhz` clock.
+    pub fn new(
+        direction: CopyDirection,
+        bytes: usize,
+        pinned: bool,
+        submit: u64,
+        start: u64,
+        end: u64,
+        clock_mhz: u32,
+    ) -> Self {
+        let time_us = (end - start) as f64 / clock_mhz.max(1) as f64;
+        CopyStats {
+            direction,
+            bytes,
+            pinned,
+            submit_cycle: submit,
+            start_cycle: start,
+            end_cycle: end,
+            time_us,
+            bandwidth_gb_s: if time_us > 0.0 { bytes as f64 / (time_us * 1e3) } else { 0.0 },
+        }
+    }
+
+    /// The copy as the visualizer shows it; `link` names what carried it.
+    pub fn snapshot(&self, link: &str) -> CopySnapshot {
+        CopySnapshot {
+            direction: self.direction.to_string(),
+            bytes_mb: self.bytes as f64 / 1_000_000.0,
+            pinned: self.pinned,
+            link: link.to_string(),
+            time_us: self.time_us,
+            bandwidth_gb_s: self.bandwidth_gb_s,
+        }
+    }
+}
+
+impl fmt::Display for CopyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} bytes ({}) in {:.2} µs ({:.1} GB/s)",
+            self.direction,
+            self.bytes,
+            if self.pinned { "pinned" } else { "pageable" },
+            self.time_us,
+            self.bandwidth_gb_s,
+        )
+    }
+}
+
+/// Copy engines and link directions, each free from some device cycle on.
+#[derive(Debug, Clone)]
+pub struct CopyEngines {
+    engine_free_at: Vec<u64>,
+    /// Host-to-device and device-to-host halves of the link
+    link_free_at: [u64; 2],
+}
+
+impl CopyEngines {
+    pub fn new(engines: usize) -> Self {
+        CopyEngines { engine_free_at: vec![0; engines.max(1)], link_free_at: [0; 2] }
+    }
+
+    /// Reserve the first free engine — and for host copies the link in
+    /// `direction` — for a copy ready at cycle `now` that transfers for
+    /// `cycles`. Returns the cycle the copy starts.
+    pub fn schedule(&mut self, direction: CopyDirection, now: u64, cycles: u64) -> u64 {
+        let engine = (0..self.engine_free_at.len())
+            .min_by_key(|&e| self.engine_free_at[e])
+            .expect("at least one copy engine");
+        let link = match direction {
+            CopyDirection::HostToDevice => Some(0),
+            CopyDirection::DeviceToHost => Some(1),
+            CopyDirection::DeviceToDevice => None,
+        };
+        let mut start = now.max(self.engine_free_at[engine]);
+        if let Some(link) = link {
+            start = start.max(self.link_free_at[link]);
+            self.link_free_at[link] = start + cycles;
+        }
+        self.engine_free_at[engine] = start + cycles;
+        start
+    }
+}
+
+/// Cycles a copy of `bytes` takes at `bandwidth_bps` plus `latency_ns`, on a
+/// `clock_mhz` clock.
+pub fn copy_cycles(bytes: usize, bandwidth_bps: u64, latency_ns: u64, clock_mhz: u32) -> u64 {
+    let transfer = (bytes as u128 * clock_mhz as u128 * 1_000_000)
+        .div_ceil(bandwidth_bps.max(1) as u128) as u64;
+    transfer + latency_ns * clock_mhz as u64 / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GPU;
+    use crate::stream::HostBuffer;
+
+    #[test]
+    fn pinned_copies_take_half_the_time_of_pageable_ones() {
+        let mut gpu = GPU::h100().with_pcie(PcieConfig::gen4_x16());
+        let bytes = 16 << 20;
+        let pageable = gpu.memcpy_htod(0, &HostBuffer::new(bytes));
+        let pinned = gpu.memcpy_htod(0, &HostBuffer::new(bytes).pinned());
+        assert!(!pageable.pinned && pinned.pinned);
+        let cycles = |c: &CopyStats| c.end_cycle - c.start_cycle;
+        assert_eq!(cycles(&pageable), copy_cycles(bytes, 12_000_000_000, 1_500, 1980));
+        assert_eq!(cycles(&pinned), copy_cycles(bytes, 25_000_000_000, 1_500, 1980));
+        assert!((pinned.bandwidth_gb_s / pageable.bandwidth_gb_s - 2.0).abs() < 0.1);
+        // Blocking copies run back to back on the device clock
+        assert_eq!(gpu.cycle, pinned.end_cycle);
+        assert_eq!(pinned.start_cycle, pageable.end_cycle);
+    }
+
+    #[test]
+    fn copies_wait_for_an_engine_and_their_link_direction() {
+        let mut two = CopyEngines::new(2);
+        assert_eq!(two.schedule(CopyDirection::HostToDevice, 0, 100), 0);
+        // The other direction of the link is free
+        assert_eq!(two.schedule(CopyDirection::DeviceToHost, 0, 100), 0);
+        // Both engines are busy
+        assert_eq!(two.schedule(CopyDirection::DeviceToDevice, 10, 50), 100);
+        // Engine 1 and the upload half of the link are free again at 100
+        assert_eq!(two.schedule(CopyDirection::HostToDevice, 0, 10), 100);
+
+        let mut one = CopyEngines::new(1);
+        one.schedule(CopyDirection::HostToDevice, 0, 100);
+        assert_eq!(one.schedule(CopyDirection::DeviceToHost, 0, 100), 100);
+    }
+}
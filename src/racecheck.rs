@@ -0,0 +1,334 @@
+//! Racecheck: an opt-in data race detector for shared and global memory (cf.
+//! compute-sanitizer --tool racecheck).
+//!
+//! With `GPU::racecheck` set, the executor records, for every byte a launch
+//! touches, the last write and the reads since, each tagged with the thread
+//! that made it and the barrier epoch that thread was in. An access races
+//! with an earlier one by another thread when at least one of them is a
+//! write and no barrier orders them:
+//!   - threads of the same block are ordered by any barrier they both passed
+//!     (`syncthreads`, `cluster_sync` or `grid_sync`),
+//!   - blocks of the same cluster only by `cluster_sync` or `grid_sync`,
+//!   - other blocks only by `grid_sync` (cooperative launches).
+//!
+//! Shared memory is checked between the threads of a block (and across the
+//! cluster, for distributed shared memory); global memory between blocks.
+//! Atomics are never reported. Hazards are aggregated by kind and source
+//! line pair into `ExecutionStats::race_hazards`.
+use std::collections::HashMap;
+
+use crate::kernel::Dim3;
+use crate::timing::Site;
+
+/// Hazards printed when a kernel completes; the rest are only counted.
+pub const MAX_PRINTED_HAZARDS: usize = 32;
+
+/// Barriers a thread has passed, by the scope they synchronize.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Epoch {
+    /// Barriers of any kind (each one orders the threads of the block)
+    pub block: u32,
+    /// Cluster and grid barriers
+    pub cluster: u32,
+    /// Grid barriers
+    pub grid: u32,
+}
+
+/// Order of the two conflicting accesses, earlier one first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HazardKind {
+    ReadAfterWrite,
+    WriteAfterRead,
+    WriteAfterWrite,
+}
+
+impl std::fmt::Display for HazardKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HazardKind::ReadAfterWrite => "read-after-write",
+            HazardKind::WriteAfterRead => "write-after-read",
+            HazardKind::WriteAfterWrite => "write-after-write",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemorySpace {
+    Shared,
+    Global,
+}
+
+/// One side of a hazard: who accessed the byte, and where in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaceAccess {
+    pub block_idx: Dim3,
+    pub thread_idx: Dim3,
+    pub site: Site,
+}
+
+/// Conflicting accesses from one pair of source lines, aggregated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaceHazard {
+    pub kind: HazardKind,
+    pub space: MemorySpace,
+    /// Address of the first conflict found (an offset into the block's
+    /// shared memory for `Shared`)
+    pub addr: usize,
+    /// Earlier access of the first conflict found
+    pub first: RaceAccess,
+    /// Later access of the first conflict found
+    pub second: RaceAccess,
+    /// Conflicting access pairs between the same two source lines
+    pub count: u64,
+}
+
+impl std::fmt::Display for RaceHazard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let space = match self.space {
+            MemorySpace::Shared => "shared",
+            MemorySpace::Global => "global",
+        };
+        let (first, second) = match self.kind {
+            HazardKind::ReadAfterWrite => ("write", "read"),
+            HazardKind::WriteAfterRead => ("read", "write"),
+            HazardKind::WriteAfterWrite => ("write", "write"),
+        };
+        let side = |a: &RaceAccess| {
+            let (t, b) = (a.thread_idx, a.block_idx);
+            format!("thread ({},{},{}) in block ({},{},{}) at {}", t.x, t.y, t.z, b.x, b.y, b.z, a.site)
+        };
+        write!(
+            f,
+            "{} hazard on {} memory address {:#x}: {} by {}, then {} by {} ({} conflicts)",
+            self.kind,
+            space,
+            self.addr,
+            first,
+            side(&self.first),
+            second,
+            side(&self.second),
+            self.count,
+        )
+    }
+}
+
+/// An access as recorded for race detection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Access {
+    pub block_idx: Dim3,
+    pub thread_idx: Dim3,
+    pub cluster_idx: Dim3,
+    pub epoch: Epoch,
+    pub site: Site,
+}
+
+impl Access {
+    fn same_thread(&self, other: &Access) -> bool {
+        self.block_idx == other.block_idx && self.thread_idx == other.thread_idx
+    }
+
+    /// Whether no barrier orders the two accesses.
+    fn unordered(&self, other: &Access) -> bool {
+        if self.block_idx == other.block_idx {
+            self.thread_idx != other.thread_idx && self.epoch.block == other.epoch.block
+        } else if self.cluster_idx == other.cluster_idx {
+            self.epoch.cluster == other.epoch.cluster
+        } else {
+            self.epoch.grid == other.epoch.grid
+        }
+    }
+
+    fn report(&self) -> RaceAccess {
+        RaceAccess { block_idx: self.block_idx, thread_idx: self.thread_idx, site: self.site }
+    }
+}
+
+/// Accesses to one byte since it was last written.
+#[derive(Default)]
+struct ByteState {
+    write: Option<Access>,
+    /// Latest read by each thread since the write
+    reads: Vec<Access>,
+}
+
+/// Race detection state of one launch.
+#[derive(Default)]
+pub(crate) struct RaceState {
+    global: HashMap<usize, ByteState>,
+    /// Keyed by the block owning the shared memory and the offset
+    shared: HashMap<(Dim3, usize), ByteState>,
+    hazards: Vec<RaceHazard>,
+    /// Index into `hazards` by kind, space and source line pair
+    index: HashMap<(HazardKind, MemorySpace, Site, Site), usize>,
+}
+
+impl RaceState {
+    /// Record an access of `size` bytes at `addr` — in the shared memory of
+    /// block `owner` for `MemorySpace::Shared` — and report the hazards it
+    /// completes. Global accesses are only checked against other blocks.
+    pub fn access(
+        &mut self,
+        space: MemorySpace,
+        owner: Dim3,
+        addr: usize,
+        size: usize,
+        write: bool,
+        who: Access,
+    ) {
+        // At most one hazard of each kind per access
+        let mut found: Vec<(HazardKind, usize, Access)> = Vec::new();
+        let mut note = |kind: HazardKind, a: usize, earlier: &Access| {
+            let conflicts = earlier.unordered(&who)
+                && (space == MemorySpace::Shared || earlier.block_idx != who.block_idx);
+            if conflicts && !found.iter().any(|(k, _, _)| *k == kind) {
+                found.push((kind, a, *earlier));
+            }
+        };
+        for a in addr..addr + size {
+            let byte = match space {
+                MemorySpace::Global => self.global.entry(a).or_default(),
+                MemorySpace::Shared => self.shared.entry((owner, a)).or_default(),
+            };
+            if let Some(w) = &byte.write {
+                let kind = if write { HazardKind::WriteAfterWrite } else { HazardKind::ReadAfterWrite };
+                note(kind, a, w);
+            }
+            if write {
+                byte.reads.iter().for_each(|r| note(HazardKind::WriteAfterRead, a, r));
+                byte.write = Some(who);
+                byte.reads.clear();
+            } else if let Some(r) = byte.reads.iter_mut().find(|r| r.same_thread(&who)) {
+                *r = who;
+            } else {
+                byte.reads.push(who);
+            }
+        }
+
+        for (kind, a, earlier) in found {
+            let key = (kind, space, earlier.site, who.site);
+            if let Some(&i) = self.index.get(&key) {
+                self.hazards[i].count += 1;
+            } else {
+                self.index.insert(key, self.hazards.len());
+                self.hazards.push(RaceHazard {
+                    kind,
+                    space,
+                    addr: a,
+                    first: earlier.report(),
+                    second: who.report(),
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    /// Forget the shared memory accesses of blocks that have finished.
+    pub fn end_dispatch(&mut self) {
+        self.shared.clear();
+    }
+
+    pub fn into_hazards(self) -> Vec<RaceHazard> {
+        self.hazards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use super::*;
+
+    /// Thread `thread` of block `block` in cluster `cluster`, accessing from
+    /// the caller's source line.
+    #[track_caller]
+    fn who(block: u32, thread: u32, cluster: u32, epoch: Epoch) -> Access {
+        Access {
+            block_idx: Dim3::new(block, 0, 0),
+            thread_idx: Dim3::new(thread, 0, 0),
+            cluster_idx: Dim3::new(cluster, 0, 0),
+            epoch,
+            site: Location::caller(),
+        }
+    }
+
+    fn after(block: u32, cluster: u32, grid: u32) -> Epoch {
+        Epoch { block, cluster, grid }
+    }
+
+    fn shared(state: &mut RaceState, addr: usize, write: bool, access: Access) {
+        state.access(MemorySpace::Shared, Dim3::new(0, 0, 0), addr, 4, write, access);
+    }
+
+    fn global(state: &mut RaceState, addr: usize, write: bool, access: Access) {
+        state.access(MemorySpace::Global, Dim3::new(0, 0, 0), addr, 4, write, access);
+    }
+
+    fn kinds(state: RaceState) -> Vec<(HazardKind, u64)> {
+        state.into_hazards().iter().map(|h| (h.kind, h.count)).collect()
+    }
+
+    #[test]
+    fn unsynchronized_threads_of_a_block_race_on_shared_memory() {
+        let mut state = RaceState::default();
+        shared(&mut state, 0, true, who(0, 0, 0, Epoch::default()));
+        shared(&mut state, 0, false, who(0, 1, 0, Epoch::default()));
+        shared(&mut state, 0, true, who(0, 2, 0, Epoch::default()));
+        assert_eq!(
+            kinds(state),
+            [
+                (HazardKind::ReadAfterWrite, 1),
+                (HazardKind::WriteAfterWrite, 1),
+                (HazardKind::WriteAfterRead, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn barriers_and_program_order_prevent_hazards() {
+        let mut state = RaceState::default();
+        shared(&mut state, 0, true, who(0, 0, 0, Epoch::default()));
+        shared(&mut state, 0, false, who(0, 0, 0, Epoch::default()));
+        shared(&mut state, 0, false, who(0, 1, 0, after(1, 0, 0)));
+        assert_eq!(kinds(state), []);
+    }
+
+    #[test]
+    fn global_accesses_race_only_across_blocks() {
+        let mut state = RaceState::default();
+        global(&mut state, 64, true, who(0, 0, 0, Epoch::default()));
+        global(&mut state, 64, true, who(0, 1, 0, Epoch::default()));
+        assert_eq!(kinds(state), []);
+
+        // A block barrier does not order blocks of a cluster; a cluster barrier does
+        let mut state = RaceState::default();
+        global(&mut state, 64, true, who(0, 0, 0, Epoch::default()));
+        global(&mut state, 64, false, who(1, 0, 0, after(1, 0, 0)));
+        global(&mut state, 64, false, who(2, 0, 0, after(1, 1, 0)));
+        assert_eq!(kinds(state), [(HazardKind::ReadAfterWrite, 1)]);
+
+        // Blocks of different clusters are ordered only by a grid barrier
+        let mut state = RaceState::default();
+        global(&mut state, 64, true, who(0, 0, 0, Epoch::default()));
+        global(&mut state, 64, false, who(1, 0, 1, after(1, 1, 0)));
+        global(&mut state, 64, false, who(2, 0, 1, after(1, 1, 1)));
+        assert_eq!(kinds(state), [(HazardKind::ReadAfterWrite, 1)]);
+    }
+
+    #[test]
+    fn hazards_aggregate_by_source_line_pair() {
+        let mut state = RaceState::default();
+        for t in 0..8 {
+            shared(&mut state, 4 * t, true, who(0, t as u32, 0, Epoch::default()));
+        }
+        // Every thread reads its neighbour's word: one hazard per access,
+        // not per byte, all from the same pair of lines
+        for t in 0..8 {
+            shared(&mut state, 4 * ((t + 1) % 8), false, who(0, t as u32, 0, Epoch::default()));
+        }
+        let hazards = state.into_hazards();
+        assert_eq!(hazards.len(), 1);
+        assert_eq!(hazards[0].count, 8);
+        assert_eq!((hazards[0].addr, hazards[0].first.thread_idx.x), (4, 1));
+        assert_eq!(hazards[0].second.thread_idx.x, 0);
+    }
+}
@@ -1,14 +1,14 @@
-/// Warp scheduling policies.
-///
-/// Each cycle, the warp scheduler selects which eligible warp to issue next.
-/// Three policies are implemented, matching those studied in the literature:
-///   - LRR  (Loose Round-Robin)       — simple rotation, baseline
-///   - GTO  (Greedy-Then-Oldest)      — cache-friendly, default in GPGPU-Sim
-///   - TwoLevel (Two-Level Active)    — active set + pending pool, best overall
-///
-/// References:
-///   Narasiman et al., MICRO 2011 — Two-Level Warp Scheduling
-///   Rogers, O'Connor, Aamodt, MICRO 2012 — GTO / Cache-Conscious Scheduling
+//! Warp scheduling policies.
+//!
+//! Each cycle, the warp scheduler selects which eligible warp to issue next.
+//! Three policies are implemented, matching those studied in the literature:
+//!   - LRR  (Loose Round-Robin)       — simple rotation, baseline
+//!   - GTO  (Greedy-Then-Oldest)      — cache-friendly, default in GPGPU-Sim
+//!   - TwoLevel (Two-Level Active)    — active set + pending pool, best overall
+//!
+//! References:
+//!   Narasiman et al., MICRO 2011 — Two-Level Warp Scheduling
+//!   Rogers, O'Connor, Aamodt, MICRO 2012 — GTO / Cache-Conscious Scheduling
 
 /// The execution state of a warp. Mirrors NVIDIA Nsight Compute stall taxonomy.
 #[derive(Debug, Clone, PartialEq)]
@@ -53,10 +53,10 @@ impl std::fmt::Display for WarpState {
 }
 
 /// The scheduler's view of a warp — its index, current state, and age.
-/// The executor creates these from the active warps in a block.
+/// The timing model creates these from the warps resident on an SM.
 #[derive(Debug, Clone)]
 pub struct WarpSlot {
-    /// Warp index within the block
+    /// Hardware warp slot on the SM (stable while the warp is resident)
     pub warp_idx: usize,
     /// Current execution state
     pub state: WarpState,
@@ -103,6 +103,12 @@ impl LrrScheduler {
     }
 }
 
+impl Default for LrrScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WarpScheduler for LrrScheduler {
     fn order_warps(&mut self, slots: &[WarpSlot]) -> Vec<usize> {
         let n = slots.len();
@@ -146,15 +152,21 @@ impl GtoScheduler {
     }
 }
 
+impl Default for GtoScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WarpScheduler for GtoScheduler {
     fn order_warps(&mut self, slots: &[WarpSlot]) -> Vec<usize> {
         // Greedy warp first (last issued, if still present)
         let mut ordered: Vec<usize> = Vec::with_capacity(slots.len());
 
-        if let Some(last) = self.last_issued {
-            if slots.iter().any(|s| s.warp_idx == last) {
-                ordered.push(last);
-            }
+        if let Some(last) = self.last_issued
+            && slots.iter().any(|s| s.warp_idx == last)
+        {
+            ordered.push(last);
         }
 
         // Remaining warps sorted by age ascending (oldest = smallest age)
@@ -210,6 +222,10 @@ impl TwoLevelScheduler {
 
 impl WarpScheduler for TwoLevelScheduler {
     fn order_warps(&mut self, slots: &[WarpSlot]) -> Vec<usize> {
+        // Drop warps that have exited (their slot may since hold a new block's warp)
+        self.active_set
+            .retain(|idx| slots.iter().any(|s| s.warp_idx == *idx));
+
         // Promote warps from pending into active set to fill free slots
         let active_set: std::collections::HashSet<usize> =
             self.active_set.iter().cloned().collect();
@@ -269,6 +285,7 @@ impl WarpScheduler for TwoLevelScheduler {
 // ---------------------------------------------------------------------------
 
 /// Selectable warp scheduling policy.
+#[derive(Debug, Clone, Copy)]
 pub enum SchedulingPolicy {
     /// Loose Round-Robin
     Lrr,
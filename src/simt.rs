@@ -0,0 +1,292 @@
+//! SIMT execution model: folds the per-lane traces of a warp into the warp's
+//! instruction stream.
+//!
+//! Kernels are plain Rust closures, so the simulator cannot see their branches.
+//! Instead every recorded instruction carries the source location of the
+//! `ThreadCtx` call that issued it (its "PC"). Lanes whose next instructions
+//! come from the same site execute together; when the active lanes of a warp
+//! are about to execute different sites, the warp has hit a divergent branch.
+//!
+//! Divergence is handled with a reconvergence stack, as on pre-Volta hardware:
+//!   - the active lanes are split into one path per next site
+//!   - the reconvergence point is the first site on the first path that every
+//!     other path also reaches (an approximation of the branch's immediate
+//!     post-dominator)
+//!   - paths execute one after another, each with only its own lanes active,
+//!     until they reach the reconvergence point — so divergent paths are
+//!     serialized in the timing model
+//!   - the lanes then continue together with the full mask
+//!
+//! Lanes that stop while the rest of their path carries on — a loop exit with
+//! differing trip counts, or an early return — also count as a divergent
+//! branch; the remaining lanes continue with a reduced mask.
+//!
+//! Barriers (block or cluster) split each lane's trace into epochs; every epoch
+//! is folded independently and epochs are joined by a single warp-wide barrier.
+use crate::timing::{InstrKind, LaneOp, Site, WarpInstr};
+use crate::warp::WARP_SIZE;
+
+/// The instruction stream of one warp plus its divergence statistics.
+#[derive(Debug, Clone, Default)]
+pub struct WarpStream {
+    /// Warp instructions in issue order, ending with EXIT
+    pub instrs: Vec<WarpInstr>,
+    /// Branches at which the active lanes took different paths
+    pub divergent_branches: u32,
+}
+
+impl WarpStream {
+    /// Instructions issued on behalf of the kernel's threads
+    /// (excludes the warp-wide barriers and EXIT added by the model)
+    pub fn lane_instructions(&self) -> u64 {
+        self.instrs.iter().filter(|i| !i.lanes.is_empty()).count() as u64
+    }
+
+    /// Sum of the active lane counts over all issued instructions
+    pub fn active_lanes(&self) -> u64 {
+        self.instrs.iter().map(|i| i.lanes.len() as u64).sum()
+    }
+
+    /// Fraction of the warp's 32 lanes active per issued instruction [0.0, 1.0]
+    pub fn lane_utilization(&self) -> f64 {
+        let issued = self.lane_instructions();
+        if issued == 0 {
+            return 1.0;
+        }
+        self.active_lanes() as f64 / (issued * WARP_SIZE as u64) as f64
+    }
+}
+
+/// Fold the per-lane instruction traces of one warp (`lanes[i]` = lane i)
+/// into its instruction stream.
+pub fn merge_lanes(lanes: &[Vec<LaneOp>]) -> WarpStream {
+    let epochs: Vec<Vec<&[LaneOp]>> = lanes
+        .iter()
+        .map(|l| l.split(|op| op.kind.is_barrier()).collect())
+        .collect();
+    // A lane that reached n barriers has n + 1 epochs
+    let num_epochs = epochs.iter().map(|e| e.len()).max().unwrap_or(0);
+
+    // Kind of the barrier ending each epoch, taken from the first lane to reach it
+    let barrier = |epoch: usize| {
+        lanes
+            .iter()
+            .find_map(|l| l.iter().filter(|op| op.kind.is_barrier()).nth(epoch))
+            .map_or(InstrKind::Barrier, |op| op.kind)
+    };
+
+    let mut stream = WarpStream::default();
+    for epoch in 0..num_epochs {
+        if epoch > 0 {
+            stream.instrs.push(WarpInstr::new(barrier(epoch - 1)));
+        }
+        let mut stack = ReconvergenceStack {
+            traces: epochs.iter().map(|e| e.get(epoch).copied().unwrap_or(&[])).collect(),
+            pc: vec![0; lanes.len()],
+            out: &mut stream,
+        };
+        let all: Vec<usize> = (0..lanes.len()).collect();
+        stack.run(&all, None);
+    }
+    stream.instrs.push(WarpInstr::new(InstrKind::Exit));
+    stream
+}
+
+/// Replays one epoch of a warp's lanes. Each nested `run` call is one entry
+/// of the reconvergence stack: a set of lanes and the site they wait at.
+struct ReconvergenceStack<'t> {
+    traces: Vec<&'t [LaneOp]>,
+    /// Index of each lane's next instruction
+    pc: Vec<usize>,
+    out: &'t mut WarpStream,
+}
+
+impl ReconvergenceStack<'_> {
+    fn next_site(&self, lane: usize) -> Option<Site> {
+        self.traces[lane].get(self.pc[lane]).map(|op| op.site)
+    }
+
+    /// Execute `lanes` until each has finished or reached `reconverge_at`.
+    fn run(&mut self, lanes: &[usize], reconverge_at: Option<Site>) {
+        // Lanes active on the previous uniform step; a lane with no work at
+        // all (it skipped a branch body or returned) counts as having stopped
+        let mut prev_active = lanes.len();
+        loop {
+            // Group the lanes that still have work by their next site
+            let mut paths: Vec<(Site, Vec<usize>)> = Vec::new();
+            for &lane in lanes {
+                let Some(site) = self.next_site(lane) else { continue };
+                if Some(site) == reconverge_at {
+                    continue;
+                }
+                match paths.iter_mut().find(|(s, _)| *s == site) {
+                    Some((_, path)) => path.push(lane),
+                    None => paths.push((site, vec![lane])),
+                }
+            }
+
+            match paths.len() {
+                0 => return,
+                1 => {
+                    // Some lanes stopped (loop exit, early return) while the
+                    // rest carry on: that is a divergent branch too
+                    let active = paths[0].1.len();
+                    if active < prev_active {
+                        self.out.divergent_branches += 1;
+                    }
+                    prev_active = active;
+                    self.issue(&paths[0].1);
+                }
+                _ => {
+                    self.out.divergent_branches += 1;
+                    let join = self.reconvergence_point(&paths).or(reconverge_at);
+                    for (_, path) in &paths {
+                        self.run(path, join);
+                    }
+                    // Lanes that finished inside a path were accounted for there
+                    prev_active = 0;
+                }
+            }
+        }
+    }
+
+    /// Issue the next instruction of every lane in `lanes` as one warp instruction.
+    fn issue(&mut self, lanes: &[usize]) {
+        let ops: Vec<(u32, LaneOp)> = lanes
+            .iter()
+            .map(|&lane| {
+                let op = self.traces[lane][self.pc[lane]];
+                self.pc[lane] += 1;
+                (lane as u32, op)
+            })
+            .collect();
+        let mut instr = WarpInstr::new(ops[0].1.kind);
+        instr.lanes = ops;
+        self.out.instrs.push(instr);
+    }
+
+    /// First site on the first path's remaining trace that the leading lane
+    /// of every other path also reaches; None if the paths never rejoin
+    /// before the end of the epoch.
+    fn reconvergence_point(&self, paths: &[(Site, Vec<usize>)]) -> Option<Site> {
+        let remaining = |lane: usize| &self.traces[lane][self.pc[lane]..];
+        let mut candidates: Vec<Site> = Vec::new();
+        for op in remaining(paths[0].1[0]) {
+            if !candidates.contains(&op.site) {
+                candidates.push(op.site);
+            }
+        }
+        candidates.into_iter().find(|&site| {
+            paths[1..]
+                .iter()
+                .all(|(_, path)| remaining(path[0]).iter().any(|op| op.site == site))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each helper records its op at its own source line, i.e. its own site
+    fn head() -> LaneOp {
+        LaneOp::new(InstrKind::Alu)
+    }
+
+    fn then_branch() -> LaneOp {
+        LaneOp::mem(InstrKind::GlobalLoad, 0, 4)
+    }
+
+    fn else_branch() -> LaneOp {
+        LaneOp::mem(InstrKind::GlobalStore, 0, 4)
+    }
+
+    fn tail() -> LaneOp {
+        LaneOp::new(InstrKind::Alu)
+    }
+
+    fn barrier() -> LaneOp {
+        LaneOp::new(InstrKind::Barrier)
+    }
+
+    fn lane_counts(stream: &WarpStream) -> Vec<(InstrKind, usize)> {
+        stream.instrs.iter().map(|i| (i.kind, i.lanes.len())).collect()
+    }
+
+    #[test]
+    fn uniform_lanes_issue_together() {
+        let lanes = vec![vec![head(), tail()]; WARP_SIZE];
+        let stream = merge_lanes(&lanes);
+        assert_eq!(
+            lane_counts(&stream),
+            [(InstrKind::Alu, 32), (InstrKind::Alu, 32), (InstrKind::Exit, 0)],
+        );
+        assert_eq!(stream.divergent_branches, 0);
+        assert_eq!(stream.instrs[0].active_mask(), u32::MAX);
+        assert_eq!(stream.lane_utilization(), 1.0);
+    }
+
+    #[test]
+    fn divergent_paths_serialize_and_reconverge() {
+        let lanes: Vec<Vec<LaneOp>> = (0..WARP_SIZE)
+            .map(|lane| {
+                let body = if lane % 2 == 0 { then_branch() } else { else_branch() };
+                vec![head(), body, tail()]
+            })
+            .collect();
+        let stream = merge_lanes(&lanes);
+        assert_eq!(
+            lane_counts(&stream),
+            [
+                (InstrKind::Alu, 32),
+                (InstrKind::GlobalLoad, 16),
+                (InstrKind::GlobalStore, 16),
+                (InstrKind::Alu, 32),
+                (InstrKind::Exit, 0),
+            ],
+        );
+        assert_eq!(stream.divergent_branches, 1);
+        assert_eq!(stream.instrs[1].active_mask(), 0x5555_5555);
+        assert_eq!(stream.instrs[2].active_mask(), 0xaaaa_aaaa);
+        assert_eq!(stream.instrs[3].active_mask(), u32::MAX);
+        assert_eq!(stream.lane_utilization(), 0.75);
+    }
+
+    #[test]
+    fn lanes_that_return_early_reduce_the_mask() {
+        let lanes: Vec<Vec<LaneOp>> = (0..WARP_SIZE)
+            .map(|lane| if lane < 8 { vec![head(), tail(), tail()] } else { vec![head()] })
+            .collect();
+        let stream = merge_lanes(&lanes);
+        assert_eq!(
+            lane_counts(&stream),
+            [(InstrKind::Alu, 32), (InstrKind::Alu, 8), (InstrKind::Alu, 8), (InstrKind::Exit, 0)],
+        );
+        // Only the first drop in active lanes is a branch
+        assert_eq!(stream.divergent_branches, 1);
+    }
+
+    #[test]
+    fn barriers_join_epochs_with_one_warp_wide_barrier() {
+        let lanes: Vec<Vec<LaneOp>> = (0..WARP_SIZE)
+            .map(|lane| {
+                let body = if lane < 16 { then_branch() } else { else_branch() };
+                vec![body, barrier(), tail()]
+            })
+            .collect();
+        let stream = merge_lanes(&lanes);
+        // The paths rejoin at the barrier even without a common site before it
+        assert_eq!(
+            lane_counts(&stream),
+            [
+                (InstrKind::GlobalLoad, 16),
+                (InstrKind::GlobalStore, 16),
+                (InstrKind::Barrier, 0),
+                (InstrKind::Alu, 32),
+                (InstrKind::Exit, 0),
+            ],
+        );
+        assert_eq!(stream.divergent_branches, 1);
+        assert_eq!(stream.lane_instructions(), 3);
+    }
+}
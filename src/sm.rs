@@ -1,9 +1,13 @@
 /// Streaming Multiprocessor (SM) — the core compute unit of a GPU.
-/// Each SM contains warp schedulers, tensor cores, CUDA cores, and fast shared memory (SMEM).
+/// Each SM contains warp schedulers, tensor cores, CUDA cores, and a unified
+/// on-chip array split between fast shared memory (SMEM) and the L1 data
+/// cache. The split (the shared memory carveout) is chosen per launch and
+/// applied whenever the SM takes a block while idle.
+use crate::cache::{CacheConfig, SectoredCache};
+use crate::occupancy::{BlockFootprint, SmConfig};
 use crate::warp::{Warp, WarpScheduler};
 use crate::tensor_core::TensorCore;
 
-pub const SMEM_SIZE_BYTES: usize = 256 * 1024; // 256KB per SM
 pub const WARPS_PER_SM: usize = 4;
 
 /// Tracks live resource consumption on an SM, updated as blocks are assigned
@@ -18,46 +22,100 @@ pub struct SmResourceUsage {
     pub used_warps: u32,
     /// Bytes of shared memory currently allocated
     pub used_smem_bytes: u32,
+    /// Registers currently allocated
+    pub used_regs: u32,
 }
 
 pub struct StreamingMultiprocessor {
     pub id: usize,
-    /// Fast on-chip shared memory (programmer-controlled cache)
-    pub smem: Box<[u8; SMEM_SIZE_BYTES]>,
+    /// Graphics processing cluster (GPC) the SM belongs to. Blocks of a
+    /// thread block cluster are always co-scheduled within one GPC.
+    pub gpc: usize,
+    /// Bytes of the unified L1 / shared memory array currently given to
+    /// shared memory (programmer-controlled cache)
+    pub smem_carveout: u32,
+    /// L1 data cache for global memory, sized by what the carveout leaves
+    pub l1: SectoredCache,
+    /// Constant cache for constant memory loads
+    pub const_cache: SectoredCache,
+    /// Texture cache for texture fetches and surface reads
+    pub tex_cache: SectoredCache,
     /// Warp schedulers (one per subpartition)
     pub warp_schedulers: Vec<WarpScheduler>,
     /// Tensor cores for matrix multiplication (one per subpartition)
     pub tensor_cores: Vec<TensorCore>,
     /// Live resource usage — updated by the executor as blocks are assigned/retired
     pub resource_usage: SmResourceUsage,
+    /// Simulated cycle counter — the device cycle at which this SM's last block retired
+    pub clock: u64,
+    /// Cycles this SM has spent with at least one resident block
+    pub busy_cycles: u64,
 }
 
 impl StreamingMultiprocessor {
-    pub fn new(id: usize) -> Self {
+    /// A new SM with the largest shared memory carveout of `cfg`.
+    pub fn new(id: usize, cfg: &SmConfig) -> Self {
         StreamingMultiprocessor {
             id,
-            smem: Box::new([0u8; SMEM_SIZE_BYTES]),
+            gpc: 0,
+            smem_carveout: cfg.total_smem_bytes,
+            l1: SectoredCache::new(CacheConfig::l1(l1_bytes(cfg, cfg.total_smem_bytes))),
+            const_cache: SectoredCache::new(CacheConfig::constant()),
+            tex_cache: SectoredCache::new(CacheConfig::texture()),
             warp_schedulers: (0..WARPS_PER_SM).map(|_| WarpScheduler::new()).collect(),
             tensor_cores: (0..WARPS_PER_SM).map(|_| TensorCore::new()).collect(),
             resource_usage: SmResourceUsage::default(),
+            clock: 0,
+            busy_cycles: 0,
         }
     }
 
-    /// Allocate resources for a new block. Returns false if the SM is full.
-    pub fn allocate_block(&mut self, threads: u32, warps: u32, smem_bytes: u32) -> bool {
+    /// Whether a block with the given footprint fits next to the blocks already
+    /// resident (possibly from other kernels), within the current carveout.
+    /// An empty SM accepts any block, so a kernel too large for the SM still
+    /// runs one block at a time.
+    pub fn fits(&self, block: &BlockFootprint, cfg: &SmConfig) -> bool {
+        let u = &self.resource_usage;
+        u.active_blocks == 0
+            || (u.active_blocks < cfg.max_blocks
+                && u.used_threads + block.threads <= cfg.max_threads
+                && u.used_warps + block.warps <= cfg.max_warps
+                && u.used_regs + block.regs <= cfg.total_regs
+                && u.used_smem_bytes + block.smem_bytes <= self.smem_carveout)
+    }
+
+    /// Allocate resources for a new block, first splitting an idle SM at the
+    /// block's carveout. Returns false if the SM is full.
+    pub fn allocate_block(&mut self, block: &BlockFootprint, cfg: &SmConfig) -> bool {
+        if !self.fits(block, cfg) {
+            return false;
+        }
+        if self.resource_usage.active_blocks == 0 && self.smem_carveout != block.smem_carveout {
+            self.set_smem_carveout(block.smem_carveout, cfg);
+        }
         self.resource_usage.active_blocks += 1;
-        self.resource_usage.used_threads += threads;
-        self.resource_usage.used_warps += warps;
-        self.resource_usage.used_smem_bytes += smem_bytes;
+        self.resource_usage.used_threads += block.threads;
+        self.resource_usage.used_warps += block.warps;
+        self.resource_usage.used_regs += block.regs;
+        self.resource_usage.used_smem_bytes += block.smem_bytes;
         true
     }
 
+    /// Split the unified array at `smem_bytes` of shared memory. The L1 is
+    /// resized to the rest, losing its contents.
+    pub fn set_smem_carveout(&mut self, smem_bytes: u32, cfg: &SmConfig) {
+        self.smem_carveout = smem_bytes;
+        self.l1 = SectoredCache::new(CacheConfig::l1(l1_bytes(cfg, smem_bytes)));
+    }
+
     /// Free resources when a block retires.
-    pub fn free_block(&mut self, threads: u32, warps: u32, smem_bytes: u32) {
-        self.resource_usage.active_blocks = self.resource_usage.active_blocks.saturating_sub(1);
-        self.resource_usage.used_threads = self.resource_usage.used_threads.saturating_sub(threads);
-        self.resource_usage.used_warps = self.resource_usage.used_warps.saturating_sub(warps);
-        self.resource_usage.used_smem_bytes = self.resource_usage.used_smem_bytes.saturating_sub(smem_bytes);
+    pub fn free_block(&mut self, block: &BlockFootprint) {
+        let u = &mut self.resource_usage;
+        u.active_blocks = u.active_blocks.saturating_sub(1);
+        u.used_threads = u.used_threads.saturating_sub(block.threads);
+        u.used_warps = u.used_warps.saturating_sub(block.warps);
+        u.used_regs = u.used_regs.saturating_sub(block.regs);
+        u.used_smem_bytes = u.used_smem_bytes.saturating_sub(block.smem_bytes);
     }
 
     /// Dispatch a warp to an available warp scheduler
@@ -71,3 +129,8 @@ impl StreamingMultiprocessor {
         false // No available scheduler
     }
 }
+
+/// L1 capacity left by a shared memory carveout of `smem_bytes`.
+fn l1_bytes(cfg: &SmConfig, smem_bytes: u32) -> usize {
+    cfg.l1_smem_bytes.saturating_sub(smem_bytes) as usize
+}
@@ -0,0 +1,592 @@
+//! CUDA-style streams and events.
+//!
+//! Work submitted to a stream — kernel launches, memory copies, event records
+//! and event waits — runs in submission order. Work in different streams may
+//! run concurrently: kernels share the SMs whenever resources allow, and
+//! copies overlap with kernels. Nothing runs until `GPU::synchronize()`, which
+//! simulates every queue to completion on the device clock (`GPU::cycle`).
+//!
+//! Events mark points in a stream. Recording an event timestamps it once all
+//! earlier work in its stream has completed; another stream can wait on it
+//! (`GPU::stream_wait_event`), and `GPU::event_elapsed` measures the time
+//! between two recorded events.
+//!
+//! Internally every submission becomes a task in a dependency graph: a task
+//! depends on the previous task of its stream, a wait adds an edge from the
+//! event's record, and a graph launch (graph.rs) adds its nodes with their own
+//! edges. `synchronize()` runs that task graph on the device.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::executor::{ExecutionStats, KernelExecutor, LaunchId};
+use crate::gpu::GPU;
+use crate::graph::{Graph, GraphExec, GraphNodeKind};
+use crate::kernel::{Kernel, LaunchConfig};
+use crate::managed::MemLocation;
+use crate::pcie::{CopyDirection, CopyEngines, CopyStats};
+
+/// Modeled overhead of launching a kernel into a stream, in nanoseconds.
+pub const KERNEL_LAUNCH_OVERHEAD_NS: u64 = 2_500;
+
+/// Modeled overhead of launching an instantiated graph, in nanoseconds.
+pub const GRAPH_LAUNCH_OVERHEAD_NS: u64 = 2_500;
+
+/// Modeled overhead of each kernel node within a graph launch, in nanoseconds.
+/// The launch parameters were validated and uploaded at instantiation, so
+/// this is far below `KERNEL_LAUNCH_OVERHEAD_NS`.
+pub const GRAPH_NODE_OVERHEAD_NS: u64 = 500;
+
+/// Handle to a stream created by `GPU::create_stream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Stream(usize);
+
+/// Handle to an event created by `GPU::create_event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Event(usize);
+
+/// Host memory that copies read from or write into.
+/// Clones share the same storage, so the buffer can be inspected after
+/// `GPU::synchronize()`. Buffers are pageable unless made `pinned`, which
+/// doubles the bandwidth of their copies (see pcie.rs).
+#[derive(Debug, Clone, Default)]
+pub struct HostBuffer {
+    data: Rc<RefCell<Vec<u8>>>,
+    pinned: bool,
+}
+
+impl HostBuffer {
+    /// A zero-filled buffer of `len` bytes.
+    pub fn new(len: usize) -> Self {
+        Self::from_vec(vec![0; len])
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        HostBuffer { data: Rc::new(RefCell::new(data)), pinned: false }
+    }
+
+    /// This buffer as page-locked memory (cudaMallocHost /
+    /// cudaHostRegister), which copies reach by DMA directly.
+    pub fn pinned(mut self) -> Self {
+        self.pinned = true;
+        self
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.borrow().is_empty()
+    }
+
+    /// Copy of the buffer's current contents.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+
+    pub(crate) fn write_from(&self, bytes: Vec<u8>) {
+        *self.data.borrow_mut() = bytes;
+    }
+
+    pub(crate) fn read(&self) -> std::cell::Ref<'_, Vec<u8>> {
+        self.data.borrow()
+    }
+}
+
+/// Work performed by one task.
+pub(crate) enum Work {
+    Launch { kernel: Kernel, config: LaunchConfig },
+    /// Host to device: the whole host buffer is copied to `dst`
+    CopyHtoD { dst: usize, src: HostBuffer },
+    /// Device to host: fills the whole host buffer from `src`
+    CopyDtoH { dst: HostBuffer, src: usize },
+    CopyDtoD { dst: usize, src: usize, len: usize },
+    /// Migration of a managed range to `location`
+    Prefetch { addr: usize, len: usize, location: MemLocation },
+    Record(Event),
+    /// No work — joins dependencies (event waits, graph launches)
+    Join,
+}
+
+/// A unit of submitted work and the tasks it must wait for.
+struct Task {
+    work: Work,
+    deps: Vec<usize>,
+    /// Launch overhead in nanoseconds, paid once the dependencies are met
+    overhead_ns: u64,
+}
+
+#[derive(Debug, Default)]
+struct EventState {
+    /// Pending task of the most recently submitted record
+    last_record: Option<usize>,
+    /// Device cycle of the most recent completed record
+    timestamp: Option<u64>,
+}
+
+/// An in-progress stream capture (see `GPU::begin_capture`).
+struct Capture {
+    graph: Graph,
+    /// Streams taking part in the capture and the nodes their next op depends on
+    tails: HashMap<Stream, Vec<usize>>,
+    /// Nodes an event recorded during the capture stands for
+    events: HashMap<Event, Vec<usize>>,
+    /// First op that could not be captured, reported by `end_capture`
+    error: Option<String>,
+}
+
+/// The streams and events of one GPU, plus the work submitted since the last
+/// `synchronize()`.
+#[derive(Default)]
+pub struct StreamTable {
+    /// Last pending task of each stream
+    tails: Vec<Option<usize>>,
+    events: Vec<EventState>,
+    tasks: Vec<Task>,
+    capture: Option<Capture>,
+}
+
+impl StreamTable {
+    pub fn create_stream(&mut self) -> Stream {
+        self.tails.push(None);
+        Stream(self.tails.len() - 1)
+    }
+
+    pub fn create_event(&mut self) -> Event {
+        self.events.push(EventState::default());
+        Event(self.events.len() - 1)
+    }
+
+    /// Append a task to `stream`, after the stream's previous task.
+    fn push(&mut self, stream: Stream, work: Work, overhead_ns: u64) -> usize {
+        let tail = self
+            .tails
+            .get_mut(stream.0)
+            .unwrap_or_else(|| panic!("unknown stream {:?}", stream));
+        self.tasks.push(Task { work, deps: tail.iter().copied().collect(), overhead_ns });
+        *tail = Some(self.tasks.len() - 1);
+        self.tasks.len() - 1
+    }
+
+    /// Record the op as a graph node instead if `stream` is being captured.
+    /// Returns false when the stream is not capturing.
+    fn capture(&mut self, stream: Stream, kind: GraphNodeKind, host: Option<HostBuffer>) -> bool {
+        let Some(cap) = &mut self.capture else { return false };
+        let Some(tail) = cap.tails.get_mut(&stream) else { return false };
+        let node = cap.graph.add_node(kind, std::mem::take(tail), host);
+        *tail = vec![node];
+        true
+    }
+
+    pub fn launch(&mut self, kernel: &Kernel, config: &LaunchConfig, stream: Stream) {
+        if self.capture(stream, GraphNodeKind::kernel(kernel, config), None) {
+            let cap = self.capture.as_mut().unwrap();
+            if let Err(e) = cap.graph.bind_kernel(kernel) {
+                cap.error.get_or_insert(e);
+            }
+            return;
+        }
+        let work = Work::Launch { kernel: kernel.clone(), config: config.clone() };
+        self.push(stream, work, KERNEL_LAUNCH_OVERHEAD_NS);
+    }
+
+    pub fn memcpy_htod(&mut self, dst: usize, src: &HostBuffer, stream: Stream) {
+        let kind = GraphNodeKind::MemcpyHtoD { dst, bytes: src.len() };
+        if !self.capture(stream, kind, Some(src.clone())) {
+            self.push(stream, Work::CopyHtoD { dst, src: src.clone() }, 0);
+        }
+    }
+
+    pub fn memcpy_dtoh(&mut self, dst: &HostBuffer, src: usize, stream: Stream) {
+        let kind = GraphNodeKind::MemcpyDtoH { src, bytes: dst.len() };
+        if !self.capture(stream, kind, Some(dst.clone())) {
+            self.push(stream, Work::CopyDtoH { dst: dst.clone(), src }, 0);
+        }
+    }
+
+    pub fn memcpy_dtod(&mut self, dst: usize, src: usize, len: usize, stream: Stream) {
+        let kind = GraphNodeKind::MemcpyDtoD { dst, src, bytes: len };
+        if !self.capture(stream, kind, None) {
+            self.push(stream, Work::CopyDtoD { dst, src, len }, 0);
+        }
+    }
+
+    pub fn mem_prefetch(&mut self, addr: usize, len: usize, location: MemLocation, stream: Stream) {
+        let kind = GraphNodeKind::MemPrefetch { addr, bytes: len, location };
+        if !self.capture(stream, kind, None) {
+            self.push(stream, Work::Prefetch { addr, len, location }, 0);
+        }
+    }
+
+    pub fn record(&mut self, event: Event, stream: Stream) {
+        if let Some(cap) = &mut self.capture
+            && let Some(tail) = cap.tails.get(&stream)
+        {
+            cap.events.insert(event, tail.clone());
+            return;
+        }
+        let task = self.push(stream, Work::Record(event), 0);
+        self.events[event.0].last_record = Some(task);
+    }
+
+    /// Make `stream` wait for the most recently submitted record of `event`.
+    /// Waiting on an event that was never recorded is a no-op, as in CUDA.
+    /// Waiting on an event recorded during a capture pulls `stream` into the
+    /// capture.
+    pub fn wait(&mut self, stream: Stream, event: Event) {
+        if let Some(cap) = &mut self.capture
+            && let Some(nodes) = cap.events.get(&event)
+        {
+            let tail = cap.tails.entry(stream).or_default();
+            for &node in nodes {
+                if !tail.contains(&node) {
+                    tail.push(node);
+                }
+            }
+            return;
+        }
+        if let Some(record) = self.events[event.0].last_record {
+            let join = self.push(stream, Work::Join, 0);
+            self.tasks[join].deps.push(record);
+        }
+    }
+
+    /// Start capturing `stream`: its ops (and those of streams that wait on
+    /// events recorded during the capture) are added to a graph instead of
+    /// being queued.
+    pub fn begin_capture(&mut self, stream: Stream) {
+        assert!(self.capture.is_none(), "a stream capture is already in progress");
+        self.capture = Some(Capture {
+            graph: Graph::new(),
+            tails: HashMap::from([(stream, Vec::new())]),
+            events: HashMap::new(),
+            error: None,
+        });
+    }
+
+    /// Stop capturing and return the captured graph, or the first op that
+    /// could not be captured.
+    pub fn end_capture(&mut self, stream: Stream) -> Result<Graph, String> {
+        let cap = self.capture.take().expect("no stream capture in progress");
+        assert!(
+            cap.tails.contains_key(&stream),
+            "stream {:?} is not being captured",
+            stream,
+        );
+        match cap.error {
+            Some(e) => Err(format!("stream capture: {}", e)),
+            None => Ok(cap.graph),
+        }
+    }
+
+    /// Queue a launch of an instantiated graph on `stream`.
+    pub fn launch_graph(&mut self, graph: &GraphExec, stream: Stream) {
+        assert!(
+            self.capture.as_ref().is_none_or(|c| !c.tails.contains_key(&stream)),
+            "graph launches cannot be captured",
+        );
+        let launch = self.push(stream, Work::Join, GRAPH_LAUNCH_OVERHEAD_NS);
+        let base = self.tasks.len();
+        for (node, host) in graph.nodes() {
+            let deps = if node.deps.is_empty() {
+                vec![launch]
+            } else {
+                node.deps.iter().map(|&d| base + d).collect()
+            };
+            let (work, overhead_ns) = match &node.kind {
+                GraphNodeKind::Kernel { kernel, .. } => {
+                    let work = Work::Launch {
+                        kernel: graph.kernel(kernel).clone(),
+                        config: node.kind.launch_config().unwrap(),
+                    };
+                    (work, GRAPH_NODE_OVERHEAD_NS)
+                }
+                GraphNodeKind::MemcpyHtoD { dst, .. } => {
+                    (Work::CopyHtoD { dst: *dst, src: host.clone() }, 0)
+                }
+                GraphNodeKind::MemcpyDtoH { src, .. } => {
+                    (Work::CopyDtoH { dst: host.clone(), src: *src }, 0)
+                }
+                GraphNodeKind::MemcpyDtoD { dst, src, bytes } => {
+                    (Work::CopyDtoD { dst: *dst, src: *src, len: *bytes }, 0)
+                }
+                GraphNodeKind::MemPrefetch { addr, bytes, location } => {
+                    (Work::Prefetch { addr: *addr, len: *bytes, location: *location }, 0)
+                }
+            };
+            self.tasks.push(Task { work, deps, overhead_ns });
+        }
+        // Later work in the stream waits for the whole graph
+        let end = self.tasks.len();
+        let join = self.push(stream, Work::Join, 0);
+        self.tasks[join].deps.extend(base..end);
+    }
+
+    /// Device cycle at which `event` was last recorded, if it has been.
+    pub fn timestamp(&self, event: Event) -> Option<u64> {
+        self.events[event.0].timestamp
+    }
+}
+
+/// Progress of a task while the task graph runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskState {
+    /// Dependencies outstanding
+    Blocked,
+    /// Dependencies met; starts at this device cycle (after launch overhead)
+    Ready(u64),
+    Kernel(LaunchId),
+    /// A copy completing at this device cycle (after waiting for a copy
+    /// engine)
+    Copy(u64),
+    Done,
+}
+
+/// Run all submitted stream work to completion. Returns the statistics of
+/// every kernel launched, in completion order.
+pub(crate) fn synchronize(gpu: &mut GPU) -> Vec<ExecutionStats> {
+    assert!(gpu.streams.capture.is_none(), "cannot synchronize during a stream capture");
+    let tasks = std::mem::take(&mut gpu.streams.tasks);
+    gpu.streams.tails.iter_mut().for_each(|t| *t = None);
+    gpu.streams.events.iter_mut().for_each(|e| e.last_record = None);
+
+    let clock_mhz = gpu.sm_config.clock_mhz;
+    let mut engines = CopyEngines::new(gpu.copy_engines);
+    // Copies in flight, by task
+    let mut copies: HashMap<usize, CopyStats> = HashMap::new();
+    let (policy, sm_config) = (gpu.scheduling_policy, gpu.sm_config.clone());
+    let mut exec = KernelExecutor::new(gpu, policy, sm_config);
+
+    // Outstanding dependency count and dependents of every task
+    let mut waiting_on: Vec<usize> = tasks.iter().map(|t| t.deps.len()).collect();
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); tasks.len()];
+    for (i, task) in tasks.iter().enumerate() {
+        for &d in &task.deps {
+            dependents[d].push(i);
+        }
+    }
+    let overhead = |t: usize| tasks[t].overhead_ns * clock_mhz as u64 / 1000;
+    let start = exec.gpu.cycle;
+    let mut state: Vec<TaskState> = (0..tasks.len())
+        .map(|t| match waiting_on[t] {
+            0 => TaskState::Ready(start + overhead(t)),
+            _ => TaskState::Blocked,
+        })
+        .collect();
+    // Tasks that are ready or running
+    let mut active: Vec<usize> = (0..tasks.len()).filter(|&t| waiting_on[t] == 0).collect();
+    let mut remaining = tasks.len();
+    let mut completed = Vec::new();
+
+    while remaining > 0 {
+        // Start ready tasks and retire finished ones until nothing changes
+        let mut progressed = true;
+        while progressed {
+            progressed = false;
+            let now = exec.gpu.cycle;
+            let mut finished = Vec::new();
+            for &t in &active {
+                match state[t] {
+                    TaskState::Ready(at) if at <= now => {
+                        progressed = true;
+                        state[t] = match &tasks[t].work {
+                            Work::Launch { kernel, config } => {
+                                TaskState::Kernel(exec.begin(kernel, config))
+                            }
+                            Work::CopyHtoD { dst, src } => {
+                                exec.gpu.hbm.write(*dst, &src.read());
+                                let copy = exec.gpu.schedule_copy(
+                                    &mut engines,
+                                    CopyDirection::HostToDevice,
+                                    src.len(),
+                                    src.is_pinned(),
+                                    now,
+                                );
+                                let end = copy.end_cycle;
+                                copies.insert(t, copy);
+                                TaskState::Copy(end)
+                            }
+                            Work::CopyDtoH { dst, src } => {
+                                dst.write_from(exec.gpu.hbm.read(*src, dst.len()));
+                                let copy = exec.gpu.schedule_copy(
+                                    &mut engines,
+                                    CopyDirection::DeviceToHost,
+                                    dst.len(),
+                                    dst.is_pinned(),
+                                    now,
+                                );
+                                let end = copy.end_cycle;
+                                copies.insert(t, copy);
+                                TaskState::Copy(end)
+                            }
+                            Work::CopyDtoD { dst, src, len } => {
+                                let data = exec.gpu.hbm.read(*src, *len);
+                                exec.gpu.hbm.write(*dst, &data);
+                                let copy = exec.gpu.schedule_copy(
+                                    &mut engines,
+                                    CopyDirection::DeviceToDevice,
+                                    *len,
+                                    true,
+                                    now,
+                                );
+                                let end = copy.end_cycle;
+                                copies.insert(t, copy);
+                                TaskState::Copy(end)
+                            }
+                            Work::Prefetch { addr, len, location } => {
+                                // Pages count as resident once the migration
+                                // is scheduled; work in the same stream waits
+                                // for it anyway
+                                let bytes =
+                                    exec.gpu.managed.prefetch(*addr, *len, *location).unwrap_or(0);
+                                let copy = exec.gpu.schedule_copy(
+                                    &mut engines,
+                                    location.copy_direction(),
+                                    bytes,
+                                    true,
+                                    now,
+                                );
+                                let end = copy.end_cycle;
+                                copies.insert(t, copy);
+                                TaskState::Copy(end)
+                            }
+                            Work::Record(event) => {
+                                exec.gpu.streams.events[event.0].timestamp = Some(now);
+                                TaskState::Done
+                            }
+                            Work::Join => TaskState::Done,
+                        };
+                    }
+                    TaskState::Copy(end) if end <= now => {
+                        exec.gpu.record_copy(copies.remove(&t).expect("copy in flight"));
+                        state[t] = TaskState::Done;
+                    }
+                    _ => {}
+                }
+                if state[t] == TaskState::Done {
+                    finished.push(t);
+                }
+            }
+            for t in finished {
+                progressed = true;
+                remaining -= 1;
+                active.retain(|&a| a != t);
+                for &d in &dependents[t] {
+                    waiting_on[d] -= 1;
+                    if waiting_on[d] == 0 {
+                        state[d] = TaskState::Ready(now + overhead(d));
+                        active.push(d);
+                    }
+                }
+            }
+        }
+        if remaining == 0 {
+            break;
+        }
+
+        // Run the device until the next copy completes or launch overhead elapses
+        let next = active
+            .iter()
+            .filter_map(|&t| match state[t] {
+                TaskState::Ready(at) | TaskState::Copy(at) => Some(at),
+                _ => None,
+            })
+            .min();
+        assert!(
+            !exec.is_idle() || next.is_some(),
+            "stream deadlock: no submitted work can make progress",
+        );
+        for (id, stats) in exec.advance(next) {
+            if let Some(&t) = active.iter().find(|&&t| state[t] == TaskState::Kernel(id)) {
+                state[t] = TaskState::Done;
+            }
+            completed.push(stats);
+        }
+        // Kernel tasks marked Done are retired on the next pass
+    }
+    completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::Dim3;
+    use crate::pcie::copy_cycles;
+
+    /// Kernel launch overhead on the 1980 MHz SM clock
+    const LAUNCH_CYCLES: u64 = KERNEL_LAUNCH_OVERHEAD_NS * 1980 / 1000;
+
+    fn one_block(name: &str) -> (Kernel, LaunchConfig) {
+        let kernel = Kernel::new(name, |ctx| ctx.alu(10));
+        (kernel, LaunchConfig::new(Dim3::x(1), Dim3::x(32)))
+    }
+
+    #[test]
+    fn kernels_in_different_streams_overlap() {
+        let (kernel, config) = one_block("alu");
+        let mut gpu = GPU::h100();
+        let (a, b) = (gpu.create_stream(), gpu.create_stream());
+        gpu.launch_kernel_async(&kernel, &config, a);
+        gpu.launch_kernel_async(&kernel, &config, b);
+        let stats = gpu.synchronize();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].start_cycle, stats[1].start_cycle), (LAUNCH_CYCLES, LAUNCH_CYCLES));
+        assert_eq!(gpu.cycle, LAUNCH_CYCLES + stats[0].total_cycles);
+    }
+
+    #[test]
+    fn kernels_in_one_stream_run_in_order() {
+        let (kernel, config) = one_block("alu");
+        let mut gpu = GPU::h100();
+        let s = gpu.create_stream();
+        gpu.launch_kernel_async(&kernel, &config, s);
+        gpu.launch_kernel_async(&kernel, &config, s);
+        let stats = gpu.synchronize();
+        assert_eq!(stats[1].start_cycle, stats[0].end_cycle + LAUNCH_CYCLES);
+        let busy = stats[0].total_cycles + stats[1].total_cycles;
+        assert_eq!(gpu.cycle, 2 * LAUNCH_CYCLES + busy);
+    }
+
+    #[test]
+    fn waiting_on_an_event_orders_streams() {
+        let (kernel, config) = one_block("alu");
+        let mut gpu = GPU::h100();
+        let (a, b) = (gpu.create_stream(), gpu.create_stream());
+        let (start, done) = (gpu.create_event(), gpu.create_event());
+        gpu.record_event(start, a);
+        gpu.launch_kernel_async(&kernel, &config, a);
+        gpu.record_event(done, a);
+        gpu.stream_wait_event(b, done);
+        gpu.launch_kernel_async(&kernel, &config, b);
+        assert_eq!(gpu.event_elapsed(start, done), None);
+
+        let stats = gpu.synchronize();
+        assert_eq!(stats[1].start_cycle, stats[0].end_cycle + LAUNCH_CYCLES);
+        assert_eq!(gpu.streams.timestamp(done), Some(stats[0].end_cycle));
+        // Elapsed time covers the launch overhead and the kernel, in
+        // milliseconds on the 1980 MHz SM clock
+        let elapsed = gpu.event_elapsed(start, done).unwrap();
+        let expected = (LAUNCH_CYCLES + stats[0].total_cycles) as f64 / 1_980_000.0;
+        assert!((elapsed - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn copies_move_host_data_at_link_bandwidth() {
+        let mut gpu = GPU::h100();
+        let s = gpu.create_stream();
+        let src = HostBuffer::from_vec((0..=255).collect());
+        let dst = HostBuffer::new(256);
+        gpu.memcpy_htod_async(1024, &src, s);
+        gpu.memcpy_dtoh_async(&dst, 1024, s);
+        gpu.synchronize();
+        assert_eq!(dst.to_vec(), src.to_vec());
+        // Pageable copies over the default PCIe Gen5 link, one after the other
+        let one_way = copy_cycles(256, 20_000_000_000, 1_200, 1980);
+        assert_eq!(gpu.cycle, 2 * one_way);
+    }
+}
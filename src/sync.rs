@@ -0,0 +1,367 @@
+//! Synchronization primitives for async kernels.
+//!
+//! Threads of an async kernel run as resumable tasks. A synchronization call
+//! such as `ctx.syncthreads().await` parks the calling thread: the returned
+//! future records the sync point on the thread's `LaneState` and stays
+//! pending until the executor releases it. The executor polls every thread of
+//! a block until each one has either finished or parked, then releases the
+//! sync points whose participants have all arrived:
+//!   - warp-level ops (shuffle / vote) once every lane named in the mask has
+//!     arrived — the op is then evaluated over those lanes in lockstep
+//!   - the block barrier once every live thread of the block has arrived
+//!   - the cluster barrier once every live thread of every block in the
+//!     cluster has arrived, and the grid barrier of a cooperative launch once
+//!     every live thread of the grid has
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::racecheck::Epoch;
+use crate::timing::{InstrKind, LaneOp};
+use crate::warp::WARP_SIZE;
+
+/// A warp-level collective operation (CUDA `__shfl_*_sync` / `__*_sync` votes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarpOp {
+    /// Read `value` from lane `src_lane` (mod 32)
+    Shfl { src_lane: u32 },
+    /// Read `value` from lane `lane_id - delta`; lanes below `delta` keep their own
+    ShflUp { delta: u32 },
+    /// Read `value` from lane `lane_id + delta`; lanes past the end keep their own
+    ShflDown { delta: u32 },
+    /// Read `value` from lane `lane_id ^ lane_mask`
+    ShflXor { lane_mask: u32 },
+    /// Bitmask of participating lanes whose predicate is true
+    Ballot,
+    /// True if the predicate is true on any participating lane
+    Any,
+    /// True if the predicate is true on every participating lane
+    All,
+}
+
+impl WarpOp {
+    /// Whether two lanes are executing the same intrinsic (arguments may differ).
+    fn same_intrinsic(self, other: WarpOp) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    fn instr_kind(self) -> InstrKind {
+        match self {
+            WarpOp::Ballot | WarpOp::Any | WarpOp::All => InstrKind::Vote,
+            _ => InstrKind::Shuffle,
+        }
+    }
+}
+
+/// A synchronization point a thread can be parked at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPoint {
+    /// Block-wide barrier (__syncthreads)
+    Barrier,
+    /// Cluster-wide barrier (cluster.sync())
+    ClusterBarrier,
+    /// Grid-wide barrier of a cooperative launch (grid.sync())
+    GridBarrier,
+    /// Warp-level collective over the lanes in `mask`
+    Warp { op: WarpOp, mask: u32 },
+}
+
+/// Per-thread execution state shared between a thread's `ThreadCtx` and the
+/// executor.
+#[derive(Default)]
+pub(crate) struct LaneState {
+    /// Instructions issued by this thread, consumed by the timing model
+    pub trace: RefCell<Vec<LaneOp>>,
+    /// Sync point the thread is currently parked at
+    pub parked: Cell<Option<SyncPoint>>,
+    /// Set by the executor when the parked thread may resume
+    pub released: Cell<bool>,
+    /// Operand contributed to a warp-level op (raw bits)
+    pub operand: Cell<u64>,
+    /// Result of a warp-level op, written by the executor before release
+    pub result: Cell<u64>,
+    /// Set once the thread's task has run to completion
+    pub finished: Cell<bool>,
+    /// Barriers the thread has passed, for race detection
+    pub epoch: Cell<Epoch>,
+}
+
+impl LaneState {
+    /// Resume a parked thread on its next poll.
+    pub fn release(&self) {
+        self.released.set(true);
+    }
+
+    /// Whether the thread is parked and has not been released yet.
+    pub fn waiting_at(&self) -> Option<SyncPoint> {
+        if self.released.get() {
+            None
+        } else {
+            self.parked.get()
+        }
+    }
+
+    fn arrive(&self, point: SyncPoint, op: LaneOp) {
+        self.trace.borrow_mut().push(op);
+        self.parked.set(Some(point));
+        self.released.set(false);
+    }
+
+    fn try_resume(&self) -> bool {
+        if self.released.get() {
+            let mut epoch = self.epoch.get();
+            match self.parked.take() {
+                Some(SyncPoint::GridBarrier) => {
+                    epoch.grid += 1;
+                    epoch.cluster += 1;
+                    epoch.block += 1;
+                }
+                Some(SyncPoint::ClusterBarrier) => {
+                    epoch.cluster += 1;
+                    epoch.block += 1;
+                }
+                Some(SyncPoint::Barrier) => epoch.block += 1,
+                _ => {}
+            }
+            self.epoch.set(epoch);
+            self.released.set(false);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Future returned by `ThreadCtx::syncthreads`, `ThreadCtx::cluster_sync` and
+/// `ThreadCtx::grid_sync`.
+/// Resolves once the executor releases the barrier.
+#[must_use = "sync points do nothing unless awaited"]
+pub struct SyncFuture<'a> {
+    lane: &'a LaneState,
+    point: SyncPoint,
+    op: LaneOp,
+    arrived: bool,
+}
+
+impl<'a> SyncFuture<'a> {
+    #[track_caller]
+    pub(crate) fn new(lane: &'a LaneState) -> Self {
+        let op = LaneOp::new(InstrKind::Barrier);
+        SyncFuture { lane, point: SyncPoint::Barrier, op, arrived: false }
+    }
+
+    #[track_caller]
+    pub(crate) fn cluster(lane: &'a LaneState) -> Self {
+        let op = LaneOp::new(InstrKind::ClusterBarrier);
+        SyncFuture { lane, point: SyncPoint::ClusterBarrier, op, arrived: false }
+    }
+
+    #[track_caller]
+    pub(crate) fn grid(lane: &'a LaneState) -> Self {
+        let op = LaneOp::new(InstrKind::GridBarrier);
+        SyncFuture { lane, point: SyncPoint::GridBarrier, op, arrived: false }
+    }
+}
+
+impl Future for SyncFuture<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if !self.arrived {
+            self.arrived = true;
+            self.lane.arrive(self.point, self.op);
+            return Poll::Pending;
+        }
+        if self.lane.try_resume() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Values that warp shuffles can move between lanes.
+pub trait WarpValue: Copy {
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Self;
+}
+
+impl WarpValue for u32 {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits as u32
+    }
+}
+
+impl WarpValue for i32 {
+    fn to_bits(self) -> u64 {
+        self as u32 as u64
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits as u32 as i32
+    }
+}
+
+impl WarpValue for f32 {
+    fn to_bits(self) -> u64 {
+        f32::to_bits(self) as u64
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+}
+
+impl WarpValue for u64 {
+    fn to_bits(self) -> u64 {
+        self
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits
+    }
+}
+
+impl WarpValue for i64 {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits as i64
+    }
+}
+
+impl WarpValue for f64 {
+    fn to_bits(self) -> u64 {
+        f64::to_bits(self)
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+}
+
+impl WarpValue for bool {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits != 0
+    }
+}
+
+/// Future returned by the shuffle and vote intrinsics on `ThreadCtx`.
+/// Resolves to the op's result once every lane in the mask has arrived.
+#[must_use = "warp intrinsics do nothing unless awaited"]
+pub struct WarpFuture<'a, T> {
+    lane: &'a LaneState,
+    point: SyncPoint,
+    op: LaneOp,
+    operand: u64,
+    arrived: bool,
+    _result: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: WarpValue> WarpFuture<'a, T> {
+    #[track_caller]
+    pub(crate) fn new(lane: &'a LaneState, op: WarpOp, mask: u32, operand: u64) -> Self {
+        WarpFuture {
+            lane,
+            point: SyncPoint::Warp { op, mask },
+            op: LaneOp::new(op.instr_kind()),
+            operand,
+            arrived: false,
+            _result: PhantomData,
+        }
+    }
+}
+
+impl<T: WarpValue> Future for WarpFuture<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<T> {
+        if !self.arrived {
+            self.arrived = true;
+            self.lane.operand.set(self.operand);
+            self.lane.arrive(self.point, self.op);
+            return Poll::Pending;
+        }
+        if self.lane.try_resume() {
+            Poll::Ready(T::from_bits(self.lane.result.get()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Evaluate every warp-level op in `warp` whose participating lanes have all
+/// arrived, and release those lanes. Returns true if any lane was released.
+///
+/// Panics if a lane named in a mask is parked somewhere else, or at a
+/// different intrinsic — on hardware this deadlocks or is undefined.
+pub(crate) fn resolve_warp_ops(warp: &[LaneState]) -> bool {
+    let mut progressed = false;
+    for (lane, state) in warp.iter().enumerate() {
+        let Some(SyncPoint::Warp { op, mask }) = state.waiting_at() else {
+            continue;
+        };
+        assert!(
+            mask & (1 << lane) != 0,
+            "warp op {:?}: lane {} is not in its own mask {:#010x}",
+            op, lane, mask,
+        );
+
+        // Lanes named in the mask that are still running
+        let group: Vec<usize> = (0..warp.len().min(WARP_SIZE))
+            .filter(|&l| mask & (1 << l) != 0 && !warp[l].finished.get())
+            .collect();
+        for &l in &group {
+            match warp[l].waiting_at() {
+                Some(SyncPoint::Warp { op: other, mask: other_mask })
+                    if other.same_intrinsic(op) && other_mask == mask => {}
+                other => panic!(
+                    "warp op {:?} with mask {:#010x}: lane {} is parked at {:?}",
+                    op, mask, l, other,
+                ),
+            }
+        }
+
+        let operand = |l: usize| warp[l].operand.get();
+        let from = |dst: usize, src: usize| {
+            if group.contains(&src) { operand(src) } else { operand(dst) }
+        };
+        let ballot: u64 = group
+            .iter()
+            .filter(|&&l| operand(l) != 0)
+            .map(|&l| 1u64 << l)
+            .sum();
+
+        for &l in &group {
+            let SyncPoint::Warp { op, .. } = warp[l].parked.get().unwrap() else { unreachable!() };
+            let result = match op {
+                WarpOp::Shfl { src_lane } => from(l, src_lane as usize % WARP_SIZE),
+                WarpOp::ShflUp { delta } => from(l, l.checked_sub(delta as usize).unwrap_or(l)),
+                WarpOp::ShflDown { delta } => {
+                    let src = l + delta as usize;
+                    from(l, if src < WARP_SIZE { src } else { l })
+                }
+                WarpOp::ShflXor { lane_mask } => from(l, (l ^ lane_mask as usize) % WARP_SIZE),
+                WarpOp::Ballot => ballot,
+                WarpOp::Any => (ballot != 0) as u64,
+                WarpOp::All => group.iter().all(|&g| operand(g) != 0) as u64,
+            };
+            warp[l].result.set(result);
+            warp[l].release();
+            progressed = true;
+        }
+    }
+    progressed
+}
@@ -50,3 +50,9 @@ impl TensorCore {
         d
     }
 }
+
+impl Default for TensorCore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
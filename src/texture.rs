@@ -0,0 +1,389 @@
+//! Texture and surface objects.
+//!
+//! A texture object (cf. cudaCreateTextureObject) binds a 1D or 2D region of
+//! a `DeviceBuffer<f32>` for filtered, read-only fetches:
+//!   - coordinates are in texels, or normalized to [0, 1) over the texture
+//!   - coordinates outside the texture are clamped to the edge, wrapped
+//!     around, or read as zero (border), per the address mode
+//!   - point filtering returns the texel holding the coordinate; linear
+//!     filtering interpolates between the 2 (1D) or 4 (2D) nearest texel
+//!     centers, in full precision (the hardware uses 8-bit fractions)
+//!
+//! Fetches go through a per-SM texture cache with its own statistics; its
+//! misses continue to the L2 and HBM like global loads. Each row of texels a
+//! fetch reads is one texture request, so a linear 2D fetch issues two.
+//!
+//! A surface object (cf. cudaCreateSurfaceObject) binds the same kind of
+//! region for unfiltered reads and writes at integer texel coordinates.
+//! Surface reads go through the texture cache; writes are global stores.
+use crate::memory::DeviceBuffer;
+
+/// Bytes per texel (textures and surfaces hold `f32`s)
+const TEXEL_SIZE: usize = 4;
+
+/// What a fetch outside the texture reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressMode {
+    /// The nearest edge texel
+    #[default]
+    Clamp,
+    /// The texture repeats
+    Wrap,
+    /// Zero
+    Border,
+}
+
+/// How a fetch combines texels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    /// The texel holding the coordinate
+    #[default]
+    Point,
+    /// Linear (1D) or bilinear (2D) interpolation of the nearest texels
+    Linear,
+}
+
+/// Sampling state of a texture object (cf. cudaTextureDesc).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub address_mode: AddressMode,
+    pub filter_mode: FilterMode,
+    /// Coordinates run over [0, 1) instead of [0, width) and [0, height)
+    pub normalized_coords: bool,
+}
+
+/// `height` rows of `width` texels in HBM, starting at `addr`, with rows
+/// `pitch` texels apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    addr: usize,
+    width: usize,
+    height: usize,
+    pitch: usize,
+}
+
+impl Region {
+    fn new(buf: DeviceBuffer<f32>, width: usize, height: usize, pitch: usize) -> Self {
+        assert!(
+            width > 0 && height > 0 && pitch >= width,
+            "invalid {}x{} region with pitch {}",
+            width,
+            height,
+            pitch,
+        );
+        assert!(
+            (height - 1) * pitch + width <= buf.len(),
+            "a {}x{} region with pitch {} does not fit in a buffer of {} elements",
+            width,
+            height,
+            pitch,
+            buf.len(),
+        );
+        Region { addr: buf.addr(), width, height, pitch }
+    }
+
+    /// Device address of texel (x, y)
+    fn texel(&self, x: usize, y: usize) -> usize {
+        self.addr + (y * self.pitch + x) * TEXEL_SIZE
+    }
+}
+
+/// The texels one fetch reads.
+#[derive(Debug, Default)]
+pub(crate) struct TexelFetch {
+    /// Address and filter weight of every texel inside the texture
+    pub taps: Vec<(usize, f32)>,
+    /// One texture request per row of texels, as (address, bytes); 0 bytes
+    /// when the row lies outside a border-mode texture
+    pub rows: Vec<(usize, usize)>,
+}
+
+impl TexelFetch {
+    /// An unfiltered read of the texel at `addr`; None reads nothing.
+    pub fn single(addr: Option<usize>) -> Self {
+        match addr {
+            Some(addr) => TexelFetch { taps: vec![(addr, 1.0)], rows: vec![(addr, TEXEL_SIZE)] },
+            None => TexelFetch { taps: Vec::new(), rows: vec![(0, 0)] },
+        }
+    }
+}
+
+/// A 1D or 2D texture over a region of a `DeviceBuffer<f32>`. The handle is
+/// `Copy`, so kernels capture it and fetch with `ThreadCtx::tex1d` /
+/// `ThreadCtx::tex2d`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureObject {
+    region: Region,
+    desc: TextureDesc,
+}
+
+impl TextureObject {
+    /// A 1D texture over the first `width` elements of `buf`.
+    pub fn new_1d(buf: DeviceBuffer<f32>, width: usize, desc: TextureDesc) -> Self {
+        TextureObject { region: Region::new(buf, width, 1, width), desc }
+    }
+
+    /// A 2D texture of `height` rows of `width` texels packed at the start of
+    /// `buf`.
+    pub fn new_2d(buf: DeviceBuffer<f32>, width: usize, height: usize, desc: TextureDesc) -> Self {
+        Self::pitch_2d(buf, width, height, width, desc)
+    }
+
+    /// A 2D texture whose rows start `pitch` elements apart — a sub-image of
+    /// a wider one, or rows padded for alignment.
+    pub fn pitch_2d(
+        buf: DeviceBuffer<f32>,
+        width: usize,
+        height: usize,
+        pitch: usize,
+        desc: TextureDesc,
+    ) -> Self {
+        TextureObject { region: Region::new(buf, width, height, pitch), desc }
+    }
+
+    pub fn width(&self) -> usize {
+        self.region.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.region.height
+    }
+
+    pub fn desc(&self) -> TextureDesc {
+        self.desc
+    }
+
+    /// Texel index `i` along an axis of `size` texels under the address
+    /// mode; None outside a border-mode texture.
+    fn address(&self, i: i64, size: usize) -> Option<usize> {
+        let size = size as i64;
+        match self.desc.address_mode {
+            AddressMode::Clamp => Some(i.clamp(0, size - 1) as usize),
+            AddressMode::Wrap => Some(i.rem_euclid(size) as usize),
+            AddressMode::Border => (0..size).contains(&i).then_some(i as usize),
+        }
+    }
+
+    /// Texels along one axis of `size` texels a fetch at `c` reads, with
+    /// their weights.
+    fn axis(&self, c: f32, size: usize) -> Vec<(Option<usize>, f32)> {
+        let c = if self.desc.normalized_coords { c * size as f32 } else { c };
+        match self.desc.filter_mode {
+            FilterMode::Point => vec![(self.address(c.floor() as i64, size), 1.0)],
+            FilterMode::Linear => {
+                // Texel centers sit at i + 0.5
+                let c = c - 0.5;
+                let i = c.floor();
+                let frac = c - i;
+                let i = i as i64;
+                vec![(self.address(i, size), 1.0 - frac), (self.address(i + 1, size), frac)]
+            }
+        }
+    }
+
+    /// The texels a fetch at (x, y) reads; a 1D fetch (`y` None) reads row 0.
+    pub(crate) fn fetch(&self, x: f32, y: Option<f32>) -> TexelFetch {
+        let r = &self.region;
+        let cols = self.axis(x, r.width);
+        let rows = match y {
+            Some(y) => self.axis(y, r.height),
+            None => vec![(Some(0), 1.0)],
+        };
+        let mut fetch = TexelFetch::default();
+        for &(row, wy) in &rows {
+            let Some(row) = row else {
+                fetch.rows.push((r.addr, 0));
+                continue;
+            };
+            let inside: Vec<usize> = cols.iter().filter_map(|&(col, _)| col).collect();
+            for &(col, wx) in &cols {
+                if let Some(col) = col {
+                    fetch.taps.push((r.texel(col, row), wx * wy));
+                }
+            }
+            fetch.rows.push(match inside[..] {
+                [c0, c1] if c1 == c0 + 1 => (r.texel(c0, row), 2 * TEXEL_SIZE),
+                [c0, ..] => (r.texel(c0, row), TEXEL_SIZE),
+                [] => (r.addr, 0),
+            });
+        }
+        fetch
+    }
+}
+
+/// What a surface access outside the surface does (cf. cudaSurfaceBoundaryMode).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SurfaceBoundary {
+    /// Panic
+    #[default]
+    Trap,
+    /// Access the nearest edge texel
+    Clamp,
+    /// Reads return zero, writes are dropped
+    Zero,
+}
+
+/// A 2D surface over a region of a `DeviceBuffer<f32>`, read and written with
+/// `ThreadCtx::surf2d_read` / `ThreadCtx::surf2d_write`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceObject {
+    region: Region,
+    boundary: SurfaceBoundary,
+}
+
+impl SurfaceObject {
+    /// A surface of `height` rows of `width` texels packed at the start of
+    /// `buf`, trapping out-of-range accesses.
+    pub fn new_2d(buf: DeviceBuffer<f32>, width: usize, height: usize) -> Self {
+        Self::pitch_2d(buf, width, height, width)
+    }
+
+    /// A surface whose rows start `pitch` elements apart.
+    pub fn pitch_2d(buf: DeviceBuffer<f32>, width: usize, height: usize, pitch: usize) -> Self {
+        let region = Region::new(buf, width, height, pitch);
+        SurfaceObject { region, boundary: SurfaceBoundary::Trap }
+    }
+
+    pub fn with_boundary(mut self, boundary: SurfaceBoundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.region.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.region.height
+    }
+
+    /// Device address of texel (x, y) under the boundary mode; None for a
+    /// zero-mode access outside the surface.
+    #[track_caller]
+    pub(crate) fn texel(&self, x: i32, y: i32) -> Option<usize> {
+        let r = &self.region;
+        let (w, h) = (r.width as i32, r.height as i32);
+        let inside = (0..w).contains(&x) && (0..h).contains(&y);
+        match self.boundary {
+            _ if inside => Some(r.texel(x as usize, y as usize)),
+            SurfaceBoundary::Trap => {
+                panic!("surface access at ({}, {}) outside the {}x{} surface", x, y, w, h)
+            }
+            SurfaceBoundary::Clamp => {
+                Some(r.texel(x.clamp(0, w - 1) as usize, y.clamp(0, h - 1) as usize))
+            }
+            SurfaceBoundary::Zero => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GPU;
+    use crate::kernel::{Dim3, Kernel, LaunchConfig};
+    use crate::scheduler::SchedulingPolicy;
+
+    fn buffer() -> DeviceBuffer<f32> {
+        DeviceBuffer::new(0, 64)
+    }
+
+    fn desc(address_mode: AddressMode, filter_mode: FilterMode) -> TextureDesc {
+        TextureDesc { address_mode, filter_mode, normalized_coords: false }
+    }
+
+    /// Addresses a point fetch of a 4-texel 1D texture reads at each of `xs`;
+    /// None when it reads nothing.
+    fn point_1d(address_mode: AddressMode, xs: &[f32]) -> Vec<Option<usize>> {
+        let tex = TextureObject::new_1d(buffer(), 4, desc(address_mode, FilterMode::Point));
+        xs.iter()
+            .map(|&x| {
+                let fetch = tex.fetch(x, None);
+                assert_eq!(fetch.rows.len(), 1);
+                fetch.taps.first().map(|&(addr, weight)| {
+                    assert_eq!((weight, fetch.rows[0]), (1.0, (addr, TEXEL_SIZE)));
+                    addr
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn address_modes_resolve_coordinates_outside_the_texture() {
+        let xs = [2.7, -3.0, 4.0, 5.2, -1.0];
+        let clamp = [Some(8), Some(0), Some(12), Some(12), Some(0)];
+        assert_eq!(point_1d(AddressMode::Clamp, &xs), clamp);
+        let wrap = [Some(8), Some(4), Some(0), Some(4), Some(12)];
+        assert_eq!(point_1d(AddressMode::Wrap, &xs), wrap);
+        assert_eq!(point_1d(AddressMode::Border, &xs), [Some(8), None, None, None, None]);
+    }
+
+    #[test]
+    fn normalized_coordinates_span_the_texture() {
+        let desc = TextureDesc { normalized_coords: true, ..TextureDesc::default() };
+        let tex = TextureObject::new_1d(buffer(), 4, desc);
+        assert_eq!(tex.fetch(0.5, None).taps, [(8, 1.0)]);
+        assert_eq!(tex.fetch(0.99, None).taps, [(12, 1.0)]);
+    }
+
+    #[test]
+    fn linear_filtering_weights_the_nearest_texel_centers() {
+        let tex = TextureObject::new_1d(buffer(), 4, desc(AddressMode::Clamp, FilterMode::Linear));
+        let fetch = tex.fetch(1.75, None);
+        assert_eq!(fetch.taps, [(4, 0.75), (8, 0.25)]);
+        assert_eq!(fetch.rows, [(4, 2 * TEXEL_SIZE)]);
+        // At a texel center the neighbour gets no weight
+        assert_eq!(tex.fetch(2.5, None).taps, [(8, 1.0), (12, 0.0)]);
+
+        // Half of the first tap lies outside a border-mode texture
+        let tex = TextureObject::new_1d(buffer(), 4, desc(AddressMode::Border, FilterMode::Linear));
+        let fetch = tex.fetch(0.25, None);
+        assert_eq!(fetch.taps, [(0, 0.75)]);
+        assert_eq!(fetch.rows, [(0, TEXEL_SIZE)]);
+    }
+
+    #[test]
+    fn bilinear_fetches_read_two_rows_of_a_pitched_texture() {
+        let desc = desc(AddressMode::Clamp, FilterMode::Linear);
+        let tex = TextureObject::pitch_2d(buffer(), 4, 3, 8, desc);
+        let fetch = tex.fetch(1.0, Some(1.0));
+        assert_eq!(fetch.taps, [(0, 0.25), (4, 0.25), (32, 0.25), (36, 0.25)]);
+        assert_eq!(fetch.rows, [(0, 8), (32, 8)]);
+
+        let point = TextureObject::pitch_2d(buffer(), 4, 3, 8, TextureDesc::default());
+        assert_eq!(point.fetch(1.5, Some(2.5)).taps, [((2 * 8 + 1) * TEXEL_SIZE, 1.0)]);
+        // A 1D fetch of a 2D texture reads its first row
+        assert_eq!(point.fetch(3.0, None).taps, [(12, 1.0)]);
+    }
+
+    #[test]
+    fn surface_boundary_modes() {
+        let surf = SurfaceObject::pitch_2d(buffer(), 4, 3, 8);
+        assert_eq!(surf.texel(3, 2), Some((2 * 8 + 3) * TEXEL_SIZE));
+        let clamp = surf.with_boundary(SurfaceBoundary::Clamp);
+        assert_eq!(clamp.texel(-1, 5), Some(2 * 8 * TEXEL_SIZE));
+        assert_eq!(surf.with_boundary(SurfaceBoundary::Zero).texel(4, 0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the 4x3 surface")]
+    fn trapping_surfaces_panic_outside() {
+        SurfaceObject::new_2d(buffer(), 4, 3).texel(0, 3);
+    }
+
+    #[test]
+    fn kernels_read_filtered_values() {
+        let mut gpu = GPU::h100();
+        let src = gpu.alloc_buffer::<f32>(4).unwrap();
+        let dst = gpu.alloc_buffer::<f32>(4).unwrap();
+        src.copy_from_host(&mut gpu, &[0.0, 10.0, 20.0, 30.0]);
+        let tex = TextureObject::new_1d(src, 4, desc(AddressMode::Clamp, FilterMode::Linear));
+        let kernel = Kernel::new("sample", move |ctx| {
+            let i = ctx.global_id() as usize;
+            ctx.gmem.store(dst.at(i), ctx.tex1d(&tex, i as f32 + 0.25));
+        });
+        let config = LaunchConfig::new(Dim3::x(1), Dim3::x(4));
+        gpu.launch_kernel(&kernel, &config, SchedulingPolicy::Gto);
+        assert_eq!(dst.copy_to_host(&mut gpu), [0.0, 7.5, 17.5, 27.5]);
+    }
+}
@@ -0,0 +1,634 @@
+//! Cycle-level timing model.
+//!
+//! The functional model (executor.rs) runs every thread's closure and records
+//! the instructions it issues through `ThreadCtx` — memory accesses, ALU hints
+//! and barriers. simt.rs folds the lanes of each warp into a warp instruction
+//! stream, splitting it wherever the lanes diverge.
+//!
+//! This module replays those warp instruction streams on an SM. Every cycle the
+//! warp scheduler orders the warps of all resident blocks and up to
+//! `issue_width` eligible warps issue one instruction each. An issued
+//! instruction keeps its warp ineligible until its latency has elapsed, which
+//! is what lets other warps — including those of other blocks — hide memory
+//! latency.
+//!
+//! Global memory instructions look up the sectors their lanes touch as they
+//! issue: loads in the SM's L1 first, then whatever missed in the shared L2;
+//! stores (the L1 is write-through) and atomics in the L2. The instruction
+//! completes after the latency of the farthest level it had to reach. L2
+//! misses and write-backs are scheduled on the HBM's channels and banks
+//! (dram.rs), so a miss completes `gmem_latency` cycles after issue when it
+//! opens a row in an idle bank, sooner on a row hit, and later on a row
+//! conflict or behind other requests. Texture fetches take the same path
+//! with the SM's texture cache in place of the L1.
+use std::panic::Location;
+
+use crate::cache::{CacheStats, SectoredCache};
+use crate::dram::{Dram, DramStats};
+use crate::managed::{ManagedMemory, ManagedStats};
+use crate::memory::L2Cache;
+use crate::occupancy::SmConfig;
+use crate::scheduler::{WarpScheduler, WarpSlot, WarpState};
+
+/// Kind of an instruction recorded by the functional model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrKind {
+    /// Arithmetic / logic, declared by the kernel via `ThreadCtx::alu`
+    Alu,
+    /// Load from per-block shared memory
+    SharedLoad,
+    /// Store to per-block shared memory
+    SharedStore,
+    /// Load from global memory (HBM)
+    GlobalLoad,
+    /// Store to global memory (HBM)
+    GlobalStore,
+    /// Atomic read-modify-write on global memory
+    GlobalAtomic,
+    /// Atomic read-modify-write on shared memory
+    SharedAtomic,
+    /// Load from constant memory, through the SM's constant cache
+    ConstantLoad,
+    /// Texture fetch or surface read, through the SM's texture cache
+    TextureFetch,
+    /// Load from another block's shared memory in the cluster (DSMEM)
+    DsmemLoad,
+    /// Store to another block's shared memory in the cluster (DSMEM)
+    DsmemStore,
+    /// Atomic on another block's shared memory in the cluster (DSMEM)
+    DsmemAtomic,
+    /// Warp shuffle (__shfl_*_sync)
+    Shuffle,
+    /// Warp vote (__ballot_sync / __any_sync / __all_sync)
+    Vote,
+    /// Device-side launch of a child grid (dynamic parallelism)
+    Launch,
+    /// Block-wide barrier (__syncthreads)
+    Barrier,
+    /// Cluster-wide barrier (cluster.sync())
+    ClusterBarrier,
+    /// Grid-wide barrier of a cooperative launch (grid.sync())
+    GridBarrier,
+    /// Warp exit — appended once to every warp's instruction stream
+    Exit,
+}
+
+impl InstrKind {
+    /// Cycles until the result of this instruction is available.
+    /// Stores are fire-and-forget for the issuing warp but still have to drain
+    /// before the block can retire.
+    pub fn latency(self, cfg: &SmConfig) -> u64 {
+        match self {
+            // A launch only hands the grid to the device runtime; the child
+            // starts asynchronously
+            InstrKind::Alu | InstrKind::Vote | InstrKind::Launch => cfg.alu_latency as u64,
+            // Shuffles move data through the same crossbar as shared memory
+            InstrKind::SharedLoad
+            | InstrKind::SharedStore
+            | InstrKind::SharedAtomic
+            | InstrKind::Shuffle => cfg.smem_latency as u64,
+            InstrKind::GlobalLoad | InstrKind::GlobalStore | InstrKind::GlobalAtomic => {
+                cfg.gmem_latency as u64
+            }
+            InstrKind::ConstantLoad => cfg.const_latency as u64,
+            InstrKind::TextureFetch => cfg.tex_latency as u64,
+            // Distributed shared memory and cluster barriers cross the SM-to-SM network
+            InstrKind::DsmemLoad
+            | InstrKind::DsmemStore
+            | InstrKind::DsmemAtomic
+            | InstrKind::ClusterBarrier => cfg.dsmem_latency as u64,
+            InstrKind::Barrier => cfg.barrier_latency as u64,
+            // Grid barriers are implemented in global memory: an arrival
+            // atomic, then polling the barrier counter
+            InstrKind::GridBarrier => 2 * cfg.gmem_latency as u64,
+            InstrKind::Exit => 1,
+        }
+    }
+
+    /// Cycles until the issuing warp may issue its next instruction, given
+    /// the instruction's latency.
+    fn issue_delay(self, latency: u64) -> u64 {
+        match self {
+            InstrKind::SharedStore | InstrKind::GlobalStore | InstrKind::DsmemStore => 1,
+            _ => latency,
+        }
+    }
+
+    /// Stall reason reported while a warp waits on this instruction.
+    fn stall_state(self) -> WarpState {
+        match self {
+            InstrKind::GlobalLoad
+            | InstrKind::GlobalAtomic
+            | InstrKind::TextureFetch
+            | InstrKind::DsmemLoad
+            | InstrKind::DsmemAtomic => WarpState::LongScoreboard,
+            InstrKind::SharedLoad
+            | InstrKind::SharedAtomic
+            | InstrKind::Shuffle
+            | InstrKind::ConstantLoad => WarpState::ShortScoreboard,
+            InstrKind::Barrier | InstrKind::ClusterBarrier | InstrKind::GridBarrier => {
+                WarpState::Barrier
+            }
+            _ => WarpState::ExecDep,
+        }
+    }
+
+    /// Whether the instruction accesses global memory (through the L2).
+    pub fn is_global(self) -> bool {
+        matches!(self, InstrKind::GlobalLoad | InstrKind::GlobalStore | InstrKind::GlobalAtomic)
+    }
+
+    /// Whether the instruction is a block, cluster or grid barrier.
+    pub fn is_barrier(self) -> bool {
+        matches!(self, InstrKind::Barrier | InstrKind::ClusterBarrier | InstrKind::GridBarrier)
+    }
+}
+
+/// Source location of the `ThreadCtx` call that issued an instruction —
+/// the simulator's stand-in for a program counter.
+pub type Site = &'static Location<'static>;
+
+/// One instruction as issued by a single thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LaneOp {
+    pub kind: InstrKind,
+    /// Call site in the kernel source
+    pub site: Site,
+    /// Byte address for memory instructions (0 otherwise)
+    pub addr: usize,
+    /// Access size in bytes for memory instructions (0 otherwise)
+    pub size: u32,
+}
+
+impl LaneOp {
+    /// Ops take their site from the caller, so every `ThreadCtx` method that
+    /// records one is `#[track_caller]` and reports the kernel's source line.
+    #[track_caller]
+    pub fn new(kind: InstrKind) -> Self {
+        LaneOp { kind, site: Location::caller(), addr: 0, size: 0 }
+    }
+
+    #[track_caller]
+    pub fn mem(kind: InstrKind, addr: usize, size: usize) -> Self {
+        LaneOp { kind, site: Location::caller(), addr, size: size as u32 }
+    }
+}
+
+/// One warp-level instruction: the lanes that issued it and their operands.
+#[derive(Debug, Clone)]
+pub struct WarpInstr {
+    pub kind: InstrKind,
+    /// Per-lane operands, as (lane index, op), for every lane that issued it
+    pub lanes: Vec<(u32, LaneOp)>,
+    /// Times the instruction is issued back to back (1 = no serialization)
+    pub replays: u32,
+}
+
+impl WarpInstr {
+    pub fn new(kind: InstrKind) -> Self {
+        WarpInstr { kind, lanes: Vec::new(), replays: 1 }
+    }
+
+    /// The lanes' ops, without lane indices
+    pub fn ops(&self) -> Vec<LaneOp> {
+        self.lanes.iter().map(|&(_, op)| op).collect()
+    }
+
+    /// Bitmask of the lanes that issued this instruction
+    pub fn active_mask(&self) -> u32 {
+        self.lanes.iter().fold(0, |mask, &(lane, _)| mask | (1 << lane))
+    }
+}
+
+/// Per-warp replay state.
+struct WarpTimer {
+    /// Id of the block the warp belongs to
+    block: u32,
+    stream: Vec<WarpInstr>,
+    pc: usize,
+    /// First cycle at which the warp may issue again
+    ready_at: u64,
+    /// Last issued instruction — determines the stall reason while not ready
+    waiting_on: InstrKind,
+    at_barrier: bool,
+    done: bool,
+    age: u64,
+}
+
+/// A thread block resident on an SM.
+struct ResidentBlock {
+    id: u32,
+    /// Cycle by which every instruction issued so far has completed
+    end: u64,
+    /// Warp instructions issued by the block so far
+    issued: u64,
+    /// Cluster or grid barrier every live warp has arrived at; released by
+    /// the executor once the rest of the cluster (grid) has arrived too
+    group_barrier: Option<InstrKind>,
+    /// L1, L2 and DRAM accesses made by the block's global memory
+    /// instructions, and the managed memory page faults they took
+    l1: CacheStats,
+    l2: CacheStats,
+    dram: DramStats,
+    managed: ManagedStats,
+    /// Constant cache lookups of the block's constant loads
+    constant: CacheStats,
+    /// Texture cache lookups of the block's texture fetches
+    texture: CacheStats,
+}
+
+/// A block that has left the SM.
+#[derive(Debug, Clone, Copy)]
+pub struct RetiredBlock {
+    /// Id passed to `SmTimer::add_block`
+    pub id: u32,
+    /// Warp instructions the block issued
+    pub instructions_issued: u64,
+    /// L1 accesses made by the block's global memory instructions
+    pub l1: CacheStats,
+    /// L2 accesses made by the block's global memory instructions and
+    /// texture cache misses
+    pub l2: CacheStats,
+    /// DRAM requests caused by the block's L2 misses and write-backs
+    pub dram: DramStats,
+    /// Managed memory page faults and migrations of the block's accesses
+    pub managed: ManagedStats,
+    /// Constant cache lookups of the block's constant loads
+    pub constant: CacheStats,
+    /// Texture cache lookups of the block's texture fetches
+    pub texture: CacheStats,
+}
+
+/// Cycle-level model of one SM's issue pipeline.
+///
+/// Any number of blocks can be resident at once (the block scheduler enforces
+/// the occupancy limit); their warps sit in hardware warp slots and compete
+/// for the same `issue_width` issue slots every cycle. A block retires once
+/// all its warps have exited and its outstanding accesses have drained.
+#[derive(Default)]
+pub struct SmTimer {
+    /// Hardware warp slots — the index is the warp id seen by the scheduler
+    slots: Vec<Option<WarpTimer>>,
+    blocks: Vec<ResidentBlock>,
+    /// Cycle from which each SM sub-partition's constant cache port takes
+    /// its next pass — a warp uses the port of its scheduler
+    const_ports: Vec<u64>,
+    /// Warp instructions issued so far
+    pub instructions_issued: u64,
+}
+
+impl SmTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True when no block is resident.
+    pub fn is_idle(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Make a block resident at cycle `now`. `warps[i]` is the instruction
+    /// stream of warp i (see simt.rs); `first_age` is the launch age of warp 0,
+    /// used by age-based policies.
+    pub fn add_block(&mut self, id: u32, warps: Vec<Vec<WarpInstr>>, first_age: u64, now: u64) {
+        self.blocks.push(ResidentBlock {
+            id,
+            end: now,
+            issued: 0,
+            group_barrier: None,
+            l1: CacheStats::default(),
+            l2: CacheStats::default(),
+            dram: DramStats::default(),
+            managed: ManagedStats::default(),
+            constant: CacheStats::default(),
+            texture: CacheStats::default(),
+        });
+        for (i, stream) in warps.into_iter().enumerate() {
+            let warp = WarpTimer {
+                block: id,
+                pc: 0,
+                ready_at: now,
+                waiting_on: InstrKind::Alu,
+                at_barrier: false,
+                done: stream.is_empty(),
+                age: first_age + i as u64,
+                stream,
+            };
+            match self.slots.iter().position(|s| s.is_none()) {
+                Some(free) => self.slots[free] = Some(warp),
+                None => self.slots.push(Some(warp)),
+            }
+        }
+    }
+
+    /// Simulate cycle `now`: release barriers whose block has fully arrived,
+    /// then issue up to `issue_width` eligible warps in scheduler order,
+    /// looking up global memory instructions in the SM's L1 and texture
+    /// fetches in its texture cache, both backed by the shared L2 whose
+    /// misses go to DRAM, after faulting in managed pages, and constant loads
+    /// in its constant cache. Returns the number of warp instructions issued.
+    ///
+    /// A block whose warps have all arrived at a cluster or grid barrier is
+    /// only flagged (see `group_barrier_waiting`): the other blocks of the
+    /// cluster (grid) may sit on other SMs, so the executor releases it.
+    pub fn step(
+        &mut self,
+        now: u64,
+        scheduler: &mut dyn WarpScheduler,
+        cfg: &SmConfig,
+        caches: &mut SmCaches<'_>,
+        global: &mut GlobalMemory<'_>,
+    ) -> usize {
+        // Release a block's barrier once every live warp of the block has arrived
+        for block in &mut self.blocks {
+            let mut live = self
+                .slots
+                .iter_mut()
+                .flatten()
+                .filter(|w| w.block == block.id && !w.done)
+                .peekable();
+            if live.peek().is_none() {
+                continue;
+            }
+            let warps: Vec<&mut WarpTimer> = live.collect();
+            if warps.iter().all(|w| w.at_barrier) {
+                let group = warps.iter().map(|w| w.waiting_on).find(|&k| k != InstrKind::Barrier);
+                if group.is_some() {
+                    block.group_barrier = group;
+                    continue;
+                }
+                for w in warps {
+                    w.at_barrier = false;
+                    w.ready_at = now + cfg.barrier_latency as u64;
+                }
+            }
+        }
+
+        let slots: Vec<WarpSlot> = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| Some((i, w.as_ref()?)))
+            .filter(|(_, w)| !w.done)
+            .map(|(i, w)| {
+                let mut slot = WarpSlot::new(i, w.age);
+                if w.at_barrier {
+                    slot.state = WarpState::Barrier;
+                } else if w.ready_at > now {
+                    slot.state = w.waiting_on.stall_state();
+                }
+                slot
+            })
+            .collect();
+
+        let issue_width = cfg.issue_width.max(1) as usize;
+        let mut issued = 0;
+        for warp_idx in scheduler.order_warps(&slots) {
+            if issued == issue_width {
+                break;
+            }
+            let eligible = slots
+                .iter()
+                .any(|s| s.warp_idx == warp_idx && s.state.is_eligible());
+            if !eligible {
+                continue;
+            }
+
+            let Some(w) = self.slots[warp_idx].as_mut() else { continue };
+            let instr = &w.stream[w.pc];
+            w.pc += 1;
+            w.waiting_on = instr.kind;
+            match instr.kind {
+                kind if kind.is_barrier() => w.at_barrier = true,
+                InstrKind::Exit => w.done = true,
+                _ => {}
+            }
+            let access = if instr.kind.is_global() || instr.kind == InstrKind::TextureFetch {
+                access_memory(instr, now, cfg, caches, global)
+            } else if instr.kind == InstrKind::ConstantLoad {
+                let port = warp_idx % cfg.issue_width.max(1) as usize;
+                if self.const_ports.len() <= port {
+                    self.const_ports.resize(port + 1, 0);
+                }
+                let port = &mut self.const_ports[port];
+                access_constant(instr, now, cfg, caches.constant, port)
+            } else {
+                MemAccess { latency: instr.kind.latency(cfg), ..Default::default() }
+            };
+            let latency = access.latency;
+            // Serialized replays occupy the warp for one extra cycle each
+            let replay_cycles = instr.replays.saturating_sub(1) as u64;
+            w.ready_at = now + replay_cycles + instr.kind.issue_delay(latency);
+            let completes = now + replay_cycles + latency;
+            if let Some(block) = self.blocks.iter_mut().find(|b| b.id == w.block) {
+                block.end = block.end.max(completes);
+                block.issued += 1;
+                block.l1 += access.l1;
+                block.l2 += access.l2;
+                block.dram += access.dram;
+                block.managed += access.managed;
+                block.constant += access.constant;
+                block.texture += access.texture;
+            }
+
+            scheduler.record_issued(warp_idx);
+            issued += 1;
+        }
+        self.instructions_issued += issued as u64;
+        issued
+    }
+
+    /// Blocks whose live warps have all arrived at a cluster or grid
+    /// barrier, with the kind of barrier.
+    pub fn group_barrier_waiting(&self) -> impl Iterator<Item = (u32, InstrKind)> + '_ {
+        self.blocks.iter().filter_map(|b| Some((b.id, b.group_barrier?)))
+    }
+
+    /// Release block `id` from its cluster or grid barrier at cycle `now`.
+    pub fn release_group_barrier(&mut self, id: u32, now: u64, cfg: &SmConfig) {
+        let Some(block) = self.blocks.iter_mut().find(|b| b.id == id) else { return };
+        let Some(kind) = block.group_barrier.take() else { return };
+        for w in self.slots.iter_mut().flatten().filter(|w| w.block == id && !w.done) {
+            w.at_barrier = false;
+            w.ready_at = now + kind.latency(cfg);
+        }
+    }
+
+    /// Remove the blocks that have retired by cycle `now` (all warps exited,
+    /// all accesses drained) and free their warp slots.
+    pub fn retire(&mut self, now: u64) -> Vec<RetiredBlock> {
+        let finished: Vec<RetiredBlock> = self
+            .blocks
+            .iter()
+            .filter(|b| b.end <= now && self.block_exited(b.id))
+            .map(|b| RetiredBlock {
+                id: b.id,
+                instructions_issued: b.issued,
+                l1: b.l1,
+                l2: b.l2,
+                dram: b.dram,
+                managed: b.managed,
+                constant: b.constant,
+                texture: b.texture,
+            })
+            .collect();
+        for retired in &finished {
+            self.blocks.retain(|b| b.id != retired.id);
+            for slot in self.slots.iter_mut() {
+                if slot.as_ref().is_some_and(|w| w.block == retired.id) {
+                    *slot = None;
+                }
+            }
+        }
+        finished
+    }
+
+    /// The next cycle after `now` at which a stalled warp becomes ready or a
+    /// block retires — used to skip ahead when nothing can issue.
+    pub fn next_event(&self, now: u64) -> Option<u64> {
+        let warps = self
+            .slots
+            .iter()
+            .flatten()
+            .filter(|w| !w.done && !w.at_barrier)
+            .map(|w| w.ready_at);
+        let retires = self
+            .blocks
+            .iter()
+            .filter(|b| self.block_exited(b.id))
+            .map(|b| b.end);
+        warps.chain(retires).filter(|&t| t > now).min()
+    }
+
+    fn block_exited(&self, id: u32) -> bool {
+        self.slots.iter().flatten().filter(|w| w.block == id).all(|w| w.done)
+    }
+}
+
+/// An SM's own caches.
+pub struct SmCaches<'a> {
+    pub l1: &'a mut SectoredCache,
+    pub constant: &'a mut SectoredCache,
+    pub texture: &'a mut SectoredCache,
+}
+
+/// The GPU-wide memory behind an SM's caches, shared by every SM.
+pub struct GlobalMemory<'a> {
+    pub l2: &'a mut L2Cache,
+    pub dram: &'a mut Dram,
+    /// Page residency of managed allocations
+    pub managed: &'a mut ManagedMemory,
+}
+
+/// Latency of a memory instruction and the accesses it made.
+#[derive(Debug, Default)]
+struct MemAccess {
+    latency: u64,
+    l1: CacheStats,
+    l2: CacheStats,
+    dram: DramStats,
+    managed: ManagedStats,
+    constant: CacheStats,
+    texture: CacheStats,
+}
+
+/// Look up a constant load issued at cycle `now` in the SM's constant
+/// cache. Its passes (`replays`) go through the constant cache `port` one
+/// per cycle, after those of earlier loads; it then completes after
+/// `const_latency` if every line it reads is cached, otherwise once the L2
+/// has filled the missing lines.
+fn access_constant(
+    instr: &WarpInstr,
+    now: u64,
+    cfg: &SmConfig,
+    cache: &mut SectoredCache,
+    port: &mut u64,
+) -> MemAccess {
+    let start = now.max(*port);
+    *port = start + instr.replays as u64;
+    let ranges = instr.lanes.iter().map(|(_, op)| (op.addr, op.size as usize));
+    let lookup = cache.access(ranges, false);
+    let latency = if lookup.missed.is_empty() { cfg.const_latency } else { cfg.l2_latency };
+    let latency = start - now + latency as u64;
+    MemAccess { latency, constant: lookup.stats, ..Default::default() }
+}
+
+/// Look up a global memory instruction or texture fetch issued at cycle
+/// `now` in the caches and schedule its L2 misses and write-backs on the
+/// DRAM. Texture fetches go through the texture cache instead of the L1 and
+/// hit after `tex_latency`. Pages of managed memory that are not resident on
+/// the device are faulted in first.
+fn access_memory(
+    instr: &WarpInstr,
+    now: u64,
+    cfg: &SmConfig,
+    caches: &mut SmCaches<'_>,
+    global: &mut GlobalMemory<'_>,
+) -> MemAccess {
+    let GlobalMemory { l2, dram, managed } = global;
+    // Texture rows outside a border-mode texture read nothing
+    let ranges: Vec<(usize, usize)> = instr
+        .lanes
+        .iter()
+        .map(|(_, op)| (op.addr, op.size as usize))
+        .filter(|&(_, size)| size > 0)
+        .collect();
+    // Atomics are performed at the L2, so they bypass the L1 and count as writes
+    let write = matches!(instr.kind, InstrKind::GlobalStore | InstrKind::GlobalAtomic);
+    let (first, hit_latency) = match instr.kind {
+        InstrKind::TextureFetch => (&mut *caches.texture, cfg.tex_latency),
+        _ => (&mut *caches.l1, cfg.l1_latency),
+    };
+    let mut access = MemAccess::default();
+    // Faulted pages hold the instruction until they are usable; the caches
+    // and DRAM are still looked up at issue
+    let mut stall = 0;
+    if !managed.is_empty()
+        && let Some(ready) =
+            managed.device_access(&ranges, write, now, cfg.clock_mhz, &mut access.managed)
+    {
+        stall = ready - now;
+    }
+    let to_l2 = match instr.kind {
+        InstrKind::GlobalAtomic => ranges,
+        _ => {
+            let first_access = first.access(ranges.iter().copied(), write);
+            match instr.kind {
+                InstrKind::TextureFetch => access.texture = first_access.stats,
+                _ => access.l1 = first_access.stats,
+            }
+            // The L1 is write-through: every store reaches the L2
+            if write { ranges } else { first_access.missed }
+        }
+    };
+    if to_l2.is_empty() {
+        access.latency = stall + hit_latency as u64;
+        return access;
+    }
+    let l2_access = l2.access(to_l2, write);
+    access.l2 = l2_access.stats;
+    access.latency = stall + cfg.l2_latency as u64;
+    // Loads and atomics fill the sectors they missed; a store needs no fill,
+    // its sectors reach DRAM once, written through or written back later
+    let fills = match instr.kind {
+        InstrKind::GlobalStore => &[][..],
+        _ => &l2_access.missed[..],
+    };
+    if !fills.is_empty() || !l2_access.written_through.is_empty() {
+        // The instruction waits for its last sector; its own requests are
+        // scheduled before the write-backs they caused
+        let fills = fills.iter().map(|&(addr, _)| (addr, false));
+        let through = l2_access.written_through.iter().map(|&(addr, _)| (addr, true));
+        let done = fills
+            .chain(through)
+            .map(|(addr, write)| dram.access(addr, write, now, &mut access.dram))
+            .max()
+            .unwrap_or(now);
+        let unloaded = dram.config().unloaded_latency();
+        access.latency = stall + (cfg.gmem_latency as u64 + (done - now)).saturating_sub(unloaded);
+    }
+    for &(addr, _) in &l2_access.written_back {
+        dram.access(addr, true, now, &mut access.dram);
+    }
+    access
+}
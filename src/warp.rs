@@ -1,6 +1,6 @@
-/// Warp and WarpScheduler simulation.
-/// A warp is a group of 32 threads executing in SIMD lockstep.
-/// The WarpScheduler manages warp execution within an SM subpartition.
+//! Warp and WarpScheduler simulation.
+//! A warp is a group of 32 threads executing in SIMD lockstep.
+//! The WarpScheduler manages warp execution within an SM subpartition.
 
 pub const WARP_SIZE: usize = 32; // Standard CUDA warp size
 
@@ -58,3 +58,9 @@ impl WarpScheduler {
         }
     }
 }
+
+impl Default for WarpScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}